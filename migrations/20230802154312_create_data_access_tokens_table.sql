@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE data_access_tokens (
+    data_access_token TEXT NOT NULL,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (data_access_token)
+);
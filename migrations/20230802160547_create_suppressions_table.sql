@@ -0,0 +1,7 @@
+-- Add migration script here
+CREATE TABLE suppressions (
+    email_hash TEXT NOT NULL,
+    reason TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (email_hash)
+);
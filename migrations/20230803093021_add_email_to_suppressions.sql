@@ -0,0 +1,3 @@
+-- Add migration script here
+-- Addresses suppressed because of an erasure request are only known by their hash.
+ALTER TABLE suppressions ADD COLUMN email TEXT NULL;
@@ -0,0 +1,12 @@
+-- Add migration script here
+CREATE TABLE email_events (
+    email_event_id uuid NOT NULL,
+    newsletter_issue_id uuid NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    event_type TEXT NOT NULL,
+    occurred_at timestamptz NOT NULL,
+    received_at timestamptz NOT NULL,
+    PRIMARY KEY (email_event_id)
+);
+
+CREATE INDEX email_events_newsletter_issue_id_idx ON email_events (newsletter_issue_id);
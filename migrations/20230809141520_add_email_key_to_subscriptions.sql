@@ -0,0 +1,9 @@
+-- Add migration script here
+-- Subscribers are told apart by their lowercased address rather than by
+-- the address as typed. If two existing rows only differ by case this
+-- migration fails - they have to be merged by hand first.
+ALTER TABLE subscriptions ADD COLUMN email_key TEXT NULL;
+UPDATE subscriptions SET email_key = lower(trim(email));
+ALTER TABLE subscriptions ALTER COLUMN email_key SET NOT NULL;
+ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_email_key;
+CREATE UNIQUE INDEX subscriptions_email_key_idx ON subscriptions (email_key);
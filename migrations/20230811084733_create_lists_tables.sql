@@ -0,0 +1,27 @@
+-- Add migration script here
+CREATE TABLE lists(
+    list_id uuid NOT NULL,
+    PRIMARY KEY (list_id),
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
+
+CREATE TABLE list_subscriptions(
+    list_id uuid NOT NULL REFERENCES lists (list_id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    subscribed_at timestamptz NOT NULL,
+    PRIMARY KEY (list_id, subscriber_id)
+);
+
+-- Until now there was a single, implicit list that everybody was subscribed to.
+INSERT INTO lists (list_id, slug, name, created_at)
+VALUES ('5d4c3e6a-2f7b-4c1e-9a8d-0b6f1e2d3c4a', 'newsletter', 'Newsletter', now());
+
+INSERT INTO list_subscriptions (list_id, subscriber_id, subscribed_at)
+SELECT '5d4c3e6a-2f7b-4c1e-9a8d-0b6f1e2d3c4a', id, subscribe_at
+FROM subscriptions;
+
+ALTER TABLE newsletter_issues ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+UPDATE newsletter_issues SET list_id = '5d4c3e6a-2f7b-4c1e-9a8d-0b6f1e2d3c4a';
+ALTER TABLE newsletter_issues ALTER COLUMN list_id SET NOT NULL;
@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE subscriber_tags(
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    tag TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    PRIMARY KEY (subscriber_id, tag)
+);
+CREATE INDEX subscriber_tags_tag_idx ON subscriber_tags (tag);
+
+ALTER TABLE newsletter_issues ADD COLUMN segment TEXT NULL;
@@ -0,0 +1,2 @@
+-- Add migration script here
+ALTER TABLE subscriptions ADD COLUMN text_only BOOLEAN NOT NULL DEFAULT FALSE;
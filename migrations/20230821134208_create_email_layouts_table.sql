@@ -0,0 +1,41 @@
+-- Add migration script here
+CREATE TABLE email_layouts(
+    layout_id uuid NOT NULL,
+    PRIMARY KEY (layout_id),
+    name TEXT NOT NULL UNIQUE,
+    html TEXT NOT NULL,
+    is_default BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at timestamptz NOT NULL
+);
+
+-- There is at most one default layout
+CREATE UNIQUE INDEX email_layouts_default_idx ON email_layouts (is_default) WHERE is_default;
+
+INSERT INTO email_layouts (layout_id, name, html, is_default, created_at)
+VALUES (
+    '8c1f4b2e-6d3a-4e5f-9b7c-2a1d0e3f4c5b',
+    'Default',
+    '<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <style>
+        body { font-family: Helvetica, Arial, sans-serif; color: #222222; }
+        .header { font-size: 20px; font-weight: bold; padding-bottom: 16px; }
+        .footer { font-size: 12px; color: #777777; padding-top: 16px; border-top: 1px solid #dddddd; }
+    </style>
+</head>
+<body>
+    <div class="header">zero2prod</div>
+    <div class="content">{{ content }}</div>
+    <div class="footer">
+        <p>You receive this email because you subscribed to zero2prod with {{ email }}.</p>
+        <p>zero2prod, 1 Newsletter Street, 00000 Internet</p>
+    </div>
+</body>
+</html>',
+    TRUE,
+    now()
+);
+
+ALTER TABLE newsletter_issues ADD COLUMN layout_id uuid NULL REFERENCES email_layouts (layout_id);
@@ -0,0 +1,16 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues
+    ALTER COLUMN published_at TYPE timestamptz USING published_at::timestamptz;
+
+ALTER TABLE newsletter_issues ADD COLUMN in_archive BOOLEAN NOT NULL DEFAULT TRUE;
+
+-- Same as the slugs generated when publishing: the title, followed by the beginning of the id
+ALTER TABLE newsletter_issues ADD COLUMN slug TEXT NULL;
+UPDATE newsletter_issues
+SET slug = concat_ws(
+    '-',
+    NULLIF(trim(BOTH '-' FROM regexp_replace(lower(title), '[^a-z0-9]+', '-', 'g')), ''),
+    left(newsletter_issue_id::text, 8)
+);
+ALTER TABLE newsletter_issues ALTER COLUMN slug SET NOT NULL;
+ALTER TABLE newsletter_issues ADD CONSTRAINT newsletter_issues_slug_key UNIQUE (slug);
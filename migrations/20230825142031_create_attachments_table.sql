@@ -0,0 +1,14 @@
+-- Add migration script here
+CREATE TABLE attachments(
+    attachment_id uuid NOT NULL,
+    PRIMARY KEY (attachment_id),
+    -- NULL until the issue the file has been uploaded for is published
+    newsletter_issue_id uuid NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    filename TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    content BYTEA NOT NULL,
+    content_id TEXT NULL,
+    uploaded_at timestamptz NOT NULL
+);
+
+CREATE INDEX attachments_newsletter_issue_id_idx ON attachments (newsletter_issue_id);
@@ -0,0 +1,22 @@
+-- Add migration script here
+CREATE TABLE subject_variants(
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    variant INT NOT NULL,
+    subject TEXT NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, variant)
+);
+
+-- Set for issues whose subject is being A/B tested
+ALTER TABLE newsletter_issues ADD COLUMN ab_test_metric TEXT NULL;
+ALTER TABLE newsletter_issues ADD COLUMN ab_test_ends_at timestamptz NULL;
+ALTER TABLE newsletter_issues ADD COLUMN winning_variant INT NULL;
+
+-- Recipients in the test slice of an issue get the variant they are assigned,
+-- the rest of the queue gets the winning one.
+ALTER TABLE issue_delivery_queue ADD COLUMN subject_variant INT NULL;
+-- Tasks are kept once processed, to report on A/B tests
+ALTER TABLE issue_delivery_queue ADD COLUMN delivered_at timestamptz NULL;
+
+CREATE INDEX issue_delivery_queue_pending_idx
+    ON issue_delivery_queue (newsletter_issue_id)
+    WHERE delivered_at IS NULL;
@@ -0,0 +1,8 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues ADD COLUMN track_opens BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE newsletter_issues ADD COLUMN track_clicks BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Opens and clicks we track ourselves are tied to the subscriber,
+-- clicks also record the link that was followed.
+ALTER TABLE email_events ADD COLUMN subscriber_id uuid NULL REFERENCES subscriptions (id);
+ALTER TABLE email_events ADD COLUMN url TEXT NULL;
@@ -0,0 +1,11 @@
+-- Add migration script here
+-- Failed sends are retried with an exponential backoff...
+ALTER TABLE issue_delivery_queue ADD COLUMN n_retries INT NOT NULL DEFAULT 0;
+ALTER TABLE issue_delivery_queue ADD COLUMN execute_after timestamptz NOT NULL DEFAULT now();
+-- ...until we give up on the recipient.
+ALTER TABLE issue_delivery_queue ADD COLUMN failed_at timestamptz NULL;
+
+DROP INDEX issue_delivery_queue_pending_idx;
+CREATE INDEX issue_delivery_queue_pending_idx
+    ON issue_delivery_queue (newsletter_issue_id, execute_after)
+    WHERE delivered_at IS NULL AND failed_at IS NULL;
@@ -0,0 +1,15 @@
+-- Add migration script here
+-- Deliveries can be paused, resumed and cancelled from the admin panel.
+ALTER TABLE newsletter_issues
+    ADD COLUMN delivery_state TEXT NOT NULL DEFAULT 'active'
+    CHECK (delivery_state IN ('active', 'paused', 'cancelled'));
+
+-- What was left in the queue when a delivery was cancelled.
+CREATE TABLE cancelled_deliveries(
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    subject_variant INT NULL,
+    n_retries INT NOT NULL,
+    cancelled_at timestamptz NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, subscriber_email)
+);
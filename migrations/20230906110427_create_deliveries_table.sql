@@ -0,0 +1,19 @@
+-- Add migration script here
+-- A log of every attempt to send an issue, written outside of the delivery
+-- queue transaction: a send that went out is not repeated when the task is
+-- retried after a crash.
+CREATE TABLE deliveries(
+    attempt_id uuid NOT NULL,
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    PRIMARY KEY (attempt_id, subscriber_email),
+    -- Sent to the provider with the email, the same for every attempt.
+    idempotency_key TEXT NOT NULL,
+    attempted_at timestamptz NOT NULL,
+    -- Neither is set while the attempt is in flight - or if we crashed.
+    sent_at timestamptz NULL,
+    error TEXT NULL,
+    provider_message_id TEXT NULL
+);
+
+CREATE INDEX deliveries_recipient_idx ON deliveries (newsletter_issue_id, subscriber_email);
@@ -0,0 +1,4 @@
+-- Add migration script here
+-- Why the workers gave up on a recipient, when an admin needs to know before
+-- requeuing: `in_doubt` if an earlier attempt may have gone out.
+ALTER TABLE issue_delivery_queue ADD COLUMN failure_reason TEXT NULL;
@@ -0,0 +1,249 @@
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Share of the recipients subject variants are tried on, unless specified.
+pub const DEFAULT_TEST_SLICE_PERCENT: u32 = 20;
+/// How long to wait for opens and clicks before picking the winning variant.
+pub const DEFAULT_TEST_WINDOW_HOURS: u32 = 4;
+
+/// How the winning subject of an A/B test is picked.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TestMetric {
+    /// Share of the recipients who opened the issue.
+    Opens,
+    /// Share of the recipients who clicked a link in the issue.
+    Clicks,
+}
+
+impl TestMetric {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TestMetric::Opens => "opens",
+            TestMetric::Clicks => "clicks",
+        }
+    }
+
+    /// The `email_events` counted towards the metric.
+    fn event_types(&self) -> Vec<String> {
+        let event_types: &[&str] = match self {
+            TestMetric::Opens => &["opened", "unique_opened"],
+            TestMetric::Clicks => &["click"],
+        };
+        event_types.iter().map(|t| t.to_string()).collect()
+    }
+}
+
+impl TryFrom<String> for TestMetric {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "opens" => Ok(Self::Opens),
+            "clicks" => Ok(Self::Clicks),
+            other => Err(format!("{} is not a supported A/B test metric", other)),
+        }
+    }
+}
+
+/// How a subject variant performed with the recipients it has been sent to.
+pub struct VariantResult {
+    pub variant: i32,
+    pub subject: String,
+    pub n_sent: i64,
+    /// Recipients who opened or clicked, depending on the test metric.
+    pub n_engaged: i64,
+}
+
+impl VariantResult {
+    pub fn rate(&self) -> f64 {
+        if self.n_sent == 0 {
+            0.
+        } else {
+            self.n_engaged as f64 / self.n_sent as f64
+        }
+    }
+}
+
+#[tracing::instrument(skip(transaction, subjects))]
+pub async fn store_variants(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    subjects: &[String],
+) -> Result<(), sqlx::Error> {
+    let variants: Vec<i32> = (0..subjects.len() as i32).collect();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO subject_variants (newsletter_issue_id, variant, subject)
+        SELECT $1, v.variant, v.subject
+        FROM UNNEST($2::int[], $3::text[]) AS v(variant, subject)
+        "#,
+        issue_id,
+        &variants,
+        subjects
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+/// Pick `slice_size` recipients of the issue at random and spread the
+/// `n_variants` subject variants evenly among them.
+#[tracing::instrument(skip(transaction))]
+pub async fn assign_test_slice(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    slice_size: i64,
+    n_variants: i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        WITH slice AS (
+            SELECT subscriber_email, row_number() OVER () AS n
+            FROM (
+                SELECT subscriber_email
+                FROM issue_delivery_queue
+                WHERE newsletter_issue_id = $1
+                ORDER BY random()
+                LIMIT $2
+            ) AS s
+        )
+        UPDATE issue_delivery_queue q
+        SET subject_variant = (slice.n % $3)::int
+        FROM slice
+        WHERE
+            q.newsletter_issue_id = $1 AND
+            q.subscriber_email = slice.subscriber_email
+        "#,
+        issue_id,
+        slice_size,
+        i64::from(n_variants)
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_variant_results(
+    pool: &PgPool,
+    issue_id: Uuid,
+    metric: TestMetric,
+) -> Result<Vec<VariantResult>, sqlx::Error> {
+    sqlx::query_as!(
+        VariantResult,
+        r#"
+        SELECT
+            v.variant,
+            v.subject,
+            COUNT(DISTINCT q.subscriber_email) AS "n_sent!",
+            COUNT(DISTINCT e.subscriber_email) AS "n_engaged!"
+        FROM subject_variants v
+        LEFT JOIN issue_delivery_queue q ON
+            q.newsletter_issue_id = v.newsletter_issue_id AND
+            q.subject_variant = v.variant AND
+            q.delivered_at IS NOT NULL
+        LEFT JOIN email_events e ON
+            e.newsletter_issue_id = v.newsletter_issue_id AND
+            lower(e.subscriber_email) = lower(q.subscriber_email) AND
+            e.event_type = ANY($2)
+        WHERE v.newsletter_issue_id = $1
+        GROUP BY v.variant, v.subject
+        ORDER BY v.variant
+        "#,
+        issue_id,
+        &metric.event_types()
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// The subject of `variant`, `None` if the issue has no such variant.
+#[tracing::instrument(skip(pool))]
+pub async fn get_variant_subject(
+    pool: &PgPool,
+    issue_id: Uuid,
+    variant: i32,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT subject
+        FROM subject_variants
+        WHERE newsletter_issue_id = $1 AND variant = $2
+        "#,
+        issue_id,
+        variant
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.map(|r| r.subject))
+}
+
+/// Close the A/B test of an issue, returning the winning variant.
+///
+/// The best rate wins, ties go to the first variant. Several workers may
+/// get there at the same time: the first decision sticks.
+#[tracing::instrument(skip(pool))]
+pub async fn pick_winner(
+    pool: &PgPool,
+    issue_id: Uuid,
+    metric: TestMetric,
+) -> Result<i32, sqlx::Error> {
+    let results = get_variant_results(pool, issue_id, metric).await?;
+    let mut winner = 0;
+    let mut best_rate = -1.;
+    for r in &results {
+        if r.rate() > best_rate {
+            winner = r.variant;
+            best_rate = r.rate();
+        }
+    }
+
+    let row = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET winning_variant = COALESCE(winning_variant, $2)
+        WHERE newsletter_issue_id = $1
+        RETURNING winning_variant AS "winning_variant!"
+        "#,
+        issue_id,
+        winner
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(row.winning_variant)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TestMetric, VariantResult};
+    use claim::assert_err;
+
+    fn result(n_sent: i64, n_engaged: i64) -> VariantResult {
+        VariantResult {
+            variant: 0,
+            subject: "Subject".into(),
+            n_sent,
+            n_engaged,
+        }
+    }
+
+    #[test]
+    fn rate_is_the_share_of_engaged_recipients() {
+        assert_eq!(result(4, 1).rate(), 0.25);
+    }
+
+    #[test]
+    fn rate_is_zero_without_recipients() {
+        assert_eq!(result(0, 0).rate(), 0.);
+    }
+
+    #[test]
+    fn unknown_metrics_are_rejected() {
+        assert_err!(TestMetric::try_from("replies".to_string()));
+    }
+}
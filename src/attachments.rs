@@ -0,0 +1,198 @@
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::email_client::Attachment;
+
+/// Largest file that can be uploaded.
+pub const MAX_ATTACHMENT_SIZE: usize = 5 * 1024 * 1024;
+/// Largest total size of the attachments of an issue: providers cap the size of emails.
+pub const MAX_ISSUE_ATTACHMENTS_SIZE: i64 = 10 * 1024 * 1024;
+
+/// The types of files that can be attached, along with the bytes their content
+/// must start with - we do not trust the type announced by the browser.
+/// Anything else (executables, scripts, HTML, archives...) is rejected.
+const ALLOWED_TYPES: &[(&str, &[u8])] = &[
+    ("application/pdf", b"%PDF-"),
+    ("image/png", b"\x89PNG\r\n\x1a\n"),
+    ("image/jpeg", b"\xff\xd8\xff"),
+    ("image/gif", b"GIF8"),
+    ("text/plain", b""),
+    ("text/csv", b""),
+];
+
+pub fn validate(
+    filename: &str,
+    content_type: &str,
+    content: &[u8],
+    inline: bool,
+) -> Result<(), String> {
+    let has_valid_filename = !filename.trim().is_empty()
+        && filename.len() <= 255
+        && !filename
+            .chars()
+            .any(|c| c.is_control() || c == '/' || c == '\\');
+    if !has_valid_filename {
+        return Err("The file name is invalid".into());
+    }
+
+    if content.is_empty() {
+        return Err(format!("{} is empty", filename));
+    }
+    if content.len() > MAX_ATTACHMENT_SIZE {
+        return Err(format!(
+            "{} is too large, attachments are limited to {} MB",
+            filename,
+            MAX_ATTACHMENT_SIZE / 1024 / 1024
+        ));
+    }
+
+    let signature = ALLOWED_TYPES
+        .iter()
+        .find(|(t, _)| *t == content_type)
+        .map(|(_, signature)| *signature)
+        .ok_or_else(|| format!("{} files cannot be attached", content_type))?;
+    let is_text = content_type.starts_with("text/");
+    if !content.starts_with(signature) || (is_text && std::str::from_utf8(content).is_err()) {
+        return Err(format!(
+            "The content of {} does not match its type ({})",
+            filename, content_type
+        ));
+    }
+
+    if inline && !content_type.starts_with("image/") {
+        return Err("Only images can be displayed inline".into());
+    }
+
+    Ok(())
+}
+
+/// Store an uploaded file, until it is attached to an issue.
+///
+/// Inline images get a content id: their own id.
+#[tracing::instrument(name = "Store an attachment", skip(pool, content))]
+pub async fn store_attachment(
+    pool: &PgPool,
+    filename: &str,
+    content_type: &str,
+    content: &[u8],
+    inline: bool,
+) -> Result<Uuid, sqlx::Error> {
+    let attachment_id = Uuid::new_v4();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO attachments (
+            attachment_id,
+            filename,
+            content_type,
+            content,
+            content_id,
+            uploaded_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        attachment_id,
+        filename,
+        content_type,
+        content,
+        inline.then(|| attachment_id.to_string())
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(attachment_id)
+}
+
+/// Attach uploaded files to an issue, returning their total size.
+///
+/// Files that do not exist or are already attached to another issue are
+/// skipped: the caller should check how many have been attached.
+#[tracing::instrument(skip(transaction))]
+pub async fn attach_to_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    attachment_ids: &[Uuid],
+) -> Result<(usize, i64), sqlx::Error> {
+    let sizes = sqlx::query!(
+        r#"
+        UPDATE attachments
+        SET newsletter_issue_id = $1
+        WHERE
+            attachment_id = ANY($2) AND
+            newsletter_issue_id IS NULL
+        RETURNING octet_length(content) AS "size!"
+        "#,
+        issue_id,
+        attachment_ids
+    )
+    .fetch_all(transaction)
+    .await?;
+
+    Ok((sizes.len(), sizes.iter().map(|r| i64::from(r.size)).sum()))
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_issue_attachments(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Vec<Attachment>, sqlx::Error> {
+    sqlx::query_as!(
+        Attachment,
+        r#"
+        SELECT filename, content_type, content, content_id
+        FROM attachments
+        WHERE newsletter_issue_id = $1
+        ORDER BY uploaded_at
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate, MAX_ATTACHMENT_SIZE};
+    use claim::{assert_err, assert_ok};
+
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+    #[test]
+    fn allowed_files_are_accepted() {
+        assert_ok!(validate("notes.txt", "text/plain", b"Notes", false));
+        assert_ok!(validate("logo.png", "image/png", PNG, true));
+    }
+
+    #[test]
+    fn types_outside_the_allowlist_are_rejected() {
+        assert_err!(validate("page.html", "text/html", b"<p>Hi</p>", false));
+        assert_err!(validate(
+            "setup.exe",
+            "application/x-msdownload",
+            b"MZ",
+            false
+        ));
+    }
+
+    #[test]
+    fn content_must_match_the_announced_type() {
+        assert_err!(validate("logo.png", "image/png", b"MZ\x90\0", false));
+        assert_err!(validate("notes.txt", "text/plain", b"\xff\xfe\0", false));
+    }
+
+    #[test]
+    fn files_over_the_size_limit_are_rejected() {
+        let content = vec![b'a'; MAX_ATTACHMENT_SIZE + 1];
+        assert_err!(validate("large.txt", "text/plain", &content, false));
+    }
+
+    #[test]
+    fn only_images_can_be_inline() {
+        assert_err!(validate("notes.txt", "text/plain", b"Notes", true));
+    }
+
+    #[test]
+    fn file_names_cannot_contain_paths() {
+        assert_err!(validate("../notes.txt", "text/plain", b"Notes", false));
+    }
+}
@@ -2,4 +2,6 @@ mod middleware;
 mod password;
 pub use middleware::reject_anonymus_users;
 pub use middleware::UserId;
-pub use password::{change_password, validate_credentials, AuthError, Credentials};
+pub use password::{
+    change_password, compute_password_hash, validate_credentials, AuthError, Credentials,
+};
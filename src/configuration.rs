@@ -1,5 +1,6 @@
-use crate::domain::SubscriberEmail;
+use crate::domain::{EmailPolicy, SubscriberEmail};
 use crate::email_client::EmailClient;
+use crate::rate_limiter::RateLimiter;
 use secrecy::{ExposeSecret, Secret};
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
@@ -10,6 +11,8 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    pub email_policy: EmailPolicySettings,
+    pub delivery: DeliverySettings,
     pub redis_uri: Secret<String>,
 }
 
@@ -31,6 +34,9 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    /// How long in-flight requests and deliveries get to complete on shutdown.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub shutdown_timeout_seconds: u64,
 }
 
 impl DatabaseSettings {
@@ -62,14 +68,53 @@ pub struct EmailClientSettings {
     pub sender_email: String,
     pub api_key: Secret<String>,
     pub timeout_milliseconds: u64,
+    pub webhook_secret: Secret<String>,
+    /// Sending limits of our provider plan, see `RateLimiter`.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub messages_per_second: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub daily_quota: u32,
+    pub sending_shares: SendingShares,
+}
+
+/// The share of the sending limits, in percent, of each process sending
+/// emails. Their limiters do not talk to each other: the shares of all the
+/// processes running at once must add up to 100 at most. `all` gets the
+/// whole limits.
+#[derive(serde::Deserialize, Clone)]
+pub struct SendingShares {
+    /// Of each `serve` process, which only sends confirmation emails.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub serve_percent: u32,
+    /// Of each `deliver` process.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub deliver_percent: u32,
 }
 
 impl EmailClientSettings {
+    /// Build a client with its own rate limiter, enforcing the whole limits:
+    /// clone it, rather than building another one, to share them.
     pub fn client(self) -> EmailClient {
+        self.client_with_share(100)
+    }
+
+    /// Build a client with its own rate limiter, enforcing `share_percent`
+    /// of the limits - see `SendingShares`.
+    pub fn client_with_share(self, share_percent: u32) -> EmailClient {
         let sender_email = self.sender().expect("Invalid sender email address");
         let timeout = self.timeout();
-
-        EmailClient::new(self.base_url, sender_email, self.api_key, timeout)
+        let rate_limiter = RateLimiter::new(
+            share_of(self.messages_per_second, share_percent),
+            share_of(self.daily_quota, share_percent),
+        );
+
+        EmailClient::new(
+            self.base_url,
+            sender_email,
+            self.api_key,
+            timeout,
+            rate_limiter,
+        )
     }
 
     pub fn sender(&self) -> Result<SubscriberEmail, String> {
@@ -81,6 +126,42 @@ impl EmailClientSettings {
     }
 }
 
+/// `percent` of `limit`, rounded up: a process always gets to send something.
+fn share_of(limit: u32, percent: u32) -> u32 {
+    let share = (u64::from(limit) * u64::from(percent.min(100))).div_ceil(100);
+    share.max(1) as u32
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct EmailPolicySettings {
+    /// Domains of disposable email providers we refuse to subscribe.
+    pub blocked_domains: Vec<String>,
+    /// Ask subscribers to confirm addresses on domains that look like a typo
+    /// of a popular provider (e.g. `gmial.com`), suggesting the right
+    /// spelling. Admin imports are not checked.
+    pub suggest_typo_fixes: bool,
+}
+
+impl EmailPolicySettings {
+    pub fn policy(self) -> EmailPolicy {
+        EmailPolicy::new(self.blocked_domains, self.suggest_typo_fixes)
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct DeliverySettings {
+    /// How many delivery workers process the queue in parallel.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub concurrency: usize,
+    /// How many recipients of an issue a worker sends to in a single request.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub batch_size: u16,
+    /// How often idle workers look at the queue anyway, should they miss a
+    /// notification or a retry come due.
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub poll_interval_seconds: u64,
+}
+
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
     let mut settings = config::Config::default();
 
@@ -137,3 +218,21 @@ impl TryFrom<String> for Environment {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::share_of;
+
+    #[test]
+    fn shares_of_the_limits_are_rounded_up() {
+        assert_eq!(share_of(100_000, 90), 90_000);
+        assert_eq!(share_of(10, 25), 3);
+        assert_eq!(share_of(10, 100), 10);
+    }
+
+    #[test]
+    fn a_share_is_never_zero() {
+        assert_eq!(share_of(2, 10), 1);
+        assert_eq!(share_of(10, 0), 1);
+    }
+}
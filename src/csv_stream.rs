@@ -0,0 +1,177 @@
+use csv_core::{ReadRecordResult, Reader};
+
+/// An incremental CSV parser that can be fed arbitrary chunks of bytes,
+/// e.g. straight from a multipart upload, without buffering the whole file.
+///
+/// Records are handed back as soon as they are complete - a record split
+/// across two chunks is stitched back together transparently.
+pub struct CsvStream {
+    reader: Reader,
+    output: Vec<u8>,
+    output_len: usize,
+    ends: Vec<usize>,
+    ends_len: usize,
+}
+
+/// A single CSV record, with fields that could not be decoded as UTF-8
+/// replaced by an error.
+pub type CsvRecord = Result<Vec<String>, String>;
+
+impl Default for CsvStream {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CsvStream {
+    pub fn new() -> Self {
+        Self {
+            reader: Reader::new(),
+            output: vec![0; 1024],
+            output_len: 0,
+            ends: vec![0; 16],
+            ends_len: 0,
+        }
+    }
+
+    /// Parse the next chunk of input, pushing every completed record into `records`.
+    pub fn feed(&mut self, input: &[u8], records: &mut Vec<CsvRecord>) {
+        self.read(input, records);
+    }
+
+    /// Signal the end of the input, flushing a trailing record that was
+    /// not terminated by a newline.
+    pub fn finish(&mut self, records: &mut Vec<CsvRecord>) {
+        self.read(&[], records);
+    }
+
+    fn read(&mut self, mut input: &[u8], records: &mut Vec<CsvRecord>) {
+        loop {
+            let (result, n_in, n_out, n_ends) = self.reader.read_record(
+                input,
+                &mut self.output[self.output_len..],
+                &mut self.ends[self.ends_len..],
+            );
+            input = &input[n_in..];
+            self.output_len += n_out;
+            self.ends_len += n_ends;
+
+            match result {
+                ReadRecordResult::InputEmpty | ReadRecordResult::End => return,
+                ReadRecordResult::OutputFull => {
+                    let new_len = self.output.len() * 2;
+                    self.output.resize(new_len, 0);
+                }
+                ReadRecordResult::OutputEndsFull => {
+                    let new_len = self.ends.len() * 2;
+                    self.ends.resize(new_len, 0);
+                }
+                ReadRecordResult::Record => {
+                    records.push(self.take_record());
+                }
+            }
+        }
+    }
+
+    fn take_record(&mut self) -> CsvRecord {
+        let mut fields = Vec::with_capacity(self.ends_len);
+        let mut start = 0;
+
+        for &end in &self.ends[..self.ends_len] {
+            let field = std::str::from_utf8(&self.output[start..end])
+                .map_err(|_| "The record is not valid UTF-8".to_string());
+            start = end;
+
+            match field {
+                Ok(f) => fields.push(f.to_string()),
+                Err(e) => {
+                    self.output_len = 0;
+                    self.ends_len = 0;
+                    return Err(e);
+                }
+            }
+        }
+
+        self.output_len = 0;
+        self.ends_len = 0;
+
+        Ok(fields)
+    }
+}
+
+/// Maps the columns of a CSV header to the position of each field in a record.
+pub struct CsvHeader(Vec<String>);
+
+impl CsvHeader {
+    pub fn parse(record: Vec<String>) -> Self {
+        Self(
+            record
+                .into_iter()
+                .map(|c| c.trim().to_lowercase())
+                .collect(),
+        )
+    }
+
+    pub fn position(&self, column: &str) -> Option<usize> {
+        self.0.iter().position(|c| c == column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CsvStream;
+
+    fn parse_in_chunks(input: &str, chunk_size: usize) -> Vec<Vec<String>> {
+        let mut stream = CsvStream::new();
+        let mut records = Vec::new();
+
+        for chunk in input.as_bytes().chunks(chunk_size) {
+            stream.feed(chunk, &mut records);
+        }
+        stream.finish(&mut records);
+
+        records.into_iter().map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn records_split_across_chunks_are_reassembled() {
+        let input = "email,name\nursula@domain.com,Ursula Le Guin\nle@guin.com,\"Le, Guin\"\n";
+
+        for chunk_size in [1, 2, 7, 1024] {
+            let records = parse_in_chunks(input, chunk_size);
+            assert_eq!(
+                records,
+                vec![
+                    vec!["email", "name"],
+                    vec!["ursula@domain.com", "Ursula Le Guin"],
+                    vec!["le@guin.com", "Le, Guin"],
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn a_trailing_record_without_newline_is_flushed_on_finish() {
+        let records = parse_in_chunks("email,name\nursula@domain.com,Ursula", 3);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1], vec!["ursula@domain.com", "Ursula"]);
+    }
+
+    #[test]
+    fn long_fields_grow_the_output_buffer() {
+        let name = "a".repeat(5000);
+        let records = parse_in_chunks(&format!("name\n{}\n", name), 100);
+        assert_eq!(records[1], vec![name]);
+    }
+
+    #[test]
+    fn invalid_utf8_is_reported_per_record() {
+        let mut stream = CsvStream::new();
+        let mut records = Vec::new();
+        stream.feed(b"a,b\n\xff,c\nd,e\n", &mut records);
+
+        assert!(records[0].is_ok());
+        assert!(records[1].is_err());
+        assert_eq!(records[2].as_ref().unwrap(), &vec!["d", "e"]);
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashSet;
+
+use crate::domain::SubscriberEmail;
+
+/// Domains of popular mailbox providers. An unknown domain one typo away from
+/// any of them is most likely a mistake.
+const WELL_KNOWN_DOMAINS: &[&str] = &[
+    "gmail.com",
+    "googlemail.com",
+    "yahoo.com",
+    "ymail.com",
+    "hotmail.com",
+    "outlook.com",
+    "live.com",
+    "icloud.com",
+    "mail.com",
+    "email.com",
+    "aol.com",
+    "gmx.com",
+    "gmx.de",
+    "protonmail.com",
+    "proton.me",
+    "tutanota.com",
+    "yandex.com",
+];
+
+/// Rules an address must follow to be allowed to subscribe, on top of being
+/// syntactically valid.
+#[derive(Debug, Clone)]
+pub struct EmailPolicy {
+    blocked_domains: HashSet<String>,
+    suggest_typo_fixes: bool,
+}
+
+impl EmailPolicy {
+    pub fn new(blocked_domains: Vec<String>, suggest_typo_fixes: bool) -> Self {
+        Self {
+            blocked_domains: blocked_domains
+                .into_iter()
+                .map(|d| d.trim().to_lowercase())
+                .collect(),
+            suggest_typo_fixes,
+        }
+    }
+
+    pub fn check(&self, email: &SubscriberEmail) -> Result<(), String> {
+        if self.is_blocked(email.domain()) {
+            return Err(format!(
+                "{} belongs to a disposable email provider, please use a permanent address",
+                email
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// The address `email` was most likely meant to be, if its domain looks
+    /// like a typo. Unusual domains do exist: it is up to the subscriber to
+    /// take the suggestion or not.
+    pub fn suggest(&self, email: &SubscriberEmail) -> Option<String> {
+        if !self.suggest_typo_fixes {
+            return None;
+        }
+
+        let domain = email.domain();
+        let local_part = &email.as_ref()[..email.as_ref().len() - domain.len()];
+        suggest_domain(domain).map(|suggestion| format!("{}{}", local_part, suggestion))
+    }
+
+    /// A domain is blocked if it, or any of its parent domains, is on the blocklist.
+    fn is_blocked(&self, domain: &str) -> bool {
+        let mut domain = domain;
+
+        loop {
+            if self.blocked_domains.contains(domain) {
+                return true;
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) => domain = parent,
+                None => return false,
+            }
+        }
+    }
+}
+
+/// Suggest the well-known domain `domain` is a typo of, if any.
+fn suggest_domain(domain: &str) -> Option<&'static str> {
+    if WELL_KNOWN_DOMAINS.contains(&domain) {
+        return None;
+    }
+
+    WELL_KNOWN_DOMAINS
+        .iter()
+        .find(|known| strsim::osa_distance(domain, known) == 1)
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::EmailPolicy;
+    use crate::domain::SubscriberEmail;
+    use claim::{assert_err, assert_none, assert_ok, assert_some_eq};
+
+    fn email(s: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(s.to_string()).unwrap()
+    }
+
+    fn policy() -> EmailPolicy {
+        EmailPolicy::new(vec!["Mailinator.com".into()], true)
+    }
+
+    #[test]
+    fn addresses_on_a_blocked_domain_are_rejected() {
+        assert_err!(policy().check(&email("john@mailinator.com")));
+    }
+
+    #[test]
+    fn addresses_on_a_subdomain_of_a_blocked_domain_are_rejected() {
+        assert_err!(policy().check(&email("john@eu.mailinator.com")));
+    }
+
+    #[test]
+    fn common_typos_get_a_suggestion() {
+        for (typo, fix) in [
+            ("gmial.com", "gmail.com"),
+            ("gmail.con", "gmail.com"),
+            ("hotmal.com", "hotmail.com"),
+            ("yahooo.com", "yahoo.com"),
+        ] {
+            let email = email(&format!("John@{}", typo));
+            assert_ok!(policy().check(&email));
+            assert_some_eq!(policy().suggest(&email), format!("John@{}", fix));
+        }
+    }
+
+    #[test]
+    fn well_known_and_unrelated_domains_are_accepted_without_suggestion() {
+        for domain in [
+            "gmail.com",
+            "mail.com",
+            "email.com",
+            "gmx.com",
+            "domain.com",
+            "example.org",
+        ] {
+            let email = email(&format!("john@{}", domain));
+            assert_ok!(policy().check(&email));
+            assert_none!(policy().suggest(&email));
+        }
+    }
+
+    #[test]
+    fn no_suggestion_is_made_if_suggestions_are_disabled() {
+        let policy = EmailPolicy::new(vec![], false);
+        assert_none!(policy.suggest(&email("john@gmial.com")));
+    }
+}
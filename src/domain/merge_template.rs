@@ -0,0 +1,159 @@
+/// Newsletter content with merge tags, e.g. `Hi {{ name }}!`, filled in for
+/// each recipient when the issue is delivered.
+///
+/// Only `name`, `email` and `unsubscribe_url` are supported - anything else
+/// between `{{` and `}}` is rejected when the issue is published rather
+/// than sent out verbatim.
+#[derive(Debug, PartialEq)]
+pub struct MergeTemplate(Vec<Part>);
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    Text(String),
+    Tag(MergeTag),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum MergeTag {
+    Name,
+    Email,
+    /// Link to the preference centre, where subscribers can unsubscribe.
+    UnsubscribeUrl,
+}
+
+impl MergeTag {
+    fn parse(s: &str) -> Result<MergeTag, String> {
+        match s.trim() {
+            "name" => Ok(MergeTag::Name),
+            "email" => Ok(MergeTag::Email),
+            "unsubscribe_url" => Ok(MergeTag::UnsubscribeUrl),
+            tag => Err(format!(
+                "Unknown merge tag `{{{{ {} }}}}`, expected one of `name`, `email` or `unsubscribe_url`",
+                tag
+            )),
+        }
+    }
+}
+
+/// What the merge tags are replaced with for a given recipient.
+pub struct MergeValues<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    pub unsubscribe_url: &'a str,
+}
+
+impl MergeValues<'_> {
+    fn get(&self, tag: MergeTag) -> &str {
+        match tag {
+            MergeTag::Name => self.name,
+            MergeTag::Email => self.email,
+            MergeTag::UnsubscribeUrl => self.unsubscribe_url,
+        }
+    }
+}
+
+impl MergeTemplate {
+    pub fn parse(s: &str) -> Result<MergeTemplate, String> {
+        let mut parts = Vec::new();
+        let mut rest = s;
+
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_owned()));
+            }
+
+            let after_start = &rest[start + 2..];
+            let end = after_start
+                .find("}}")
+                .ok_or("A merge tag is missing its closing `}}`")?;
+            parts.push(Part::Tag(MergeTag::parse(&after_start[..end])?));
+
+            rest = &after_start[end + 2..];
+        }
+
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_owned()));
+        }
+
+        Ok(Self(parts))
+    }
+
+    /// Render the template for a plain-text email, values are inserted as they are.
+    pub fn render_text(&self, values: &MergeValues) -> String {
+        self.render(values, str::to_owned)
+    }
+
+    /// Render the template for an HTML email.
+    ///
+    /// Values are escaped for attributes, since tags may well be used
+    /// inside one - e.g. `<a href="{{ unsubscribe_url }}">`.
+    pub fn render_html(&self, values: &MergeValues) -> String {
+        self.render(values, htmlescape::encode_attribute)
+    }
+
+    fn render(&self, values: &MergeValues, escape: impl Fn(&str) -> String) -> String {
+        let mut rendered = String::new();
+
+        for part in &self.0 {
+            match part {
+                Part::Text(text) => rendered.push_str(text),
+                Part::Tag(tag) => rendered.push_str(&escape(values.get(*tag))),
+            }
+        }
+
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MergeTemplate, MergeValues};
+    use claim::{assert_err, assert_ok};
+
+    fn values() -> MergeValues<'static> {
+        MergeValues {
+            name: "Ursula <Le Guin>",
+            email: "ursula@domain.com",
+            unsubscribe_url: "https://example.com/preferences?a=1&b=2",
+        }
+    }
+
+    #[test]
+    fn content_without_tags_is_left_untouched() {
+        let template = assert_ok!(MergeTemplate::parse("Hello { world }!"));
+        assert_eq!(template.render_text(&values()), "Hello { world }!");
+    }
+
+    #[test]
+    fn tags_are_replaced_with_the_recipient_values() {
+        let template = assert_ok!(MergeTemplate::parse(
+            "Hi {{name}}, this was sent to {{ email }}."
+        ));
+        assert_eq!(
+            template.render_text(&values()),
+            "Hi Ursula <Le Guin>, this was sent to ursula@domain.com."
+        );
+    }
+
+    #[test]
+    fn values_are_escaped_in_html() {
+        let template = assert_ok!(MergeTemplate::parse(
+            r#"<p>{{ name }}</p><a href="{{ unsubscribe_url }}">"#
+        ));
+
+        let html = template.render_html(&values());
+
+        assert!(!html.contains("<Le Guin>"));
+        assert!(!html.contains("a=1&b=2"));
+    }
+
+    #[test]
+    fn unknown_tags_are_rejected() {
+        assert_err!(MergeTemplate::parse("Hi {{ first_name }}"));
+    }
+
+    #[test]
+    fn unclosed_tags_are_rejected() {
+        assert_err!(MergeTemplate::parse("Hi {{ name"));
+    }
+}
@@ -1,7 +1,15 @@
+mod email_policy;
+mod merge_template;
 mod new_subscriber;
+mod segment;
 mod subscriber_email;
 mod subscriber_name;
+mod subscriber_tag;
 
+pub use email_policy::EmailPolicy;
+pub use merge_template::{MergeTemplate, MergeValues};
 pub use new_subscriber::NewSubscriber;
+pub use segment::Segment;
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
+pub use subscriber_tag::SubscriberTag;
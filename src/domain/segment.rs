@@ -0,0 +1,273 @@
+use crate::domain::SubscriberTag;
+
+/// How deeply expressions can be nested - segments are typed by hand,
+/// anything deeper is a mistake (or an attempt at blowing up the parser).
+const MAX_DEPTH: usize = 32;
+const MAX_TAGS: usize = 64;
+
+/// A boolean expression over subscriber tags, e.g. `tag beta AND NOT tag churned`.
+///
+/// ```text
+/// segment := term ("OR" term)*
+/// term    := factor ("AND" factor)*
+/// factor  := "NOT" factor | "(" segment ")" | "tag" <tag>
+/// ```
+///
+/// Keywords are case-insensitive.
+#[derive(Debug, PartialEq)]
+pub enum Segment {
+    Tag(SubscriberTag),
+    Not(Box<Segment>),
+    And(Box<Segment>, Box<Segment>),
+    Or(Box<Segment>, Box<Segment>),
+}
+
+impl Segment {
+    pub fn parse(s: &str) -> Result<Segment, String> {
+        let mut parser = Parser {
+            tokens: tokenize(s),
+            position: 0,
+            n_tags: 0,
+        };
+
+        let segment = parser.segment(0)?;
+        match parser.next() {
+            None => Ok(segment),
+            Some(token) => Err(format!("Unexpected `{}` in segment", token)),
+        }
+    }
+
+    /// Compile the segment into a SQL condition on a `subscriptions` row aliased `s`.
+    ///
+    /// Tags are not inlined: the condition refers to them through numbered
+    /// placeholders, starting at `$first_placeholder`. The tags to bind are
+    /// returned in placeholder order.
+    pub fn to_sql(&self, first_placeholder: usize) -> (String, Vec<String>) {
+        let mut tags = Vec::new();
+        let condition = self.write_sql(first_placeholder, &mut tags);
+        (condition, tags)
+    }
+
+    fn write_sql(&self, first_placeholder: usize, tags: &mut Vec<String>) -> String {
+        match self {
+            Segment::Tag(tag) => {
+                tags.push(tag.as_ref().to_owned());
+                format!(
+                    "EXISTS (SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = ${})",
+                    first_placeholder + tags.len() - 1
+                )
+            }
+            Segment::Not(s) => format!("NOT ({})", s.write_sql(first_placeholder, tags)),
+            Segment::And(l, r) => format!(
+                "({} AND {})",
+                l.write_sql(first_placeholder, tags),
+                r.write_sql(first_placeholder, tags)
+            ),
+            Segment::Or(l, r) => format!(
+                "({} OR {})",
+                l.write_sql(first_placeholder, tags),
+                r.write_sql(first_placeholder, tags)
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for Segment {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Segment::Tag(tag) => write!(f, "tag {}", tag),
+            Segment::Not(s) => match **s {
+                Segment::And(..) | Segment::Or(..) => write!(f, "NOT ({})", s),
+                _ => write!(f, "NOT {}", s),
+            },
+            Segment::And(l, r) => {
+                for (i, s) in [l, r].into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " AND ")?;
+                    }
+                    match **s {
+                        Segment::Or(..) => write!(f, "({})", s)?,
+                        _ => write!(f, "{}", s)?,
+                    }
+                }
+                Ok(())
+            }
+            Segment::Or(l, r) => write!(f, "{} OR {}", l, r),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut word = String::new();
+
+    for c in s.chars() {
+        if c.is_whitespace() || c == '(' || c == ')' {
+            if !word.is_empty() {
+                tokens.push(std::mem::take(&mut word));
+            }
+            if !c.is_whitespace() {
+                tokens.push(c.to_string());
+            }
+        } else {
+            word.push(c);
+        }
+    }
+
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    position: usize,
+    n_tags: usize,
+}
+
+impl Parser {
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.position), Some(t) if t.eq_ignore_ascii_case(keyword))
+    }
+
+    fn next(&mut self) -> Option<&str> {
+        let token = self.tokens.get(self.position)?;
+        self.position += 1;
+        Some(token)
+    }
+
+    fn segment(&mut self, depth: usize) -> Result<Segment, String> {
+        if depth > MAX_DEPTH {
+            return Err("The segment is nested too deeply".into());
+        }
+
+        let mut segment = self.term(depth)?;
+        while self.peek_keyword("OR") {
+            self.position += 1;
+            segment = Segment::Or(Box::new(segment), Box::new(self.term(depth)?));
+        }
+
+        Ok(segment)
+    }
+
+    fn term(&mut self, depth: usize) -> Result<Segment, String> {
+        let mut segment = self.factor(depth)?;
+        while self.peek_keyword("AND") {
+            self.position += 1;
+            segment = Segment::And(Box::new(segment), Box::new(self.factor(depth)?));
+        }
+
+        Ok(segment)
+    }
+
+    fn factor(&mut self, depth: usize) -> Result<Segment, String> {
+        if depth > MAX_DEPTH {
+            return Err("The segment is nested too deeply".into());
+        }
+
+        let token = self
+            .next()
+            .ok_or("The segment ended unexpectedly")?
+            .to_owned();
+
+        if token.eq_ignore_ascii_case("NOT") {
+            Ok(Segment::Not(Box::new(self.factor(depth + 1)?)))
+        } else if token == "(" {
+            let segment = self.segment(depth + 1)?;
+            match self.next() {
+                Some(")") => Ok(segment),
+                _ => Err("Missing `)` in segment".into()),
+            }
+        } else if token.eq_ignore_ascii_case("tag") {
+            self.n_tags += 1;
+            if self.n_tags > MAX_TAGS {
+                return Err(format!(
+                    "A segment cannot refer to more than {} tags",
+                    MAX_TAGS
+                ));
+            }
+            let tag = self.next().ok_or("Missing tag name after `tag`")?;
+            Ok(Segment::Tag(SubscriberTag::parse(tag)?))
+        } else {
+            Err(format!(
+                "Unexpected `{}` in segment, expected `tag`, `NOT` or `(`",
+                token
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Segment;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn a_single_tag_is_a_valid_segment() {
+        let segment = assert_ok!(Segment::parse("tag beta"));
+        assert_eq!(segment.to_string(), "tag beta");
+    }
+
+    #[test]
+    fn keywords_are_case_insensitive() {
+        let segment = assert_ok!(Segment::parse("TAG beta and not Tag churned"));
+        assert_eq!(segment.to_string(), "tag beta AND NOT tag churned");
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let segment = assert_ok!(Segment::parse("tag a OR tag b AND tag c"));
+        assert!(matches!(segment, Segment::Or(..)));
+
+        let segment = assert_ok!(Segment::parse("(tag a OR tag b) AND tag c"));
+        assert_eq!(segment.to_string(), "(tag a OR tag b) AND tag c");
+    }
+
+    #[test]
+    fn invalid_segments_are_rejected() {
+        for segment in [
+            "",
+            "beta",
+            "tag",
+            "tag beta AND",
+            "tag beta tag paying",
+            "(tag beta",
+            "tag beta)",
+            "NOT",
+            "tag <beta>",
+        ] {
+            assert_err!(Segment::parse(segment), "{} was accepted", segment);
+        }
+    }
+
+    #[test]
+    fn deeply_nested_segments_are_rejected() {
+        let segment = format!("{}tag beta{}", "(".repeat(100), ")".repeat(100));
+        assert_err!(Segment::parse(&segment));
+
+        let segment = format!("{}tag beta", "NOT ".repeat(100));
+        assert_err!(Segment::parse(&segment));
+    }
+
+    #[test]
+    fn segments_referring_to_too_many_tags_are_rejected() {
+        let segment = vec!["tag beta"; 100].join(" AND ");
+        assert_err!(Segment::parse(&segment));
+    }
+
+    #[test]
+    fn tags_are_bound_as_parameters() {
+        let segment = Segment::parse("tag beta AND NOT tag region:eu").unwrap();
+
+        let (condition, tags) = segment.to_sql(3);
+
+        assert_eq!(
+            condition,
+            "(EXISTS (SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = $3) \
+            AND NOT (EXISTS (SELECT 1 FROM subscriber_tags t WHERE t.subscriber_id = s.id AND t.tag = $4)))"
+        );
+        assert_eq!(tags, ["beta", "region:eu"]);
+    }
+}
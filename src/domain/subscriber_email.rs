@@ -1,16 +1,36 @@
 use validator::validate_email;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SubscriberEmail(String);
 
 impl SubscriberEmail {
+    /// Parse an email address, normalising it along the way: surrounding
+    /// whitespace is trimmed and the domain is lowercased and converted to its
+    /// ASCII (punycode) form. The local part is kept as it was typed.
     pub fn parse(s: String) -> Result<SubscriberEmail, String> {
-        if validate_email(&s) {
-            Ok(Self(s))
-        } else {
-            Err(format!("{} is not a valid subscriber email", s))
+        let normalised = s.trim().rsplit_once('@').and_then(|(local_part, domain)| {
+            let domain = idna::domain_to_ascii(domain).ok()?;
+            Some(format!("{}@{}", local_part, domain))
+        });
+
+        match normalised {
+            Some(email) if validate_email(&email) => Ok(Self(email)),
+            _ => Err(format!("{} is not a valid subscriber email", s)),
         }
     }
+
+    pub fn domain(&self) -> &str {
+        self.0
+            .rsplit_once('@')
+            .map(|(_, domain)| domain)
+            .unwrap_or_default()
+    }
+
+    /// The key subscribers are told apart by - `Foo@Example.com` and
+    /// `foo@example.com` are the same person.
+    pub fn key(&self) -> String {
+        self.0.to_lowercase()
+    }
 }
 
 impl AsRef<str> for SubscriberEmail {
@@ -28,7 +48,7 @@ impl std::fmt::Display for SubscriberEmail {
 #[cfg(test)]
 mod tests {
     use super::SubscriberEmail;
-    use claim::assert_err;
+    use claim::{assert_err, assert_ok};
     use fake::faker::internet::en::SafeEmail;
     use fake::Fake;
     use quickcheck::Gen;
@@ -51,6 +71,32 @@ mod tests {
         assert_err!(SubscriberEmail::parse(email));
     }
 
+    #[test]
+    fn surrounding_whitespace_is_trimmed() {
+        let email = assert_ok!(SubscriberEmail::parse("  john@domain.com\n".to_string()));
+        assert_eq!(email.as_ref(), "john@domain.com");
+    }
+
+    #[test]
+    fn the_domain_is_lowercased_but_the_local_part_is_not() {
+        let email = assert_ok!(SubscriberEmail::parse("John@Domain.COM".to_string()));
+        assert_eq!(email.as_ref(), "John@domain.com");
+    }
+
+    #[test]
+    fn internationalised_domains_are_converted_to_punycode() {
+        let email = assert_ok!(SubscriberEmail::parse("john@bücher.de".to_string()));
+        assert_eq!(email.as_ref(), "john@xn--bcher-kva.de");
+        assert_eq!(email.domain(), "xn--bcher-kva.de");
+    }
+
+    #[test]
+    fn addresses_differing_only_by_case_share_the_same_key() {
+        let a = SubscriberEmail::parse("John@Domain.com".to_string()).unwrap();
+        let b = SubscriberEmail::parse("john@domain.com".to_string()).unwrap();
+        assert_eq!(a.key(), b.key());
+    }
+
     #[derive(Debug, Clone)]
     struct ValidEmailFixture(pub String);
 
@@ -0,0 +1,78 @@
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    /// Tags are case-insensitive: they are stored lowercased.
+    /// Besides letters and digits they may contain `:`, `_`, `-` and `.`,
+    /// e.g. `region:eu`.
+    pub fn parse(s: &str) -> Result<SubscriberTag, String> {
+        let tag = s.trim().to_lowercase();
+
+        let is_too_long = tag.chars().count() > 64;
+        let has_valid_characters = tag
+            .chars()
+            .all(|c| c.is_alphanumeric() || [':', '_', '-', '.'].contains(&c));
+
+        if tag.is_empty() || is_too_long || !has_valid_characters {
+            Err(format!("{} is not a valid tag", s))
+        } else {
+            Ok(Self(tag))
+        }
+    }
+
+    /// Parse a list of tags separated by whitespace or semicolons.
+    pub fn parse_list(s: &str) -> Result<Vec<SubscriberTag>, String> {
+        s.split(|c: char| c.is_whitespace() || c == ';')
+            .filter(|t| !t.is_empty())
+            .map(SubscriberTag::parse)
+            .collect()
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for SubscriberTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SubscriberTag;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn tags_are_lowercased_and_trimmed() {
+        let tag = assert_ok!(SubscriberTag::parse(" Region:EU "));
+        assert_eq!(tag.as_ref(), "region:eu");
+    }
+
+    #[test]
+    fn empty_tags_are_rejected() {
+        assert_err!(SubscriberTag::parse("  "));
+    }
+
+    #[test]
+    fn tags_with_forbidden_characters_are_rejected() {
+        for tag in ["beta tester", "a,b", "(beta)", "<script>"] {
+            assert_err!(SubscriberTag::parse(tag));
+        }
+    }
+
+    #[test]
+    fn a_65_characters_long_tag_is_rejected() {
+        assert_err!(SubscriberTag::parse(&"a".repeat(65)));
+    }
+
+    #[test]
+    fn tag_lists_are_split_on_whitespace_and_semicolons() {
+        let tags = assert_ok!(SubscriberTag::parse_list("beta; paying region:eu"));
+        let tags: Vec<&str> = tags.iter().map(AsRef::as_ref).collect();
+        assert_eq!(tags, ["beta", "paying", "region:eu"]);
+    }
+}
@@ -1,12 +1,55 @@
 use crate::domain::SubscriberEmail;
-use reqwest::Client;
+use crate::rate_limiter::{QuotaExceeded, RateLimiter};
+use chrono::Utc;
+use reqwest::{Client, StatusCode};
 use secrecy::{ExposeSecret, Secret};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
 
+/// How long to hold off when the provider throttles us without saying.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Clones share the same rate limiter.
+#[derive(Clone)]
 pub struct EmailClient {
     http_client: Client,
     base_url: String,
     sender: SubscriberEmail,
     api_key: Secret<String>,
+    rate_limiter: Arc<RateLimiter>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SendEmailError {
+    #[error(transparent)]
+    QuotaExceeded(#[from] QuotaExceeded),
+    #[error("The email provider asked us to slow down")]
+    Throttled { retry_after: Duration },
+    #[error("Failed to send an email")]
+    RequestError(#[from] reqwest::Error),
+}
+
+impl SendEmailError {
+    /// How long to wait before trying again, when sending is on hold rather
+    /// than failing.
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            SendEmailError::QuotaExceeded(e) => Some(e.resets_in),
+            SendEmailError::Throttled { retry_after } => Some(*retry_after),
+            SendEmailError::RequestError(_) => None,
+        }
+    }
+
+    /// Whether the provider turned the email down - e.g. for an invalid recipient.
+    pub fn is_rejected(&self) -> bool {
+        match self {
+            SendEmailError::RequestError(e) => {
+                matches!(e.status(), Some(status) if status.is_client_error())
+            }
+            _ => false,
+        }
+    }
 }
 
 #[derive(serde::Serialize)]
@@ -15,6 +58,8 @@ struct SendEmailRequest<'a> {
     subject: &'a str,
     content: Vec<EmailContent<'a>>,
     personalization: Vec<EmailPersonalization<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<EmailAttachment<'a>>,
 }
 
 #[derive(serde::Serialize)]
@@ -29,9 +74,73 @@ struct EmailContent<'a> {
     value: &'a str,
 }
 
+#[derive(serde::Serialize)]
+struct EmailAttachment<'a> {
+    name: &'a str,
+    r#type: &'a str,
+    /// Base64 encoded
+    content: String,
+    disposition: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_id: Option<&'a str>,
+}
+
 #[derive(serde::Serialize)]
 struct EmailPersonalization<'a> {
     to: EmailPeer<'a>,
+    // Echoed back by the provider in the delivery events it reports
+    #[serde(rename = "x-apiheader", skip_serializing_if = "Option::is_none")]
+    x_apiheader: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attributes: Option<&'a BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    headers: Option<BTreeMap<&'a str, String>>,
+}
+
+#[derive(serde::Deserialize)]
+struct SendEmailResponse {
+    data: SendEmailResponseData,
+}
+
+#[derive(serde::Deserialize)]
+struct SendEmailResponseData {
+    message_id: String,
+}
+
+/// A file sent along with an email.
+pub struct Attachment {
+    pub filename: String,
+    pub content_type: String,
+    pub content: Vec<u8>,
+    /// Set for images displayed inline, referenced as `<img src="cid:...">`
+    /// in the HTML body.
+    pub content_id: Option<String>,
+}
+
+/// What an email is made of: a plain-text body at least.
+pub struct EmailBody<'a> {
+    pub html: Option<&'a str>,
+    pub text: &'a str,
+    pub attachments: &'a [Attachment],
+}
+
+/// One of the recipients of a batch send.
+pub struct BatchRecipient<'a> {
+    pub email: &'a SubscriberEmail,
+    pub name: &'a str,
+    /// What the placeholders of the subject and body are replaced with for
+    /// this recipient, by attribute name.
+    pub attributes: BTreeMap<String, String>,
+    /// The same every time this email is sent to this recipient. It makes up
+    /// the `Message-ID` of the email: a send that already went out can be told
+    /// apart from a new one.
+    pub idempotency_key: String,
+}
+
+/// How placeholders are written for the provider: `[%NAME%]` is replaced
+/// with the `NAME` attribute of each recipient of a batch send.
+pub fn placeholder(attribute: &str) -> String {
+    format!("[%{}%]", attribute)
 }
 
 impl EmailClient {
@@ -40,6 +149,7 @@ impl EmailClient {
         sender: SubscriberEmail,
         api_key: Secret<String>,
         timeout: std::time::Duration,
+        rate_limiter: RateLimiter,
     ) -> Self {
         let http_client = Client::builder().timeout(timeout).build().unwrap();
 
@@ -48,16 +158,83 @@ impl EmailClient {
             base_url,
             sender,
             api_key,
+            rate_limiter: Arc::new(rate_limiter),
         }
     }
 
+    /// See `RateLimiter::count_sent_today`.
+    pub fn count_sent_today(&self, n: u32) {
+        self.rate_limiter.count_sent_today(n);
+    }
+
     pub async fn send_email(
         &self,
         recipient: &SubscriberEmail,
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
+    ) -> Result<(), SendEmailError> {
+        let body = EmailBody {
+            html: Some(html_content),
+            text: text_content,
+            attachments: &[],
+        };
+
+        let personalization = EmailPersonalization {
+            to: EmailPeer {
+                email: recipient.as_ref(),
+                name: recipient.as_ref(),
+            },
+            x_apiheader: None,
+            attributes: None,
+            headers: None,
+        };
+
+        self.send(vec![personalization], subject, &body).await?;
+
+        Ok(())
+    }
+
+    /// Send the same email to several recipients in a single request, the
+    /// provider filling in the placeholders (see `placeholder`) for each of them.
+    ///
+    /// The email carries a `tag` - e.g. the id of a newsletter issue - that the
+    /// provider includes in the delivery events (bounces, complaints, ...) it reports.
+    /// Without an HTML body only the plain-text one is sent.
+    ///
+    /// Returns the id the provider gave to the request, if it says.
+    pub async fn send_batch(
+        &self,
+        recipients: &[BatchRecipient<'_>],
+        subject: &str,
+        body: &EmailBody<'_>,
+        tag: &str,
+    ) -> Result<Option<String>, SendEmailError> {
+        let personalization = recipients
+            .iter()
+            .map(|r| EmailPersonalization {
+                to: EmailPeer {
+                    email: r.email.as_ref(),
+                    name: r.name,
+                },
+                x_apiheader: Some(tag),
+                attributes: Some(&r.attributes),
+                headers: Some(BTreeMap::from([(
+                    "Message-ID",
+                    format!("<{}@{}>", r.idempotency_key, self.sender.domain()),
+                )])),
+            })
+            .collect();
+
+        self.send(personalization, subject, body).await
+    }
+
+    async fn send(
+        &self,
+        personalization: Vec<EmailPersonalization<'_>>,
+        subject: &str,
+        body: &EmailBody<'_>,
+    ) -> Result<Option<String>, SendEmailError> {
         // curl --request POST \
         // --url https://emailapi.netcorecloud.net/v5/mail/send \
         // --header 'api_key: <Your API Key>' \
@@ -71,51 +248,106 @@ impl EmailClient {
         // /v5/mail/send
         let url = format!("{}/v5/mail/send", self.base_url);
 
+        let mut content = Vec::new();
+        if let Some(html_content) = body.html {
+            content.push(EmailContent {
+                r#type: "html",
+                value: html_content,
+            });
+        }
+        content.push(EmailContent {
+            r#type: "text",
+            value: body.text,
+        });
+
+        let attachments = body
+            .attachments
+            .iter()
+            .map(|a| EmailAttachment {
+                name: &a.filename,
+                r#type: &a.content_type,
+                content: base64::encode(&a.content),
+                disposition: if a.content_id.is_some() {
+                    "inline"
+                } else {
+                    "attachment"
+                },
+                content_id: a.content_id.as_deref(),
+            })
+            .collect();
+
         let request_body = SendEmailRequest {
             from: EmailPeer {
                 email: self.sender.as_ref(),
                 name: "zero2prod",
             },
             subject,
-            content: vec![
-                EmailContent {
-                    r#type: "html",
-                    value: html_content,
-                },
-                EmailContent {
-                    r#type: "text",
-                    value: text_content,
-                },
-            ],
-            personalization: vec![EmailPersonalization {
-                to: EmailPeer {
-                    email: recipient.as_ref(),
-                    name: recipient.as_ref(),
-                },
-            }],
+            content,
+            personalization,
+            attachments,
         };
 
-        self.http_client
+        self.rate_limiter
+            .acquire(request_body.personalization.len() as u32)
+            .await?;
+
+        let response = self
+            .http_client
             .post(&url)
             .header("api_key", self.api_key.expose_secret())
             .json(&request_body)
             .send()
-            .await?
-            .error_for_status()?;
+            .await?;
 
-        Ok(())
+        if response.status() == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = retry_after(&response);
+            // Everything sending through the client holds off, not just us
+            self.rate_limiter.pause_for(retry_after);
+            return Err(SendEmailError::Throttled { retry_after });
+        }
+        let message_id = response
+            .error_for_status()?
+            .json::<SendEmailResponse>()
+            .await
+            .ok()
+            .map(|r| r.data.message_id);
+
+        Ok(message_id)
     }
 }
 
+/// How long the provider asks us to wait: `Retry-After` is either a number
+/// of seconds or a date.
+fn retry_after(response: &reqwest::Response) -> Duration {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| match value.trim().parse::<u64>() {
+            Ok(seconds) => Some(Duration::from_secs(seconds)),
+            Err(_) => chrono::DateTime::parse_from_rfc2822(value)
+                .ok()
+                .map(|date| {
+                    (date.with_timezone(&Utc) - Utc::now())
+                        .to_std()
+                        .unwrap_or_default()
+                }),
+        })
+        .unwrap_or(DEFAULT_RETRY_AFTER)
+}
+
 #[cfg(test)]
 mod tests {
     use crate::domain::SubscriberEmail;
-    use crate::email_client::EmailClient;
+    use crate::email_client::{BatchRecipient, EmailBody, EmailClient, SendEmailError};
+    use crate::rate_limiter::RateLimiter;
     use claim::{assert_err, assert_ok};
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::{Fake, Faker};
     use secrecy::Secret;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
     use wiremock::matchers::{any, header, method, path};
     use wiremock::Request;
     use wiremock::{Mock, MockServer, ResponseTemplate};
@@ -207,6 +439,116 @@ mod tests {
         assert_err!(outcome);
     }
 
+    #[tokio::test]
+    async fn send_batch_fires_a_single_request_with_a_personalization_per_recipient() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let emails = [email(), email()];
+        let recipients: Vec<_> = emails
+            .iter()
+            .enumerate()
+            .map(|(i, email)| BatchRecipient {
+                email,
+                name: "Ursula",
+                attributes: BTreeMap::from([("N".to_string(), i.to_string())]),
+                idempotency_key: format!("key-{}", i),
+            })
+            .collect();
+        let body = EmailBody {
+            html: None,
+            text: "Recipient number [%N%]",
+            attachments: &[],
+        };
+
+        Mock::given(path("/v5/mail/send"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "data": {"message_id": "a-message-id"},
+                "message": "OK",
+                "status": "success"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_batch(&recipients, &subject(), &body, "a-tag")
+            .await;
+
+        // Assert
+        assert_eq!(outcome.unwrap().as_deref(), Some("a-message-id"));
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let request: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        let personalization = request["personalization"].as_array().unwrap();
+        assert_eq!(personalization.len(), 2);
+        for (i, p) in personalization.iter().enumerate() {
+            assert_eq!(p["to"]["email"], emails[i].as_ref());
+            assert_eq!(p["x-apiheader"], "a-tag");
+            assert_eq!(p["attributes"]["N"], i.to_string());
+            let message_id = p["headers"]["Message-ID"].as_str().unwrap();
+            assert!(message_id.starts_with(&format!("<key-{}@", i)));
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_reports_how_long_to_wait_when_throttled() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "30"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        match outcome {
+            Err(SendEmailError::Throttled { retry_after }) => {
+                assert_eq!(retry_after, Duration::from_secs(30))
+            }
+            _ => panic!("Expected the email to be throttled"),
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_without_a_request_once_the_daily_quota_is_used_up() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = EmailClient::new(
+            mock_server.uri(),
+            email(),
+            Secret::new(Faker.fake()),
+            std::time::Duration::from_millis(200),
+            RateLimiter::new(10, 1),
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let first = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        let second = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_ok!(first);
+        assert!(matches!(second, Err(SendEmailError::QuotaExceeded(_))));
+    }
+
     fn subject() -> String {
         Sentence(1..2).fake()
     }
@@ -225,6 +567,7 @@ mod tests {
             email(),
             Secret::new(Faker.fake()),
             std::time::Duration::from_millis(20),
+            RateLimiter::new(1000, 1000),
         )
     }
 }
@@ -5,19 +5,21 @@ use chrono::{DateTime, Utc};
 use sqlx::{postgres::PgHasArrayType, PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
-use crate::{configuration::Settings, startup::get_connection_pool};
+use crate::{configuration::Settings, shutdown::Shutdown, startup::get_connection_pool};
 
 use super::IdempotencyKey;
 
-pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);
-    worker_loop(&connection_pool).await
+    worker_loop(&connection_pool, shutdown).await
 }
 
-async fn worker_loop(pool: &PgPool) -> Result<(), anyhow::Error> {
-    let expiration_time = Utc::now() - chrono::Duration::minutes(5);
-
-    loop {
+async fn worker_loop(pool: &PgPool, shutdown: Shutdown) -> Result<(), anyhow::Error> {
+    while !shutdown.is_triggered() {
+        let expiration_time = Utc::now() - chrono::Duration::minutes(5);
         if let Err(e) = delete_expired_idempotency_keys(pool, expiration_time).await {
             tracing::error!(
             error.cause_chain = ?e,
@@ -25,8 +27,13 @@ async fn worker_loop(pool: &PgPool) -> Result<(), anyhow::Error> {
             "Failed to delete expired idempotency keys"
             );
         }
-        tokio::time::sleep(Duration::from_secs(300)).await;
+        tokio::select! {
+            _ = tokio::time::sleep(Duration::from_secs(300)) => {}
+            _ = shutdown.triggered() => {}
+        }
     }
+
+    Ok(())
 }
 
 pub async fn delete_expired_idempotency_keys(
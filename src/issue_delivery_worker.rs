@@ -1,145 +1,940 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
 use std::time::Duration;
 
-use sqlx::{PgPool, Postgres, Transaction};
-use tracing::{field::display, Span};
+use anyhow::Context;
+use futures_util::future::try_join_all;
+use secrecy::Secret;
+use sha2::{Digest, Sha256};
+use sqlx::{
+    postgres::{PgListener, PgPoolOptions},
+    PgPool, Postgres, Transaction,
+};
+use tokio::sync::Notify;
+use tracing::{field::display, Instrument, Span};
 use uuid::Uuid;
 
 use crate::{
-    configuration::Settings, domain::SubscriberEmail, email_client::EmailClient,
-    startup::get_connection_pool,
+    ab_testing::{get_variant_subject, pick_winner, TestMetric},
+    attachments::get_issue_attachments,
+    configuration::Settings,
+    domain::{MergeTemplate, MergeValues, SubscriberEmail},
+    email_client::{placeholder, BatchRecipient, EmailBody, EmailClient, SendEmailError},
+    routes::preferences_link,
+    shutdown::Shutdown,
+    tracking::{extract_links, link_attribute, open_pixel_url, tracked_link},
 };
 
-pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
-    let connection_pool = get_connection_pool(&configuration.database);
-    let email_client = configuration.email_client.client();
+/// Where new delivery tasks are announced, see `enqueue_delivery_tasks`.
+pub const NEW_TASKS_CHANNEL: &str = "new_delivery_tasks";
+
+/// Deliver issues with `delivery.concurrency` workers.
+///
+/// Workers share `email_client` - and its rate limit - with the API. They
+/// pick their tasks with `FOR UPDATE SKIP LOCKED`, hence never deliver the
+/// same task twice.
+/// Idle workers wait for new tasks to be announced on `NEW_TASKS_CHANNEL`,
+/// polling every `delivery.poll_interval_seconds` in case they miss one.
+///
+/// Returns once `shutdown` is triggered and every worker is done with the
+/// batch it was delivering.
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    email_client: EmailClient,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    let concurrency = configuration.delivery.concurrency.max(1);
+    // A task holds a connection for its transaction while querying the pool
+    // with another one. The listener keeps one to itself.
+    let connection_pool = PgPoolOptions::new()
+        .connect_timeout(Duration::from_secs(2))
+        .max_connections(2 * concurrency as u32 + 1)
+        .connect_lazy_with(configuration.database.with_db());
+
+    // The limiter starts afresh: do not let a restart reset the daily quota
+    match count_sent_today(&connection_pool).await {
+        Ok(n_sent) => email_client.count_sent_today(n_sent),
+        Err(e) => {
+            tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to count the emails sent today. The daily quota starts afresh"
+            );
+        }
+    }
+
+    let worker = Arc::new(DeliveryWorker {
+        pool: connection_pool,
+        email_client,
+        base_url: configuration.application.base_url,
+        hmac_secret: configuration.application.hmac_secret,
+        batch_size: configuration.delivery.batch_size,
+        poll_interval: Duration::from_secs(configuration.delivery.poll_interval_seconds),
+        new_tasks: Notify::new(),
+        n_delivered: AtomicU64::new(0),
+    });
+
+    let listener = tokio::spawn(listen_for_new_tasks(worker.clone()));
+    let reporter = tokio::spawn(report_throughput(worker.clone()));
+    let mut handles = vec![];
+    for worker_id in 0..concurrency {
+        let worker = worker.clone();
+        let shutdown = shutdown.clone();
+        handles.push(tokio::spawn(
+            async move { worker_loop(&worker, shutdown).await }
+                .instrument(tracing::info_span!("Delivery worker", worker_id)),
+        ));
+    }
 
-    worker_loop(&connection_pool, &email_client).await
+    let outcomes = try_join_all(handles).await;
+    // Nobody left to wake up or report on
+    listener.abort();
+    reporter.abort();
+    for outcome in outcomes? {
+        outcome?;
+    }
+
+    Ok(())
 }
 
-async fn worker_loop(pool: &PgPool, email_client: &EmailClient) -> Result<(), anyhow::Error> {
-    loop {
-        match try_execute_task(pool, email_client).await {
+struct DeliveryWorker {
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: String,
+    hmac_secret: Secret<String>,
+    batch_size: u16,
+    poll_interval: Duration,
+    /// Wakes idle workers up when new tasks are enqueued.
+    new_tasks: Notify,
+    /// Tasks completed by all the workers since the last throughput report.
+    n_delivered: AtomicU64,
+}
+
+async fn worker_loop(worker: &DeliveryWorker, shutdown: Shutdown) -> Result<(), anyhow::Error> {
+    // Only checked between batches: stopping halfway through one would send
+    // its emails again after a restart.
+    while !shutdown.is_triggered() {
+        // Created before looking at the queue: tasks enqueued in the meantime
+        // are not missed.
+        let new_tasks = worker.new_tasks.notified();
+
+        match try_execute_task(
+            &worker.pool,
+            &worker.email_client,
+            &worker.base_url,
+            &worker.hmac_secret,
+            worker.batch_size,
+        )
+        .await
+        {
             Ok(ExecutionOutcome::EmptyQueue) => {
-                tokio::time::sleep(Duration::from_secs(10)).await;
+                tokio::select! {
+                    _ = new_tasks => {}
+                    _ = tokio::time::sleep(worker.poll_interval) => {}
+                    _ = shutdown.triggered() => {}
+                }
             }
             Err(_) => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+                    _ = shutdown.triggered() => {}
+                }
+            }
+            Ok(ExecutionOutcome::BatchCompleted { n_delivered }) => {
+                worker.n_delivered.fetch_add(n_delivered, Ordering::Relaxed);
             }
-            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// How many emails the send log shows attempts for since midnight UTC, by
+/// any process: with several `deliver` processes, each one counts them all.
+pub async fn count_sent_today(pool: &PgPool) -> Result<u32, anyhow::Error> {
+    let n_sent = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "n_sent!"
+        FROM deliveries
+        WHERE attempted_at >= date_trunc('day', now() AT TIME ZONE 'UTC') AT TIME ZONE 'UTC'
+        "#
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count the emails sent today")?
+    .n_sent;
+
+    Ok(u32::try_from(n_sent).unwrap_or(u32::MAX))
+}
+
+/// Wake idle workers up whenever tasks are enqueued.
+///
+/// If the connection is lost, workers fall back to polling until it is back.
+async fn listen_for_new_tasks(worker: Arc<DeliveryWorker>) -> Result<(), anyhow::Error> {
+    loop {
+        if let Err(e) = forward_notifications(&worker).await {
+            tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to listen for new delivery tasks"
+            );
+            // Tasks may have been announced while we were not listening
+            worker.new_tasks.notify_waiters();
+            tokio::time::sleep(worker.poll_interval).await;
+        }
+    }
+}
+
+async fn forward_notifications(worker: &DeliveryWorker) -> Result<(), sqlx::Error> {
+    let mut listener = PgListener::connect_with(&worker.pool).await?;
+    listener.listen(NEW_TASKS_CHANNEL).await?;
+
+    loop {
+        listener.recv().await?;
+        worker.new_tasks.notify_waiters();
+    }
+}
+
+/// Periodically trace how many tasks the workers completed.
+async fn report_throughput(worker: Arc<DeliveryWorker>) -> Result<(), anyhow::Error> {
+    const REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
+    loop {
+        tokio::time::sleep(REPORT_INTERVAL).await;
+
+        let n_delivered = worker.n_delivered.swap(0, Ordering::Relaxed);
+        if n_delivered > 0 {
+            tracing::info!(
+                n_delivered,
+                deliveries_per_second = n_delivered as f64 / REPORT_INTERVAL.as_secs_f64(),
+                "Delivery throughput"
+            );
         }
     }
 }
 
 pub enum ExecutionOutcome {
-    TaskCompleted,
+    BatchCompleted { n_delivered: u64 },
     EmptyQueue,
 }
 
+/// How many times sending to a recipient is retried before giving up.
+const MAX_RETRIES: i32 = 5;
+
 #[tracing::instrument(
     skip_all,
     fields(
         newsletter_issue_id=tracing::field::Empty,
-        subscriber_email=tracing::field::Empty
+        n_recipients=tracing::field::Empty
     ),
     err
 )]
 pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    batch_size: u16,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    let task = dequeue_task(pool).await?;
-
-    if task.is_none() {
-        return Ok(ExecutionOutcome::EmptyQueue);
-    }
-
-    let (transaction, issue_id, email) = task.unwrap();
+    let (transaction, batch) = match dequeue_batch(pool, batch_size).await? {
+        Some(batch) => batch,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    let issue_id = batch.issue_id;
 
     Span::current()
         .record("newsletter_issue_id", &display(issue_id))
-        .record("subscriber_email", &display(&email));
-
-    match SubscriberEmail::parse(email.clone()) {
-        Ok(email) => {
-            let issue = get_issue(pool, issue_id).await?;
-
-            if let Err(e) = email_client
-                .send_email(
-                    &email,
-                    &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
-                )
-                .await
-            {
-                // TODO: Add retry mechanism
+        .record("n_recipients", &batch.emails.len());
+
+    let issue = get_issue(pool, issue_id).await?;
+    let subject = get_subject(pool, issue_id, &issue, batch.subject_variant).await?;
+    let mut attachments = get_issue_attachments(pool, issue_id).await?;
+    let content = IssueContent::render(&issue, base_url);
 
+    let mut outcome = BatchOutcome::default();
+    let mut emails = Vec::new();
+    for email in batch.emails {
+        match SubscriberEmail::parse(email.clone()) {
+            Ok(parsed) => emails.push((email, parsed)),
+            Err(e) => {
                 tracing::error!(
                 error.cause_chain = ?e,
                 error.message = %e,
-                "Failed to deliver issue to a confirmed subscriber. Skipping."
+                "Skipping a confirmed subscriber. Their stored contact details are invalid"
                 );
+                outcome.given_up.push(email);
             }
         }
-        Err(e) => {
-            tracing::error!(
-            error.cause_chain = ?e,
-            error.message = %e,
-            "Skipping a confirmed subscriber. Their stored contact details are invalid"
+    }
+
+    let recipients = get_recipients(pool, &emails).await?;
+    let already_sent = get_already_sent(pool, issue_id, &emails).await?;
+    let mut html_group = Vec::new();
+    let mut text_group = Vec::new();
+    for (email, parsed) in &emails {
+        // The task is being retried after the send went out - e.g. we crashed
+        // before recording it in the queue
+        if let Some(&in_doubt) = already_sent.get(email) {
+            if in_doubt {
+                tracing::warn!(
+                    subscriber_email = %email,
+                    "Giving up on a send that may have gone out, an earlier attempt has no recorded outcome"
+                );
+                outcome.in_doubt.push(email.clone());
+            } else {
+                tracing::info!(subscriber_email = %email, "Skipping a send that already went out");
+                outcome.delivered.push(email.clone());
+            }
+            continue;
+        }
+        let recipient = match recipients.get(&parsed.key()) {
+            Some(recipient) => recipient,
+            // They unsubscribed, were suppressed or erased their data since
+            // being queued
+            None => {
+                outcome.given_up.push(email.clone());
+                continue;
+            }
+        };
+        let attributes =
+            content.attributes(&issue, issue_id, recipient, parsed, base_url, hmac_secret);
+        let group = if recipient.text_only {
+            &mut text_group
+        } else {
+            &mut html_group
+        };
+        group.push((
+            email.clone(),
+            BatchRecipient {
+                email: parsed,
+                name: &recipient.name,
+                attributes,
+                idempotency_key: idempotency_key(issue_id, parsed),
+            },
+        ));
+    }
+
+    let body = EmailBody {
+        html: Some(&content.html),
+        text: &content.text,
+        attachments: &attachments,
+    };
+    send_to_group(
+        pool,
+        email_client,
+        issue_id,
+        html_group,
+        &subject,
+        &body,
+        &mut outcome,
+    )
+    .await?;
+
+    // Subscribers who opted for plain-text emails do not get the HTML body
+    // nor the images displayed inline in it
+    attachments.retain(|a| a.content_id.is_none());
+    let body = EmailBody {
+        html: None,
+        text: &content.text,
+        attachments: &attachments,
+    };
+    send_to_group(
+        pool,
+        email_client,
+        issue_id,
+        text_group,
+        &subject,
+        &body,
+        &mut outcome,
+    )
+    .await?;
+
+    let n_delivered = outcome.delivered.len() as u64;
+    record_outcome(transaction, issue_id, outcome).await?;
+
+    Ok(ExecutionOutcome::BatchCompleted { n_delivered })
+}
+
+/// What became of the recipients of a batch, by queued email address.
+#[derive(Default)]
+struct BatchOutcome {
+    delivered: Vec<String>,
+    /// Tried again later, unless they ran out of retries.
+    failed: Vec<String>,
+    /// Not worth retrying.
+    given_up: Vec<String>,
+    /// An earlier attempt has no recorded outcome, it may have gone out. Given
+    /// up on rather than risking a duplicate, an admin decides whether to
+    /// requeue them.
+    in_doubt: Vec<String>,
+    /// Held off without counting as a failure - e.g. when the provider throttles
+    /// us - for `postponed_for`.
+    postponed: Vec<String>,
+    postponed_for: Duration,
+}
+
+impl BatchOutcome {
+    fn postpone(&mut self, emails: impl IntoIterator<Item = String>, retry_after: Duration) {
+        self.postponed.extend(emails);
+        self.postponed_for = self.postponed_for.max(retry_after);
+    }
+}
+
+/// Send to a group of recipients in a single request.
+///
+/// If the provider rejects it, every recipient is tried on their own: one
+/// bad address must not hold back the others. Other failures - timeouts,
+/// server errors - are retried later for the whole group, while throttling
+/// and the daily quota postpone it.
+///
+/// Fails only if the send log cannot be written to, before sending.
+async fn send_to_group(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    issue_id: Uuid,
+    group: Vec<(String, BatchRecipient<'_>)>,
+    subject: &str,
+    body: &EmailBody<'_>,
+    outcome: &mut BatchOutcome,
+) -> Result<(), anyhow::Error> {
+    if group.is_empty() {
+        return Ok(());
+    }
+    let (emails, recipients): (Vec<_>, Vec<_>) = group.into_iter().unzip();
+
+    let e = match send_and_log(
+        pool,
+        email_client,
+        issue_id,
+        &emails,
+        &recipients,
+        subject,
+        body,
+    )
+    .await?
+    {
+        Ok(()) => {
+            outcome.delivered.extend(emails);
+            return Ok(());
+        }
+        Err(e) => e,
+    };
+
+    if let Some(retry_after) = e.retry_after() {
+        tracing::warn!(
+        error.message = %e,
+        n_recipients = recipients.len(),
+        retry_after = ?retry_after,
+        "Sending is on hold. Postponing delivery."
+        );
+        outcome.postpone(emails, retry_after);
+        return Ok(());
+    }
+    if !e.is_rejected() || recipients.len() == 1 {
+        tracing::error!(
+        error.cause_chain = ?e,
+        error.message = %e,
+        n_recipients = recipients.len(),
+        "Failed to deliver issue to confirmed subscribers. Retrying later."
+        );
+        outcome.failed.extend(emails);
+        return Ok(());
+    }
+
+    let mut emails = emails.into_iter();
+    for recipient in &recipients {
+        let email = emails.next().unwrap();
+        match send_and_log(
+            pool,
+            email_client,
+            issue_id,
+            std::slice::from_ref(&email),
+            std::slice::from_ref(recipient),
+            subject,
+            body,
+        )
+        .await?
+        {
+            Ok(()) => outcome.delivered.push(email),
+            Err(e) => match e.retry_after() {
+                // The rest of the group is held off as well
+                Some(retry_after) => {
+                    outcome.postpone(std::iter::once(email).chain(emails), retry_after);
+                    return Ok(());
+                }
+                None => {
+                    tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    subscriber_email = %email,
+                    "Failed to deliver issue to a confirmed subscriber. Retrying later."
+                    );
+                    outcome.failed.push(email);
+                }
+            },
+        }
+    }
+
+    Ok(())
+}
+
+/// Send to `recipients` - queued as `emails` - in a single request, keeping
+/// track of the attempt in the send log.
+///
+/// The attempt is logged before sending, outside of the task transaction:
+/// should we crash before the task completes, the log tells whether the send
+/// went out. Fails if the attempt cannot be logged, without sending.
+async fn send_and_log(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    issue_id: Uuid,
+    emails: &[String],
+    recipients: &[BatchRecipient<'_>],
+    subject: &str,
+    body: &EmailBody<'_>,
+) -> Result<Result<(), SendEmailError>, anyhow::Error> {
+    let attempt_id = log_attempt(pool, issue_id, emails, recipients)
+        .await
+        .context("Failed to log a send attempt")?;
+
+    let tag = issue_id.to_string();
+    let result = email_client
+        .send_batch(recipients, subject, body, &tag)
+        .await;
+
+    // Recording the task as completed matters more: carry on regardless
+    if let Err(e) = log_attempt_outcome(pool, attempt_id, &result).await {
+        tracing::error!(
+        error.cause_chain = ?e,
+        error.message = %e,
+        "Failed to log the outcome of a send attempt"
+        );
+    }
+
+    Ok(result.map(|_| ()))
+}
+
+/// Stable across attempts to send an issue to a subscriber.
+fn idempotency_key(issue_id: Uuid, email: &SubscriberEmail) -> String {
+    format!(
+        "{:x}",
+        Sha256::digest(format!("{}:{}", issue_id, email.key()).as_bytes())
+    )
+}
+
+async fn log_attempt(
+    pool: &PgPool,
+    issue_id: Uuid,
+    emails: &[String],
+    recipients: &[BatchRecipient<'_>],
+) -> Result<Uuid, sqlx::Error> {
+    let attempt_id = Uuid::new_v4();
+    let keys: Vec<String> = recipients
+        .iter()
+        .map(|r| r.idempotency_key.clone())
+        .collect();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO deliveries (
+            attempt_id,
+            newsletter_issue_id,
+            subscriber_email,
+            idempotency_key,
+            attempted_at
+        )
+        SELECT $1, $2, email, key, now()
+        FROM UNNEST($3::text[], $4::text[]) AS t(email, key)
+        "#,
+        attempt_id,
+        issue_id,
+        emails,
+        &keys
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(attempt_id)
+}
+
+async fn log_attempt_outcome(
+    pool: &PgPool,
+    attempt_id: Uuid,
+    result: &Result<Option<String>, SendEmailError>,
+) -> Result<(), sqlx::Error> {
+    let (sent, provider_message_id, error) = match result {
+        Ok(message_id) => (true, message_id.clone(), None),
+        Err(e) => (false, None, Some(e.to_string())),
+    };
+
+    sqlx::query!(
+        r#"
+        UPDATE deliveries
+        SET
+            sent_at = CASE WHEN $2 THEN now() END,
+            provider_message_id = $3,
+            error = $4
+        WHERE attempt_id = $1
+        "#,
+        attempt_id,
+        sent,
+        provider_message_id,
+        error
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Who of `emails` the issue already went out to, according to the send log.
+///
+/// An attempt without a recorded outcome - we crashed, or failed to record
+/// it, while sending - may well have gone out: rather than risking a
+/// duplicate, it counts as sent, flagged as in doubt. The provider does not
+/// deduplicate on our Message-ID, sending it again is up to an admin - see
+/// `requeue_failed_deliveries`.
+async fn get_already_sent(
+    pool: &PgPool,
+    issue_id: Uuid,
+    emails: &[(String, SubscriberEmail)],
+) -> Result<HashMap<String, bool>, anyhow::Error> {
+    let emails: Vec<&str> = emails.iter().map(|(email, _)| email.as_str()).collect();
+    let rows = sqlx::query!(
+        r#"
+        SELECT subscriber_email, bool_and(sent_at IS NULL) AS "in_doubt!"
+        FROM deliveries
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = ANY($2) AND
+            (sent_at IS NOT NULL OR error IS NULL)
+        GROUP BY subscriber_email
+        "#,
+        issue_id,
+        &emails as &[&str]
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|r| (r.subscriber_email, r.in_doubt))
+        .collect())
+}
+
+/// An issue rendered once for a whole batch: what differs from a recipient
+/// to the next is left to placeholders, filled in by the provider from the
+/// attributes of each recipient.
+struct IssueContent {
+    html: String,
+    text: String,
+    /// The tracked links of `html`, see `extract_links`.
+    links: Vec<String>,
+}
+
+impl IssueContent {
+    fn render(issue: &NewsletterIssue, base_url: &str) -> Self {
+        // Values are escaped ahead of time in the `_HTML` attributes
+        let mut html = render_placeholders(&issue.html_content, "_HTML");
+        let text = render_placeholders(&issue.text_content, "");
+
+        let mut links = Vec::new();
+        if issue.track_clicks {
+            let (tracked_html, tracked_links) = extract_links(&html, base_url);
+            html = tracked_html;
+            links = tracked_links;
+        }
+        if issue.track_opens {
+            html = append_to_body(
+                html,
+                &format!(
+                    r#"<img src="{}" width="1" height="1" alt="" />"#,
+                    placeholder("OPEN_PIXEL_URL")
+                ),
+            );
+        }
+
+        let html = append_to_body(
+            html,
+            &format!(
+                r#"<p><a href="{}">Manage your subscription</a></p>"#,
+                placeholder("PREFERENCES_URL_HTML")
+            ),
+        );
+        let text = format!(
+            "{}\n\nManage your subscription: {}",
+            text,
+            placeholder("PREFERENCES_URL")
+        );
+
+        Self { html, text, links }
+    }
+
+    fn attributes(
+        &self,
+        issue: &NewsletterIssue,
+        issue_id: Uuid,
+        recipient: &Recipient,
+        email: &SubscriberEmail,
+        base_url: &str,
+        hmac_secret: &Secret<String>,
+    ) -> BTreeMap<String, String> {
+        let preferences_link = preferences_link(base_url, hmac_secret, recipient.id);
+        let mut attributes = BTreeMap::from([
+            (
+                "NAME_HTML".into(),
+                htmlescape::encode_attribute(&recipient.name),
+            ),
+            (
+                "EMAIL_HTML".into(),
+                htmlescape::encode_attribute(email.as_ref()),
+            ),
+            (
+                "PREFERENCES_URL_HTML".into(),
+                htmlescape::encode_attribute(&preferences_link),
+            ),
+            ("NAME".into(), recipient.name.clone()),
+            ("EMAIL".into(), email.as_ref().to_owned()),
+            ("PREFERENCES_URL".into(), preferences_link),
+        ]);
+
+        if issue.track_opens {
+            attributes.insert(
+                "OPEN_PIXEL_URL".into(),
+                open_pixel_url(base_url, hmac_secret, issue_id, recipient.id),
+            );
+        }
+        for (i, url) in self.links.iter().enumerate() {
+            attributes.insert(
+                link_attribute(i),
+                tracked_link(base_url, hmac_secret, issue_id, recipient.id, url),
             );
         }
+
+        attributes
     }
+}
 
-    delete_task(transaction, issue_id, &email).await?;
+/// Fill in merge tags with the placeholders of the `NAME`, `EMAIL` and
+/// `PREFERENCES_URL` attributes, `suffix`ed.
+fn render_placeholders(content: &str, suffix: &str) -> String {
+    // Issues published before merge tags were introduced were never
+    // validated: if they do not parse, they are sent as they are.
+    match MergeTemplate::parse(content) {
+        Ok(template) => template.render_text(&MergeValues {
+            name: &placeholder(&format!("NAME{}", suffix)),
+            email: &placeholder(&format!("EMAIL{}", suffix)),
+            unsubscribe_url: &placeholder(&format!("PREFERENCES_URL{}", suffix)),
+        }),
+        Err(_) => content.to_owned(),
+    }
+}
 
-    Ok(ExecutionOutcome::TaskCompleted)
+/// Issues wrapped in a layout are full documents: `html` goes at the end of
+/// their body rather than after `</html>`.
+fn append_to_body(mut document: String, html: &str) -> String {
+    match document.rfind("</body>") {
+        Some(i) => document.insert_str(i, html),
+        None => document.push_str(html),
+    }
+    document
+}
+
+/// The subject a recipient gets: the issue title, unless it is being A/B tested.
+///
+/// Recipients outside the test slice get the winning variant, picked the
+/// first time one of them is served.
+async fn get_subject(
+    pool: &PgPool,
+    issue_id: Uuid,
+    issue: &NewsletterIssue,
+    subject_variant: Option<i32>,
+) -> Result<String, anyhow::Error> {
+    let variant = match (
+        subject_variant,
+        issue.winning_variant,
+        &issue.ab_test_metric,
+    ) {
+        (Some(variant), _, _) | (None, Some(variant), _) => variant,
+        (None, None, Some(metric)) => {
+            let metric = TestMetric::try_from(metric.clone()).map_err(anyhow::Error::msg)?;
+            pick_winner(pool, issue_id, metric).await?
+        }
+        (None, None, None) => return Ok(issue.title.clone()),
+    };
+
+    let subject = get_variant_subject(pool, issue_id, variant)
+        .await?
+        .unwrap_or_else(|| issue.title.clone());
+
+    Ok(subject)
 }
 
 type PgTransaction = Transaction<'static, Postgres>;
 
+/// Recipients of an issue sent to together.
+struct Batch {
+    issue_id: Uuid,
+    subject_variant: Option<i32>,
+    emails: Vec<String>,
+}
+
+/// Pick up to `batch_size` pending tasks of the same issue, sharing a subject.
+///
+/// While the A/B test of an issue is running, only the recipients in its
+/// test slice are served: the rest of the queue waits for the test window
+/// to end and for the whole slice to be sent. Tasks being retried wait for
+/// their `execute_after`.
 #[tracing::instrument(skip_all)]
-async fn dequeue_task(
+async fn dequeue_batch(
     pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+    batch_size: u16,
+) -> Result<Option<(PgTransaction, Batch)>, anyhow::Error> {
     let mut transaction = pool.begin().await?;
-    let r = sqlx::query!(
+    let rows = sqlx::query!(
         r#"
-        SELECT newsletter_issue_id, subscriber_email
-        FROM issue_delivery_queue
-        FOR UPDATE
+        WITH next AS (
+            SELECT q.newsletter_issue_id, q.subject_variant
+            FROM issue_delivery_queue q
+            JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+            WHERE
+                i.delivery_state = 'active' AND
+                q.delivered_at IS NULL AND
+                q.failed_at IS NULL AND
+                q.execute_after <= now() AND (
+                    q.subject_variant IS NOT NULL OR
+                    i.ab_test_ends_at IS NULL OR (
+                        i.ab_test_ends_at <= now() AND
+                        NOT EXISTS (
+                            SELECT 1
+                            FROM issue_delivery_queue t
+                            WHERE
+                                t.newsletter_issue_id = q.newsletter_issue_id AND
+                                t.subject_variant IS NOT NULL AND
+                                t.delivered_at IS NULL AND
+                                t.failed_at IS NULL
+                        )
+                    )
+                )
+            FOR UPDATE OF q
+            SKIP LOCKED
+            LIMIT 1
+        )
+        SELECT q.newsletter_issue_id, q.subscriber_email, q.subject_variant
+        FROM issue_delivery_queue q
+        JOIN next ON
+            next.newsletter_issue_id = q.newsletter_issue_id AND
+            next.subject_variant IS NOT DISTINCT FROM q.subject_variant
+        WHERE
+            q.delivered_at IS NULL AND
+            q.failed_at IS NULL AND
+            q.execute_after <= now()
+        FOR UPDATE OF q
         SKIP LOCKED
-        LIMIT 1
-        "#
+        LIMIT $1
+        "#,
+        i64::from(batch_size.max(1))
     )
-    .fetch_optional(&mut transaction)
+    .fetch_all(&mut transaction)
     .await?;
 
-    if let Some(r) = r {
-        Ok(Some((
-            transaction,
-            r.newsletter_issue_id,
-            r.subscriber_email,
-        )))
-    } else {
-        Ok(None)
-    }
+    let batch = match rows.first() {
+        Some(r) => Batch {
+            issue_id: r.newsletter_issue_id,
+            subject_variant: r.subject_variant,
+            emails: rows.into_iter().map(|r| r.subscriber_email).collect(),
+        },
+        None => return Ok(None),
+    };
+
+    Ok(Some((transaction, batch)))
 }
 
+/// Mark tasks as delivered, schedule the failed ones for a retry - backing
+/// off exponentially from a minute - give up on the hopeless ones and
+/// postpone the others.
 #[tracing::instrument(skip_all)]
-async fn delete_task(
+async fn record_outcome(
     mut transaction: PgTransaction,
     issue_id: Uuid,
-    email: &str,
+    outcome: BatchOutcome,
 ) -> Result<(), anyhow::Error> {
     sqlx::query!(
         r#"
-        DELETE FROM issue_delivery_queue
+        UPDATE issue_delivery_queue
+        SET delivered_at = now()
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = ANY($2)
+        "#,
+        issue_id,
+        &outcome.delivered
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_retries = n_retries + 1,
+            execute_after = now() + make_interval(secs => 60 * power(2, n_retries)),
+            failed_at = CASE WHEN n_retries >= $3 THEN now() END
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = ANY($2)
+        "#,
+        issue_id,
+        &outcome.failed,
+        MAX_RETRIES
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET failed_at = now()
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = ANY($2)
+        "#,
+        issue_id,
+        &outcome.given_up
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            failed_at = now(),
+            failure_reason = 'in_doubt'
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = ANY($2)
+        "#,
+        issue_id,
+        &outcome.in_doubt
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET execute_after = now() + make_interval(secs => $3)
         WHERE
             newsletter_issue_id = $1 AND
-            subscriber_email = $2
+            subscriber_email = ANY($2)
         "#,
         issue_id,
-        email
+        &outcome.postponed,
+        outcome.postponed_for.as_secs_f64()
     )
     .execute(&mut transaction)
     .await?;
@@ -153,6 +948,10 @@ struct NewsletterIssue {
     title: String,
     text_content: String,
     html_content: String,
+    ab_test_metric: Option<String>,
+    winning_variant: Option<i32>,
+    track_opens: bool,
+    track_clicks: bool,
 }
 
 #[tracing::instrument(skip_all)]
@@ -160,7 +959,14 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
     let issue = sqlx::query_as!(
         NewsletterIssue,
         r#"
-        SELECT title, text_content, html_content
+        SELECT
+            title,
+            text_content,
+            html_content,
+            ab_test_metric,
+            winning_variant,
+            track_opens,
+            track_clicks
         FROM newsletter_issues
         WHERE
             newsletter_issue_id = $1
@@ -172,3 +978,54 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
 
     Ok(issue)
 }
+
+struct Recipient {
+    id: Uuid,
+    name: String,
+    text_only: bool,
+}
+
+/// The subscribers behind the queued `emails`, by email key.
+///
+/// Only those we may still email are returned: the queue is filled when the
+/// issue is published, they might have unsubscribed or been suppressed since.
+#[tracing::instrument(skip_all)]
+async fn get_recipients(
+    pool: &PgPool,
+    emails: &[(String, SubscriberEmail)],
+) -> Result<HashMap<String, Recipient>, anyhow::Error> {
+    let keys: Vec<String> = emails.iter().map(|(_, email)| email.key()).collect();
+    let rows = sqlx::query!(
+        r#"
+        SELECT id, name, text_only, email_key
+        FROM subscriptions s
+        WHERE
+            email_key = ANY($1) AND
+            status = 'confirmed' AND
+            NOT EXISTS (
+                SELECT 1
+                FROM suppressions
+                WHERE
+                    email_hash = encode(sha256(convert_to(lower(s.email), 'UTF8')), 'hex') AND
+                    reason <> 'erasure'
+            )
+        "#,
+        &keys
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let recipients = rows
+        .into_iter()
+        .map(|r| {
+            let recipient = Recipient {
+                id: r.id,
+                name: r.name,
+                text_only: r.text_only,
+            };
+            (r.email_key, recipient)
+        })
+        .collect();
+
+    Ok(recipients)
+}
@@ -0,0 +1,121 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::MergeTemplate;
+
+/// Marks where the content of the email goes in a layout.
+pub const CONTENT_PLACEHOLDER: &str = "{{ content }}";
+
+/// An HTML document wrapping the content of emails - header, footer, styles...
+///
+/// Besides [`CONTENT_PLACEHOLDER`], layouts may use the same merge tags
+/// as issues, e.g. `{{ unsubscribe_url }}`.
+pub struct EmailLayout {
+    pub layout_id: Uuid,
+    pub name: String,
+    pub html: String,
+    pub is_default: bool,
+}
+
+impl EmailLayout {
+    pub fn validate(html: &str) -> Result<(), String> {
+        if html.matches(CONTENT_PLACEHOLDER).count() != 1 {
+            return Err(format!(
+                "A layout must contain `{}` exactly once",
+                CONTENT_PLACEHOLDER
+            ));
+        }
+        MergeTemplate::parse(&html.replace(CONTENT_PLACEHOLDER, ""))?;
+        inline_css(html)?;
+
+        Ok(())
+    }
+
+    /// Wrap `content` in the layout, inlining its CSS in `style` attributes:
+    /// most email clients ignore `<style>` elements.
+    pub fn wrap(&self, content: &str) -> Result<String, String> {
+        inline_css(&self.html.replacen(CONTENT_PLACEHOLDER, content, 1))
+    }
+}
+
+fn inline_css(html: &str) -> Result<String, String> {
+    css_inline::inline(html).map_err(|e| format!("Failed to inline the layout CSS: {}", e))
+}
+
+#[tracing::instrument(name = "Get all email layouts", skip(pool))]
+pub async fn get_layouts(pool: &PgPool) -> Result<Vec<EmailLayout>, sqlx::Error> {
+    sqlx::query_as!(
+        EmailLayout,
+        r#"SELECT layout_id, name, html, is_default FROM email_layouts ORDER BY created_at, name"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get an email layout", skip(pool))]
+pub async fn get_layout(
+    pool: &PgPool,
+    layout_id: Uuid,
+) -> Result<Option<EmailLayout>, sqlx::Error> {
+    sqlx::query_as!(
+        EmailLayout,
+        r#"SELECT layout_id, name, html, is_default FROM email_layouts WHERE layout_id = $1"#,
+        layout_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get the default email layout", skip(pool))]
+pub async fn get_default_layout(pool: &PgPool) -> Result<Option<EmailLayout>, sqlx::Error> {
+    sqlx::query_as!(
+        EmailLayout,
+        r#"SELECT layout_id, name, html, is_default FROM email_layouts WHERE is_default"#
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Create an email layout", skip(pool, html))]
+pub async fn create_layout(pool: &PgPool, name: &str, html: &str) -> Result<bool, sqlx::Error> {
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO email_layouts (layout_id, name, html, is_default, created_at)
+        VALUES ($1, $2, $3, FALSE, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        name,
+        html
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(n_inserted_rows == 1)
+}
+
+/// Make `layout_id` the default layout, in place of the current one.
+#[tracing::instrument(name = "Set the default email layout", skip(pool))]
+pub async fn set_default_layout(pool: &PgPool, layout_id: Uuid) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+
+    sqlx::query!(r#"UPDATE email_layouts SET is_default = FALSE WHERE is_default"#)
+        .execute(&mut transaction)
+        .await?;
+
+    let n_updated_rows = sqlx::query!(
+        r#"UPDATE email_layouts SET is_default = TRUE WHERE layout_id = $1"#,
+        layout_id
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+
+    if n_updated_rows == 1 {
+        transaction.commit().await?;
+        Ok(true)
+    } else {
+        Ok(false)
+    }
+}
@@ -1,13 +1,25 @@
 extern crate core;
 
+pub mod ab_testing;
+pub mod attachments;
 pub mod authentication;
 pub mod configuration;
+pub mod csv_stream;
 pub mod domain;
 pub mod email_client;
 pub mod idempotency;
 pub mod issue_delivery_worker;
+pub mod layouts;
+pub mod lists;
+pub mod markdown;
+pub mod operations;
+pub mod rate_limiter;
 pub mod routes;
 pub mod session_state;
+pub mod shutdown;
+pub mod signature;
 pub mod startup;
+pub mod suppression;
 pub mod telemetry;
+pub mod tracking;
 pub mod utils;
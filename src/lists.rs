@@ -0,0 +1,101 @@
+use std::fmt::Write;
+
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// The list subscribers join and issues are sent to when no list is specified.
+pub const DEFAULT_LIST_SLUG: &str = "newsletter";
+
+pub struct MailingList {
+    pub list_id: Uuid,
+    pub slug: String,
+    pub name: String,
+}
+
+impl MailingList {
+    /// Render `lists` as the `<option>`s of a list selector, keyed by slug.
+    pub fn options_html(lists: &[MailingList], selected_slug: &str) -> String {
+        let mut options_html = String::new();
+
+        for l in lists {
+            let selected = if l.slug == selected_slug {
+                " selected"
+            } else {
+                ""
+            };
+            writeln!(
+                options_html,
+                r#"<option value="{}"{}>{}</option>"#,
+                htmlescape::encode_attribute(&l.slug),
+                selected,
+                htmlescape::encode_minimal(&l.name)
+            )
+            .unwrap();
+        }
+
+        options_html
+    }
+}
+
+#[tracing::instrument(name = "Get all mailing lists", skip(pool))]
+pub async fn get_lists(pool: &PgPool) -> Result<Vec<MailingList>, sqlx::Error> {
+    sqlx::query_as!(
+        MailingList,
+        r#"SELECT list_id, slug, name FROM lists ORDER BY created_at, name"#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Get a mailing list id from its slug", skip(pool))]
+pub async fn get_list_id(pool: &PgPool, slug: &str) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(r#"SELECT list_id FROM lists WHERE slug = $1"#, slug)
+        .fetch_optional(pool)
+        .await?;
+
+    Ok(row.map(|r| r.list_id))
+}
+
+#[tracing::instrument(name = "Create a mailing list", skip(pool))]
+pub async fn create_list(pool: &PgPool, slug: &str, name: &str) -> Result<bool, sqlx::Error> {
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, slug, name, created_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        slug,
+        name
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(n_inserted_rows == 1)
+}
+
+#[tracing::instrument(
+    name = "Add subscribers to a mailing list",
+    skip(transaction, subscriber_ids)
+)]
+pub async fn add_to_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (list_id, subscriber_id, subscribed_at)
+        SELECT $1, subscriber_id, now()
+        FROM UNNEST($2::uuid[]) AS s(subscriber_id)
+        ON CONFLICT DO NOTHING
+        "#,
+        list_id,
+        subscriber_ids
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
@@ -1,36 +1,288 @@
 use std::fmt::{Debug, Display};
+use std::future::Future;
+use std::time::Duration;
 
-use tokio::task::JoinError;
+use anyhow::Context;
+use chrono::Utc;
+use clap::{Parser, Subcommand};
+use futures_util::future::join_all;
+use secrecy::Secret;
+use tokio::task::{JoinError, JoinHandle};
+use uuid::Uuid;
 use zero2prod::configuration::get_configuration;
-use zero2prod::idempotency;
+use zero2prod::domain::SubscriberEmail;
+use zero2prod::idempotency::{self, delete_expired_idempotency_keys};
 use zero2prod::issue_delivery_worker::run_worker_until_stopped;
-use zero2prod::startup::Application;
+use zero2prod::operations;
+use zero2prod::shutdown::{shutdown_signal, Shutdown};
+use zero2prod::startup::{get_connection_pool, Application};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
+#[derive(Parser)]
+#[clap(about = "Newsletter delivery service")]
+struct Cli {
+    /// Defaults to `all`.
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+/// Each part of the service can run in a process of its own, to be scaled
+/// independently. Processes sending emails enforce the rate limits of the
+/// provider on their own, with the share of them set in
+/// `email_client.sending_shares`.
+#[derive(Subcommand)]
+enum Command {
+    /// Serve the API.
+    Serve,
+    /// Run the issue delivery workers.
+    Deliver,
+    /// Periodically delete expired idempotency keys.
+    Sweep,
+    /// Serve the API and run all the workers, in a single process.
+    All,
+    /// Operational tasks there is no UI for.
+    Admin {
+        #[clap(subcommand)]
+        command: AdminCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum AdminCommand {
+    /// Run the database migrations.
+    Migrate,
+    /// Create an admin user, or reset their password. The password is read
+    /// from standard input.
+    SetUser {
+        #[clap(value_parser)]
+        username: String,
+    },
+    /// List the deliveries given up on after repeated failures.
+    FailedDeliveries,
+    /// Put failed deliveries back in the queue.
+    Requeue {
+        /// Only requeue the deliveries of this issue.
+        #[clap(long, value_parser)]
+        issue: Option<Uuid>,
+    },
+    /// Show how many emails are left to deliver for each issue.
+    QueueDepth,
+    /// Delete all the idempotency keys, without waiting for them to expire.
+    ExpireIdempotencyKeys,
+    /// Confirm a subscriber, without them following the confirmation link.
+    Confirm {
+        #[clap(value_parser)]
+        email: String,
+    },
+    /// Unsubscribe a subscriber from everything.
+    Unsubscribe {
+        #[clap(value_parser)]
+        email: String,
+    },
+}
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let command = match Cli::parse().command.unwrap_or(Command::All) {
+        Command::Admin { command } => return run_admin_command(command).await,
+        command => command,
+    };
+
     let subscriber = get_subscriber("zero2prod".into(), "info".into(), std::io::stdout);
     init_subscriber(subscriber);
 
     let configuration = get_configuration().expect("Failed to read configuration");
 
-    let application = Application::build(configuration.clone()).await?;
+    let shutdown_timeout = Duration::from_secs(configuration.application.shutdown_timeout_seconds);
 
-    let application_task = tokio::spawn(application.run_until_stopped());
-    let delivery_worker = tokio::spawn(run_worker_until_stopped(configuration.clone()));
-    let idempotency_worker = tokio::spawn(idempotency::run_worker_until_stopped(configuration));
+    // A single client, for the API and the delivery workers to share its
+    // rate limit when they run in the same process
+    let shares = &configuration.email_client.sending_shares;
+    let share_percent = match command {
+        Command::Serve => shares.serve_percent,
+        Command::Deliver => shares.deliver_percent,
+        _ => 100,
+    };
+    let email_client = configuration
+        .email_client
+        .clone()
+        .client_with_share(share_percent);
+
+    let shutdown = Shutdown::new();
+    let mut server = None;
+    let mut tasks = vec![];
+    if matches!(command, Command::Serve | Command::All) {
+        let application = Application::build(configuration.clone(), email_client.clone()).await?;
+        server = Some(application.handle());
+        tasks.push(spawn_supervised(
+            "API",
+            application.run_until_stopped(),
+            &shutdown,
+        ));
+    }
+    if matches!(command, Command::Deliver | Command::All) {
+        tasks.push(spawn_supervised(
+            "Delivery workers",
+            run_worker_until_stopped(configuration.clone(), email_client, shutdown.clone()),
+            &shutdown,
+        ));
+    }
+    if matches!(command, Command::Sweep | Command::All) {
+        tasks.push(spawn_supervised(
+            "Background worker",
+            idempotency::run_worker_until_stopped(configuration, shutdown.clone()),
+            &shutdown,
+        ));
+    }
 
     tokio::select! {
-        o = application_task => report_exit("API", o),
-        o = delivery_worker => report_exit("Background worker", o),
-        o = idempotency_worker => report_exit("Background worker", o)
+        _ = shutdown_signal() => tracing::info!("Shutting down"),
+        _ = shutdown.triggered() => {}
+    }
+    shutdown.trigger();
+
+    // Stop accepting connections, then wait for in-flight requests and
+    // deliveries to complete
+    let stopped = async {
+        if let Some(server) = server {
+            server.stop(true).await;
+        }
+        join_all(tasks).await;
+    };
+    if tokio::time::timeout(shutdown_timeout, stopped)
+        .await
+        .is_err()
+    {
+        tracing::warn!(
+            "Shutdown did not complete within {} seconds, exiting anyway",
+            shutdown_timeout.as_secs()
+        );
     }
 
+    // Flush the spans still buffered
     opentelemetry::global::shutdown_tracer_provider();
 
     Ok(())
 }
 
+async fn run_admin_command(command: AdminCommand) -> anyhow::Result<()> {
+    // Results are printed on stdout, keep it for them
+    let subscriber = get_subscriber("zero2prod".into(), "warn".into(), std::io::stderr);
+    init_subscriber(subscriber);
+
+    let configuration = get_configuration().expect("Failed to read configuration");
+    let pool = get_connection_pool(&configuration.database);
+
+    let outcome = match command {
+        AdminCommand::Migrate => operations::run_migrations(&pool).await,
+        AdminCommand::SetUser { username } => set_admin_user(&pool, &username).await,
+        AdminCommand::FailedDeliveries => print_failed_deliveries(&pool).await,
+        AdminCommand::Requeue { issue } => requeue_failed_deliveries(&pool, issue).await,
+        AdminCommand::QueueDepth => print_queue_depth(&pool).await,
+        AdminCommand::ExpireIdempotencyKeys => delete_expired_idempotency_keys(&pool, Utc::now())
+            .await
+            .context("Failed to delete idempotency keys"),
+        AdminCommand::Confirm { email } => confirm(&pool, email).await,
+        AdminCommand::Unsubscribe { email } => unsubscribe(&pool, email).await,
+    };
+
+    opentelemetry::global::shutdown_tracer_provider();
+
+    outcome
+}
+
+async fn set_admin_user(pool: &sqlx::PgPool, username: &str) -> anyhow::Result<()> {
+    let mut password = String::new();
+    std::io::stdin()
+        .read_line(&mut password)
+        .context("Failed to read the password")?;
+    let password = Secret::new(password.trim_end_matches(&['\r', '\n'][..]).to_string());
+
+    let user_id = operations::set_admin_user(pool, username, password).await?;
+    println!("Password set for {} ({})", username, user_id);
+
+    Ok(())
+}
+
+async fn print_failed_deliveries(pool: &sqlx::PgPool) -> anyhow::Result<()> {
+    for delivery in operations::get_failed_deliveries(pool).await? {
+        println!(
+            "{}\t{}\t{}\t{} retries\t{}\t{}",
+            delivery.failed_at.to_rfc3339(),
+            delivery.newsletter_issue_id,
+            delivery.subscriber_email,
+            delivery.n_retries,
+            delivery.failure_reason.as_deref().unwrap_or("-"),
+            delivery.title
+        );
+    }
+
+    Ok(())
+}
+
+async fn requeue_failed_deliveries(
+    pool: &sqlx::PgPool,
+    newsletter_issue_id: Option<Uuid>,
+) -> anyhow::Result<()> {
+    let n_requeued = operations::requeue_failed_deliveries(pool, newsletter_issue_id).await?;
+    println!("Requeued {} deliveries", n_requeued);
+
+    Ok(())
+}
+
+async fn confirm(pool: &sqlx::PgPool, email: String) -> anyhow::Result<()> {
+    let email = SubscriberEmail::parse(email).map_err(anyhow::Error::msg)?;
+    if !operations::confirm_address(pool, &email).await? {
+        anyhow::bail!("{} has not subscribed", email);
+    }
+    println!("Confirmed {}", email);
+
+    Ok(())
+}
+
+async fn unsubscribe(pool: &sqlx::PgPool, email: String) -> anyhow::Result<()> {
+    let email = SubscriberEmail::parse(email).map_err(anyhow::Error::msg)?;
+    if !operations::unsubscribe_address(pool, &email).await? {
+        anyhow::bail!("{} has not subscribed", email);
+    }
+    println!("Unsubscribed {}", email);
+
+    Ok(())
+}
+
+async fn print_queue_depth(pool: &sqlx::PgPool) -> anyhow::Result<()> {
+    for issue in operations::get_queue_depth(pool).await? {
+        println!(
+            "{}\t{}\t{} pending ({} retrying)\t{} failed\t{}",
+            issue.newsletter_issue_id,
+            issue.delivery_state,
+            issue.n_pending,
+            issue.n_retrying,
+            issue.n_failed,
+            issue.title
+        );
+    }
+
+    Ok(())
+}
+
+/// Run `task` in the background. When it exits, for whatever reason, all the
+/// other tasks are shut down as well.
+fn spawn_supervised<E>(
+    task_name: &'static str,
+    task: impl Future<Output = Result<(), E>> + Send + 'static,
+    shutdown: &Shutdown,
+) -> JoinHandle<()>
+where
+    E: Debug + Display + Send + 'static,
+{
+    let shutdown = shutdown.clone();
+    tokio::spawn(async move {
+        report_exit(task_name, tokio::spawn(task).await);
+        shutdown.trigger();
+    })
+}
+
 fn report_exit(task_name: &str, outcome: Result<Result<(), impl Debug + Display>, JoinError>) {
     match outcome {
         Ok(Ok(())) => {
@@ -0,0 +1,175 @@
+use pulldown_cmark::{html, Event, Options, Parser, Tag};
+use std::fmt::Write;
+
+/// The two bodies of an email, derived from a single Markdown document.
+pub struct RenderedMarkdown {
+    pub html: String,
+    pub text: String,
+}
+
+pub fn render(markdown: &str) -> RenderedMarkdown {
+    RenderedMarkdown {
+        html: to_html(markdown),
+        text: to_text(markdown),
+    }
+}
+
+/// Render `markdown` as HTML, stripping anything unsafe (scripts, event handlers, ...)
+/// that raw HTML embedded in the document might contain.
+fn to_html(markdown: &str) -> String {
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, Parser::new_ext(markdown, options()));
+
+    // Link destinations are percent-encoded: undo it for merge tags,
+    // so that `[unsubscribe]({{unsubscribe_url}})` keeps working.
+    let unsafe_html = unsafe_html.replace("%7B%7B", "{{").replace("%7D%7D", "}}");
+
+    // `cid:` URLs point at the inline images attached to the email
+    ammonia::Builder::default()
+        .add_url_schemes(&["cid"])
+        .clean(&unsafe_html)
+        .to_string()
+}
+
+/// Render `markdown` as plain text.
+///
+/// Formatting is dropped, links are numbered and listed at the end of the text:
+///
+/// ```text
+/// Read the announcement [1].
+///
+/// [1] https://example.com/announcement
+/// ```
+fn to_text(markdown: &str) -> String {
+    let mut text = String::new();
+    let mut links: Vec<String> = Vec::new();
+    // The next item number of each (nested) list, `None` for bullet lists
+    let mut lists: Vec<Option<u64>> = Vec::new();
+
+    for event in Parser::new_ext(markdown, options()) {
+        match event {
+            Event::Start(Tag::List(first_number)) => {
+                if !lists.is_empty() {
+                    text.push('\n');
+                }
+                lists.push(first_number);
+            }
+            Event::End(Tag::List(_)) => {
+                lists.pop();
+                if lists.is_empty() {
+                    text.push('\n');
+                }
+            }
+            Event::Start(Tag::Item) => {
+                let indent = "  ".repeat(lists.len().saturating_sub(1));
+                match lists.last_mut() {
+                    Some(Some(n)) => {
+                        write!(text, "{}{}. ", indent, n).unwrap();
+                        *n += 1;
+                    }
+                    _ => write!(text, "{}- ", indent).unwrap(),
+                }
+            }
+            Event::End(Tag::Item) if !text.ends_with('\n') => text.push('\n'),
+            Event::End(Tag::Link(_, destination, _) | Tag::Image(_, destination, _)) => {
+                let destination = destination.to_string();
+                let n = match links.iter().position(|l| *l == destination) {
+                    Some(i) => i + 1,
+                    None => {
+                        links.push(destination);
+                        links.len()
+                    }
+                };
+                write!(text, " [{}]", n).unwrap();
+            }
+            Event::End(Tag::Paragraph | Tag::Heading(..) | Tag::CodeBlock(_)) => {
+                if lists.is_empty() {
+                    text.push_str("\n\n");
+                } else if !text.ends_with('\n') {
+                    text.push('\n');
+                }
+            }
+            Event::Text(s) | Event::Code(s) => text.push_str(&s),
+            Event::SoftBreak | Event::HardBreak => text.push('\n'),
+            Event::Rule => text.push_str("----------\n\n"),
+            _ => {}
+        }
+    }
+
+    let mut text = text.trim_end().to_owned();
+
+    if !links.is_empty() {
+        text.push('\n');
+        for (i, link) in links.iter().enumerate() {
+            write!(text, "\n[{}] {}", i + 1, link).unwrap();
+        }
+    }
+
+    text
+}
+
+fn options() -> Options {
+    Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render;
+
+    #[test]
+    fn markdown_is_rendered_as_html() {
+        let rendered = render("# Hello\n\nSome *emphasis*.");
+        assert_eq!(
+            rendered.html,
+            "<h1>Hello</h1>\n<p>Some <em>emphasis</em>.</p>\n"
+        );
+    }
+
+    #[test]
+    fn unsafe_html_is_stripped() {
+        let rendered = render(
+            "<script>alert('hi')</script>\n\n<a href=\"javascript:alert('hi')\" onclick=\"alert('hi')\">Click</a>",
+        );
+        assert!(!rendered.html.contains("script"));
+        assert!(!rendered.html.contains("onclick"));
+        assert!(!rendered.html.contains("javascript"));
+    }
+
+    #[test]
+    fn inline_images_are_kept() {
+        let rendered = render("![Logo](cid:logo.png)\n\n<img src=\"cid:banner.png\">");
+        assert!(rendered.html.contains(r#"src="cid:logo.png""#));
+        assert!(rendered.html.contains(r#"src="cid:banner.png""#));
+    }
+
+    #[test]
+    fn links_are_listed_at_the_end_of_the_text() {
+        let rendered = render(
+            "Read [the announcement](https://example.com/a), \
+            [again](https://example.com/a) or [the docs](https://example.com/docs).",
+        );
+        assert_eq!(
+            rendered.text,
+            "Read the announcement [1], again [1] or the docs [2].\n\n\
+            [1] https://example.com/a\n\
+            [2] https://example.com/docs"
+        );
+    }
+
+    #[test]
+    fn lists_are_kept_readable_in_the_text() {
+        let rendered = render("Steps:\n\n1. First\n2. Second\n\n- a\n- b\n\nDone");
+        assert_eq!(
+            rendered.text,
+            "Steps:\n\n1. First\n2. Second\n\n- a\n- b\n\nDone"
+        );
+    }
+
+    #[test]
+    fn merge_tags_survive_in_link_destinations() {
+        let rendered = render("[Unsubscribe]({{unsubscribe_url}}), {{ name }}");
+        assert!(rendered.html.contains(r#"href="{{unsubscribe_url}}""#));
+        assert!(rendered.html.contains("{{ name }}"));
+        assert!(rendered.text.ends_with("[1] {{unsubscribe_url}}"));
+    }
+}
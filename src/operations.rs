@@ -0,0 +1,270 @@
+//! Operational tasks there is no UI for, run with `zero2prod admin`.
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    authentication::compute_password_hash,
+    domain::SubscriberEmail,
+    issue_delivery_worker::NEW_TASKS_CHANNEL,
+    routes::{confirm_subscriber, unsubscribe},
+    telemetry::spawn_blocking_with_tracing,
+};
+
+pub async fn run_migrations(pool: &PgPool) -> Result<(), anyhow::Error> {
+    sqlx::migrate!("./migrations")
+        .run(pool)
+        .await
+        .context("Failed to run the database migrations")
+}
+
+/// Create an admin user, or reset their password if they already exist.
+#[tracing::instrument(name = "Set admin user", skip(password, pool))]
+pub async fn set_admin_user(
+    pool: &PgPool,
+    username: &str,
+    password: Secret<String>,
+) -> Result<Uuid, anyhow::Error> {
+    // Same rules as when changing the password from the admin panel
+    let password_len = password.expose_secret().len();
+    if password_len <= 12 {
+        anyhow::bail!("The password is too short.");
+    }
+    if password_len >= 128 {
+        anyhow::bail!("The password is too long.");
+    }
+
+    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
+        .await?
+        .context("Failed to hash password")?;
+
+    let user = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (username) DO UPDATE
+        SET password_hash = EXCLUDED.password_hash
+        RETURNING user_id
+        "#,
+        Uuid::new_v4(),
+        username,
+        password_hash.expose_secret()
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to store the admin user")?;
+
+    Ok(user.user_id)
+}
+
+/// A delivery the workers gave up on after too many failures.
+pub struct FailedDelivery {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub subscriber_email: String,
+    pub n_retries: i32,
+    pub failed_at: DateTime<Utc>,
+    /// `in_doubt` if the email may have gone out already.
+    pub failure_reason: Option<String>,
+}
+
+pub async fn get_failed_deliveries(pool: &PgPool) -> Result<Vec<FailedDelivery>, anyhow::Error> {
+    let deliveries = sqlx::query_as!(
+        FailedDelivery,
+        r#"
+        SELECT
+            q.newsletter_issue_id,
+            n.title,
+            q.subscriber_email,
+            q.n_retries,
+            q.failed_at AS "failed_at!",
+            q.failure_reason
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues n USING (newsletter_issue_id)
+        WHERE q.failed_at IS NOT NULL
+        ORDER BY q.failed_at
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve failed deliveries")?;
+
+    Ok(deliveries)
+}
+
+/// Put failed deliveries - of a single issue, or all of them - back in the
+/// queue, with a fresh retry budget. Returns how many were requeued.
+///
+/// Cancelled issues are left alone. Deliveries given up on because they may
+/// have gone out already are sent again, even though it may be a duplicate.
+#[tracing::instrument(name = "Requeue failed deliveries", skip(pool))]
+pub async fn requeue_failed_deliveries(
+    pool: &PgPool,
+    newsletter_issue_id: Option<Uuid>,
+) -> Result<u64, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    // The workers skip recipients with an attempt in flight: settle the
+    // attempts that are in doubt so that the requeued sends go out.
+    sqlx::query!(
+        r#"
+        UPDATE deliveries d
+        SET error = 'In doubt, requeued by an admin'
+        FROM issue_delivery_queue q
+        WHERE
+            q.failure_reason = 'in_doubt' AND
+            ($1::uuid IS NULL OR q.newsletter_issue_id = $1) AND
+            d.newsletter_issue_id = q.newsletter_issue_id AND
+            d.subscriber_email = q.subscriber_email AND
+            d.sent_at IS NULL AND
+            d.error IS NULL
+        "#,
+        newsletter_issue_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to settle the attempts in doubt")?;
+
+    let n_requeued = sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            failed_at = NULL,
+            failure_reason = NULL,
+            n_retries = 0,
+            execute_after = now()
+        WHERE
+            failed_at IS NOT NULL AND
+            ($1::uuid IS NULL OR newsletter_issue_id = $1) AND
+            newsletter_issue_id IN (
+                SELECT newsletter_issue_id
+                FROM newsletter_issues
+                WHERE delivery_state <> 'cancelled'
+            )
+        "#,
+        newsletter_issue_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to requeue failed deliveries")?
+    .rows_affected();
+
+    if n_requeued > 0 {
+        sqlx::query("SELECT pg_notify($1, '')")
+            .bind(NEW_TASKS_CHANNEL)
+            .execute(&mut transaction)
+            .await
+            .context("Failed to wake the delivery workers up")?;
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to requeue failed deliveries")?;
+
+    Ok(n_requeued)
+}
+
+/// Undelivered emails of an issue.
+pub struct QueueDepth {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    /// `active`, `paused` or `cancelled`.
+    pub delivery_state: String,
+    /// Waiting to be sent, including those being retried.
+    pub n_pending: i64,
+    pub n_retrying: i64,
+    pub n_failed: i64,
+}
+
+/// Issues with undelivered emails, oldest first.
+pub async fn get_queue_depth(pool: &PgPool) -> Result<Vec<QueueDepth>, anyhow::Error> {
+    let depth = sqlx::query_as!(
+        QueueDepth,
+        r#"
+        SELECT
+            n.newsletter_issue_id,
+            n.title,
+            n.delivery_state,
+            COUNT(*) FILTER (WHERE q.failed_at IS NULL) AS "n_pending!",
+            COUNT(*) FILTER (WHERE q.failed_at IS NULL AND q.n_retries > 0) AS "n_retrying!",
+            COUNT(q.failed_at) AS "n_failed!"
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues n USING (newsletter_issue_id)
+        WHERE q.delivered_at IS NULL
+        GROUP BY n.newsletter_issue_id
+        ORDER BY n.published_at
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the delivery queue depth")?;
+
+    Ok(depth)
+}
+
+/// Confirm a subscriber without them following the confirmation link.
+///
+/// Returns `false` if nobody subscribed with `email`.
+#[tracing::instrument(name = "Confirm subscriber", skip(pool))]
+pub async fn confirm_address(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<bool, anyhow::Error> {
+    let subscriber_id = match get_subscriber_id(pool, email).await? {
+        Some(subscriber_id) => subscriber_id,
+        None => return Ok(false),
+    };
+
+    confirm_subscriber(pool, subscriber_id)
+        .await
+        .context("Failed to confirm a subscriber")?;
+
+    Ok(true)
+}
+
+/// Unsubscribe from everything, as from the preference centre.
+///
+/// Returns `false` if nobody subscribed with `email`.
+#[tracing::instrument(name = "Unsubscribe subscriber", skip(pool))]
+pub async fn unsubscribe_address(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<bool, anyhow::Error> {
+    let subscriber_id = match get_subscriber_id(pool, email).await? {
+        Some(subscriber_id) => subscriber_id,
+        None => return Ok(false),
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    unsubscribe(&mut transaction, subscriber_id).await?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber")?;
+
+    Ok(true)
+}
+
+async fn get_subscriber_id(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let subscriber = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE email_key = $1"#,
+        email.key()
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look the subscriber up")?;
+
+    Ok(subscriber.map(|s| s.id))
+}
@@ -0,0 +1,233 @@
+use chrono::{DateTime, Duration as ChronoDuration, NaiveDate, Utc};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Keep outbound emails within the limits of our provider plan: a number of
+/// messages per second - with bursts of up to a second worth of them - and
+/// per day. Days are UTC days, the quota resets at midnight UTC.
+///
+/// A single limiter is shared by everything sending through an `EmailClient`.
+/// Its state is kept in memory: the delivery workers carry the daily count
+/// over a restart with `count_sent_today`, and other processes have limiters
+/// of their own - see `SendingShares`.
+pub struct RateLimiter(Mutex<Bucket>);
+
+/// The daily quota has been used up.
+#[derive(thiserror::Error, Debug)]
+#[error("The daily sending quota has been used up")]
+pub struct QuotaExceeded {
+    /// How long until the quota resets, at midnight UTC.
+    pub resets_in: Duration,
+}
+
+impl RateLimiter {
+    pub fn new(messages_per_second: u32, daily_quota: u32) -> Self {
+        let rate = f64::from(messages_per_second.max(1));
+        Self(Mutex::new(Bucket {
+            rate,
+            tokens: rate,
+            refilled_at: Instant::now(),
+            daily_quota,
+            day: Utc::now().date().naive_utc(),
+            n_sent_today: 0,
+            paused_until: None,
+        }))
+    }
+
+    /// Wait until `n` messages can go out.
+    pub async fn acquire(&self, n: u32) -> Result<(), QuotaExceeded> {
+        loop {
+            let reservation = self
+                .0
+                .lock()
+                .unwrap()
+                .reserve(n, Instant::now(), Utc::now());
+            match reservation {
+                Reservation::Granted => return Ok(()),
+                Reservation::Wait(wait) => tokio::time::sleep(wait).await,
+                Reservation::QuotaExceeded(resets_in) => return Err(QuotaExceeded { resets_in }),
+            }
+        }
+    }
+
+    /// Count `n` messages towards today's quota, sent before the limiter was
+    /// created - e.g. before a restart.
+    pub fn count_sent_today(&self, n: u32) {
+        self.0.lock().unwrap().count_sent(n, Utc::now());
+    }
+
+    /// Hold off all sending for `duration` - e.g. when the provider throttles us.
+    pub fn pause_for(&self, duration: Duration) {
+        let until = Instant::now() + duration;
+        let mut bucket = self.0.lock().unwrap();
+        // `None` sorts first: sending is not on hold yet
+        if bucket.paused_until < Some(until) {
+            bucket.paused_until = Some(until);
+        }
+    }
+}
+
+struct Bucket {
+    /// Tokens added per second, one per message. Also the size of the bucket.
+    rate: f64,
+    tokens: f64,
+    refilled_at: Instant,
+    daily_quota: u32,
+    day: NaiveDate,
+    n_sent_today: u32,
+    paused_until: Option<Instant>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Reservation {
+    Granted,
+    Wait(Duration),
+    QuotaExceeded(Duration),
+}
+
+impl Bucket {
+    fn start_day(&mut self, utc_now: DateTime<Utc>) {
+        let today = utc_now.date().naive_utc();
+        if today != self.day {
+            self.day = today;
+            self.n_sent_today = 0;
+        }
+    }
+
+    fn count_sent(&mut self, n: u32, utc_now: DateTime<Utc>) {
+        self.start_day(utc_now);
+        self.n_sent_today = self.n_sent_today.saturating_add(n);
+    }
+
+    fn reserve(&mut self, n: u32, now: Instant, utc_now: DateTime<Utc>) -> Reservation {
+        self.start_day(utc_now);
+        let today = self.day;
+        if self.n_sent_today.saturating_add(n) > self.daily_quota {
+            let midnight = (today + ChronoDuration::days(1)).and_hms(0, 0, 0);
+            let resets_in = (midnight - utc_now.naive_utc())
+                .to_std()
+                .unwrap_or_default();
+            return Reservation::QuotaExceeded(resets_in);
+        }
+
+        if let Some(paused_until) = self.paused_until {
+            if paused_until > now {
+                return Reservation::Wait(paused_until - now);
+            }
+            self.paused_until = None;
+        }
+
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.rate);
+        self.refilled_at = now;
+
+        // Batches larger than the bucket go out once it is full, leaving it
+        // in debt: the average rate is kept either way.
+        let needed = f64::from(n).min(self.rate);
+        if self.tokens < needed {
+            return Reservation::Wait(Duration::from_secs_f64((needed - self.tokens) / self.rate));
+        }
+
+        self.tokens -= f64::from(n);
+        self.n_sent_today += n;
+        Reservation::Granted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bucket, Reservation};
+    use chrono::{TimeZone, Utc};
+    use std::time::{Duration, Instant};
+
+    fn bucket(rate: f64, daily_quota: u32, now: Instant) -> Bucket {
+        Bucket {
+            rate,
+            tokens: rate,
+            refilled_at: now,
+            daily_quota,
+            day: Utc.ymd(2023, 9, 1).naive_utc(),
+            n_sent_today: 0,
+            paused_until: None,
+        }
+    }
+
+    #[test]
+    fn a_burst_of_up_to_one_second_worth_of_messages_goes_out_at_once() {
+        let now = Instant::now();
+        let utc_now = Utc.ymd(2023, 9, 1).and_hms(12, 0, 0);
+        let mut bucket = bucket(10., 1000, now);
+
+        assert_eq!(bucket.reserve(10, now, utc_now), Reservation::Granted);
+        assert_eq!(
+            bucket.reserve(5, now, utc_now),
+            Reservation::Wait(Duration::from_millis(500))
+        );
+        assert_eq!(
+            bucket.reserve(5, now + Duration::from_millis(500), utc_now),
+            Reservation::Granted
+        );
+    }
+
+    #[test]
+    fn batches_larger_than_the_bucket_leave_it_in_debt() {
+        let now = Instant::now();
+        let utc_now = Utc.ymd(2023, 9, 1).and_hms(12, 0, 0);
+        let mut bucket = bucket(4., 1000, now);
+
+        assert_eq!(bucket.reserve(12, now, utc_now), Reservation::Granted);
+        assert_eq!(
+            bucket.reserve(1, now, utc_now),
+            Reservation::Wait(Duration::from_millis(2250))
+        );
+    }
+
+    #[test]
+    fn the_daily_quota_resets_at_midnight() {
+        let now = Instant::now();
+        let utc_now = Utc.ymd(2023, 9, 1).and_hms(23, 0, 0);
+        let mut bucket = bucket(10., 10, now);
+
+        assert_eq!(bucket.reserve(10, now, utc_now), Reservation::Granted);
+        assert_eq!(
+            bucket.reserve(1, now, utc_now),
+            Reservation::QuotaExceeded(Duration::from_secs(3600))
+        );
+
+        let later = now + Duration::from_secs(3600);
+        let tomorrow = Utc.ymd(2023, 9, 2).and_hms(0, 0, 0);
+        assert_eq!(bucket.reserve(1, later, tomorrow), Reservation::Granted);
+    }
+
+    #[test]
+    fn messages_sent_before_a_restart_count_towards_the_quota() {
+        let now = Instant::now();
+        let utc_now = Utc.ymd(2023, 9, 1).and_hms(12, 0, 0);
+        let mut bucket = bucket(10., 10, now);
+
+        bucket.count_sent(8, utc_now);
+
+        assert_eq!(bucket.reserve(2, now, utc_now), Reservation::Granted);
+        assert_eq!(
+            bucket.reserve(1, now, utc_now),
+            Reservation::QuotaExceeded(Duration::from_secs(12 * 3600))
+        );
+    }
+
+    #[test]
+    fn nothing_goes_out_while_paused() {
+        let now = Instant::now();
+        let utc_now = Utc.ymd(2023, 9, 1).and_hms(12, 0, 0);
+        let mut bucket = bucket(10., 1000, now);
+        bucket.paused_until = Some(now + Duration::from_secs(30));
+
+        assert_eq!(
+            bucket.reserve(1, now, utc_now),
+            Reservation::Wait(Duration::from_secs(30))
+        );
+        assert_eq!(
+            bucket.reserve(1, now + Duration::from_secs(30), utc_now),
+            Reservation::Granted
+        );
+    }
+}
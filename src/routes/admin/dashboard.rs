@@ -33,6 +33,12 @@ pub async fn admin_dashboard(
             </form>
         </li>
         <li><a href="/admin/newsletters">Send a newsletter issue</a></li>
+        <li><a href="/admin/issues">Review sent issues</a></li>
+        <li><a href="/admin/lists">Manage mailing lists</a></li>
+        <li><a href="/admin/layouts">Manage email layouts</a></li>
+        <li><a href="/admin/subscribers/import">Import subscribers</a></li>
+        <li><a href="/admin/subscribers/tags">Tag subscribers</a></li>
+        <li><a href="/admin/suppressions">Manage the suppression list</a></li>
     </ol>
 </body>
 </html>"#
@@ -0,0 +1,228 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    issue_delivery_worker::NEW_TASKS_CHANNEL,
+    utils::{e500, see_other},
+};
+
+/// Whether the workers may deliver an issue. Only `Active` issues are
+/// picked from the queue.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DeliveryState {
+    Active,
+    Paused,
+    /// What was left in the queue has been moved to `cancelled_deliveries`.
+    Cancelled,
+}
+
+impl DeliveryState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryState::Active => "active",
+            DeliveryState::Paused => "paused",
+            DeliveryState::Cancelled => "cancelled",
+        }
+    }
+}
+
+impl TryFrom<String> for DeliveryState {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "active" => Ok(Self::Active),
+            "paused" => Ok(Self::Paused),
+            "cancelled" => Ok(Self::Cancelled),
+            other => Err(format!("{} is not a supported delivery state", other)),
+        }
+    }
+}
+
+#[tracing::instrument(name = "Pause the delivery of a newsletter issue", skip(pool))]
+pub async fn pause_delivery(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    if set_delivery_state(
+        &mut transaction,
+        issue_id,
+        DeliveryState::Active,
+        DeliveryState::Paused,
+    )
+    .await
+    .map_err(e500)?
+    {
+        FlashMessage::info("The delivery has been paused.").send();
+    } else {
+        FlashMessage::error("Only deliveries in progress can be paused.").send();
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to pause a delivery")
+        .map_err(e500)?;
+
+    Ok(see_other(&format!("/admin/issues/{}", issue_id)))
+}
+
+#[tracing::instrument(name = "Resume the delivery of a newsletter issue", skip(pool))]
+pub async fn resume_delivery(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+    if set_delivery_state(
+        &mut transaction,
+        issue_id,
+        DeliveryState::Paused,
+        DeliveryState::Active,
+    )
+    .await
+    .map_err(e500)?
+    {
+        // Idle workers would otherwise only notice on their next poll
+        sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(NEW_TASKS_CHANNEL)
+            .bind(issue_id.to_string())
+            .execute(&mut transaction)
+            .await
+            .context("Failed to wake the delivery workers up")
+            .map_err(e500)?;
+        FlashMessage::info("The delivery has been resumed.").send();
+    } else {
+        FlashMessage::error("Only paused deliveries can be resumed.").send();
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to resume a delivery")
+        .map_err(e500)?;
+
+    Ok(see_other(&format!("/admin/issues/{}", issue_id)))
+}
+
+#[tracing::instrument(name = "Cancel the delivery of a newsletter issue", skip(pool))]
+pub async fn cancel_delivery(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+
+    match cancel(&pool, issue_id).await.map_err(e500)? {
+        Some(n_cancelled) => FlashMessage::info(format!(
+            "The delivery has been cancelled, {} email(s) will not be sent.",
+            n_cancelled
+        ))
+        .send(),
+        None => FlashMessage::error("The delivery has already been cancelled.").send(),
+    }
+
+    Ok(see_other(&format!("/admin/issues/{}", issue_id)))
+}
+
+/// Returns `false` if the delivery was not in the `from` state.
+async fn set_delivery_state(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    issue_id: Uuid,
+    from: DeliveryState,
+    to: DeliveryState,
+) -> Result<bool, anyhow::Error> {
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET delivery_state = $3
+        WHERE newsletter_issue_id = $1 AND delivery_state = $2
+        "#,
+        issue_id,
+        from.as_str(),
+        to.as_str()
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to update the delivery state")?
+    .rows_affected();
+
+    Ok(n_updated > 0)
+}
+
+/// Move what is left in the queue to `cancelled_deliveries`. Returns how
+/// many emails will not be sent, or `None` if the delivery had already been
+/// cancelled.
+async fn cancel(pool: &PgPool, issue_id: Uuid) -> Result<Option<u64>, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    // The queue is drained before the issue is touched: a worker halfway
+    // through a batch holds on to its tasks - we wait for it to complete -
+    // and may need to update the issue in the meantime, to pick the winner
+    // of an A/B test.
+    let n_cancelled = sqlx::query!(
+        r#"
+        WITH drained AS (
+            DELETE FROM issue_delivery_queue
+            WHERE
+                newsletter_issue_id = $1 AND
+                delivered_at IS NULL AND
+                failed_at IS NULL
+            RETURNING newsletter_issue_id, subscriber_email, subject_variant, n_retries
+        )
+        INSERT INTO cancelled_deliveries (
+            newsletter_issue_id,
+            subscriber_email,
+            subject_variant,
+            n_retries,
+            cancelled_at
+        )
+        SELECT newsletter_issue_id, subscriber_email, subject_variant, n_retries, now()
+        FROM drained
+        "#,
+        issue_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to drain the delivery queue")?
+    .rows_affected();
+
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET delivery_state = 'cancelled'
+        WHERE newsletter_issue_id = $1 AND delivery_state <> 'cancelled'
+        "#,
+        issue_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to update the delivery state")?
+    .rows_affected();
+    if n_updated == 0 {
+        // Rolled back, there was nothing to drain anyway
+        return Ok(None);
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to cancel a delivery")?;
+
+    Ok(Some(n_cancelled))
+}
@@ -0,0 +1,334 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use super::DeliveryState;
+use crate::{
+    ab_testing::{get_variant_results, TestMetric},
+    utils::e500,
+};
+
+#[tracing::instrument(
+    name = "Show the history of a newsletter issue",
+    skip(flash_message, pool)
+)]
+pub async fn issue_history(
+    issue_id: web::Path<Uuid>,
+    flash_message: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let issue = match get_issue(&pool, issue_id).await.map_err(e500)? {
+        Some(issue) => issue,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let mut msg_html = String::new();
+    for m in flash_message.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let title = htmlescape::encode_minimal(&issue.title);
+    let published_at = issue.published_at.format("%Y-%m-%d %H:%M");
+
+    let mut events_html = String::new();
+    for e in get_event_counts(&pool, issue_id).await.map_err(e500)? {
+        writeln!(
+            events_html,
+            "<tr><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&e.event_type),
+            e.n_events
+        )
+        .unwrap();
+    }
+
+    let engagement = get_engagement(&pool, issue_id).await.map_err(e500)?;
+    let open_rate = rate(engagement.n_opened, issue.n_delivered);
+    let click_rate = rate(engagement.n_clicked, issue.n_delivered);
+
+    let mut links_html = String::new();
+    for l in get_clicked_links(&pool, issue_id).await.map_err(e500)? {
+        writeln!(
+            links_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&l.url),
+            l.n_clicks,
+            l.n_subscribers
+        )
+        .unwrap();
+    }
+
+    let delivery_state = DeliveryState::try_from(issue.delivery_state.clone()).map_err(e500)?;
+    let delivery_html = delivery_html(&issue, issue_id, delivery_state);
+
+    let ab_test_html = match issue.ab_test_metric.clone() {
+        Some(metric) => {
+            let metric = TestMetric::try_from(metric).map_err(e500)?;
+            ab_test_html(&pool, &issue, issue_id, metric).await?
+        }
+        None => String::new(),
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    {msg_html}
+    <h1>{title}</h1>
+    <p>Published on {published_at}</p>
+    <p>Delivered to {} out of {} recipient(s).</p>
+    <p>Gave up on {} recipient(s) after repeated failures.</p>
+    {delivery_html}
+    <h2>Engagement</h2>
+    <p>Opened by {} recipient(s) ({open_rate:.1}%), clicked by {} recipient(s) ({click_rate:.1}%).</p>
+    <table>
+        <thead>
+            <tr><th>Link</th><th>Clicks</th><th>Recipients</th></tr>
+        </thead>
+        <tbody>
+            {links_html}
+        </tbody>
+    </table>
+    <table>
+        <thead>
+            <tr><th>Event</th><th>Count</th></tr>
+        </thead>
+        <tbody>
+            {events_html}
+        </tbody>
+    </table>
+    {ab_test_html}
+    <p><a href="/admin/issues">&lt;- Back</a></p>
+</body>
+</html>"#,
+            issue.n_delivered,
+            issue.n_recipients,
+            issue.n_failed,
+            engagement.n_opened, engagement.n_clicked
+        )))
+}
+
+/// Where the delivery is at, with the actions it allows.
+fn delivery_html(issue: &IssueHistory, issue_id: Uuid, state: DeliveryState) -> String {
+    let n_pending = issue.n_recipients - issue.n_delivered - issue.n_failed;
+    let form = |action: &str, label: &str| {
+        format!(
+            r#"<form action="/admin/issues/{issue_id}/{action}" method="post"><button type="submit">{label}</button></form>"#
+        )
+    };
+
+    match state {
+        DeliveryState::Active if n_pending > 0 => format!(
+            "<p>Delivery in progress, {} email(s) left to send.</p>\n    {}\n    {}",
+            n_pending,
+            form("pause", "Pause delivery"),
+            form("cancel", "Cancel delivery")
+        ),
+        DeliveryState::Active => String::new(),
+        DeliveryState::Paused => format!(
+            "<p>Delivery paused, {} email(s) on hold.</p>\n    {}\n    {}",
+            n_pending,
+            form("resume", "Resume delivery"),
+            form("cancel", "Cancel delivery")
+        ),
+        DeliveryState::Cancelled => format!(
+            "<p>Delivery cancelled, {} email(s) were not sent.</p>",
+            issue.n_cancelled
+        ),
+    }
+}
+
+/// `n` as a percentage of `total`.
+fn rate(n: i64, total: i64) -> f64 {
+    if total == 0 {
+        0.
+    } else {
+        n as f64 * 100. / total as f64
+    }
+}
+
+/// Report on the subject line A/B test of an issue.
+async fn ab_test_html(
+    pool: &PgPool,
+    issue: &IssueHistory,
+    issue_id: Uuid,
+    metric: TestMetric,
+) -> Result<String, actix_web::Error> {
+    let mut variants_html = String::new();
+
+    for r in get_variant_results(pool, issue_id, metric)
+        .await
+        .map_err(e500)?
+    {
+        let winner = if issue.winning_variant == Some(r.variant) {
+            " (winner)"
+        } else {
+            ""
+        };
+        writeln!(
+            variants_html,
+            "<tr><td>{}{}</td><td>{}</td><td>{}</td><td>{:.1}%</td></tr>",
+            htmlescape::encode_minimal(&r.subject),
+            winner,
+            r.n_sent,
+            r.n_engaged,
+            r.rate() * 100.
+        )
+        .unwrap();
+    }
+
+    let status = match (issue.winning_variant, issue.ab_test_ends_at) {
+        (Some(_), _) => {
+            "The winning subject has been sent to the rest of the recipients.".to_owned()
+        }
+        (None, Some(ends_at)) => format!(
+            "The test runs until {}, the winner is picked by {}.",
+            ends_at.format("%Y-%m-%d %H:%M"),
+            metric.as_str()
+        ),
+        (None, None) => String::new(),
+    };
+
+    Ok(format!(
+        r#"<h2>Subject A/B test</h2>
+    <p>{status}</p>
+    <table>
+        <thead>
+            <tr><th>Subject</th><th>Sent</th><th>Engaged</th><th>Rate</th></tr>
+        </thead>
+        <tbody>
+            {variants_html}
+        </tbody>
+    </table>"#
+    ))
+}
+
+struct IssueHistory {
+    title: String,
+    published_at: DateTime<Utc>,
+    ab_test_metric: Option<String>,
+    ab_test_ends_at: Option<DateTime<Utc>>,
+    winning_variant: Option<i32>,
+    delivery_state: String,
+    n_recipients: i64,
+    n_delivered: i64,
+    n_failed: i64,
+    n_cancelled: i64,
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<Option<IssueHistory>, sqlx::Error> {
+    sqlx::query_as!(
+        IssueHistory,
+        r#"
+        SELECT
+            i.title,
+            i.published_at,
+            i.ab_test_metric,
+            i.ab_test_ends_at,
+            i.winning_variant,
+            i.delivery_state,
+            COUNT(q.subscriber_email) AS "n_recipients!",
+            COUNT(q.delivered_at) AS "n_delivered!",
+            COUNT(q.failed_at) AS "n_failed!",
+            (
+                SELECT COUNT(*)
+                FROM cancelled_deliveries c
+                WHERE c.newsletter_issue_id = i.newsletter_issue_id
+            ) AS "n_cancelled!"
+        FROM newsletter_issues i
+        LEFT JOIN issue_delivery_queue q ON q.newsletter_issue_id = i.newsletter_issue_id
+        WHERE i.newsletter_issue_id = $1
+        GROUP BY i.newsletter_issue_id
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+struct EventCount {
+    event_type: String,
+    n_events: i64,
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_event_counts(pool: &PgPool, issue_id: Uuid) -> Result<Vec<EventCount>, sqlx::Error> {
+    sqlx::query_as!(
+        EventCount,
+        r#"
+        SELECT event_type, COUNT(*) AS "n_events!"
+        FROM email_events
+        WHERE newsletter_issue_id = $1
+        GROUP BY event_type
+        ORDER BY event_type
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// How many recipients opened the issue or clicked a link in it, as tracked
+/// by us or reported by the email provider.
+struct Engagement {
+    n_opened: i64,
+    n_clicked: i64,
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_engagement(pool: &PgPool, issue_id: Uuid) -> Result<Engagement, sqlx::Error> {
+    sqlx::query_as!(
+        Engagement,
+        r#"
+        SELECT
+            COUNT(DISTINCT lower(subscriber_email))
+                FILTER (WHERE event_type IN ('opened', 'unique_opened')) AS "n_opened!",
+            COUNT(DISTINCT lower(subscriber_email))
+                FILTER (WHERE event_type = 'click') AS "n_clicked!"
+        FROM email_events
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .await
+}
+
+struct ClickedLink {
+    url: String,
+    n_clicks: i64,
+    n_subscribers: i64,
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_clicked_links(pool: &PgPool, issue_id: Uuid) -> Result<Vec<ClickedLink>, sqlx::Error> {
+    sqlx::query_as!(
+        ClickedLink,
+        r#"
+        SELECT
+            url AS "url!",
+            COUNT(*) AS "n_clicks!",
+            COUNT(DISTINCT subscriber_id) AS "n_subscribers!"
+        FROM email_events
+        WHERE
+            newsletter_issue_id = $1 AND
+            event_type = 'click' AND
+            url IS NOT NULL
+        GROUP BY url
+        ORDER BY COUNT(*) DESC, url
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await
+}
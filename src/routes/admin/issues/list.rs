@@ -0,0 +1,79 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::utils::e500;
+
+pub async fn issues_page(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let mut issues_html = String::new();
+
+    for i in get_issues(&pool).await.map_err(e500)? {
+        writeln!(
+            issues_html,
+            r#"<tr><td><a href="/admin/issues/{}">{}</a></td><td>{}</td><td>{} / {}</td></tr>"#,
+            i.newsletter_issue_id,
+            htmlescape::encode_minimal(&i.title),
+            i.published_at.format("%Y-%m-%d %H:%M"),
+            i.n_delivered,
+            i.n_recipients
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Newsletter issues</title>
+</head>
+<body>
+    <table>
+        <thead>
+            <tr><th>Title</th><th>Published</th><th>Delivered</th></tr>
+        </thead>
+        <tbody>
+            {issues_html}
+        </tbody>
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+struct IssueRow {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+    n_recipients: i64,
+    n_delivered: i64,
+}
+
+#[tracing::instrument(
+    name = "Get newsletter issues with their delivery progress",
+    skip(pool)
+)]
+async fn get_issues(pool: &PgPool) -> Result<Vec<IssueRow>, sqlx::Error> {
+    sqlx::query_as!(
+        IssueRow,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.published_at,
+            COUNT(q.subscriber_email) AS "n_recipients!",
+            COUNT(q.delivered_at) AS "n_delivered!"
+        FROM newsletter_issues i
+        LEFT JOIN issue_delivery_queue q ON q.newsletter_issue_id = i.newsletter_issue_id
+        GROUP BY i.newsletter_issue_id
+        ORDER BY i.published_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,7 @@
+mod delivery;
+mod history;
+mod list;
+
+pub use delivery::{cancel_delivery, pause_delivery, resume_delivery, DeliveryState};
+pub use history::issue_history;
+pub use list::issues_page;
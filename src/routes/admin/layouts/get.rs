@@ -0,0 +1,77 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    layouts::{get_layouts, CONTENT_PLACEHOLDER},
+    utils::e500,
+};
+
+pub async fn layouts_page(
+    flash_message: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+
+    for m in flash_message.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut layouts_html = String::new();
+
+    for l in get_layouts(&pool).await.map_err(e500)? {
+        let default_html = if l.is_default {
+            "Default".to_owned()
+        } else {
+            format!(
+                r#"<form action="/admin/layouts/default" method="post">
+                    <input hidden type="text" name="layout_id" value="{}">
+                    <button type="submit">Make default</button>
+                </form>"#,
+                l.layout_id
+            )
+        };
+        writeln!(
+            layouts_html,
+            "<tr><td>{}</td><td><pre>{}</pre></td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&l.name),
+            htmlescape::encode_minimal(&l.html),
+            default_html
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Email layouts</title>
+</head>
+<body>
+    {msg_html}
+    <table>
+        <thead>
+            <tr><th>Name</th><th>HTML</th><th></th></tr>
+        </thead>
+        <tbody>
+            {layouts_html}
+        </tbody>
+    </table>
+    <form action="/admin/layouts" method="post">
+        <label>Name
+            <input type="text" placeholder="Announcements" name="name">
+        </label>
+        <label>HTML, with <code>{CONTENT_PLACEHOLDER}</code> where the content goes
+            <textarea name="html" cols="60" rows="20"></textarea>
+        </label>
+        <button type="submit">Create layout</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
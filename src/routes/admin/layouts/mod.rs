@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::layouts_page;
+pub use post::{create_layout, set_default_layout};
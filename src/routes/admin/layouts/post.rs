@@ -0,0 +1,79 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    layouts::EmailLayout,
+    utils::{e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    html: String,
+}
+
+#[tracing::instrument(name = "Create an email layout", skip(form, pool), fields(name = %form.name))]
+pub async fn create_layout(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData { name, html } = form.0;
+    let name = name.trim();
+
+    if name.is_empty() {
+        FlashMessage::error("The layout must have a name.").send();
+        return Ok(see_other("/admin/layouts"));
+    }
+
+    if let Err(e) = EmailLayout::validate(&html) {
+        FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+        return Ok(see_other("/admin/layouts"));
+    }
+
+    if crate::layouts::create_layout(&pool, name, &html)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info(format!(
+            "The {} layout has been created.",
+            htmlescape::encode_minimal(name)
+        ))
+        .send();
+    } else {
+        FlashMessage::error(format!(
+            "A layout named {} already exists.",
+            htmlescape::encode_minimal(name)
+        ))
+        .send();
+    }
+
+    Ok(see_other("/admin/layouts"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct DefaultLayoutFormData {
+    layout_id: Uuid,
+}
+
+#[tracing::instrument(
+    name = "Set the default email layout",
+    skip(form, pool),
+    fields(layout_id = %form.layout_id)
+)]
+pub async fn set_default_layout(
+    form: web::Form<DefaultLayoutFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if crate::layouts::set_default_layout(&pool, form.layout_id)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info("The default layout has been changed.").send();
+    } else {
+        FlashMessage::error("The layout does not exist.").send();
+    }
+
+    Ok(see_other("/admin/layouts"))
+}
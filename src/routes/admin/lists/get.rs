@@ -0,0 +1,89 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::utils::e500;
+
+pub async fn lists_page(
+    flash_message: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+
+    for m in flash_message.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut lists_html = String::new();
+
+    for l in get_lists_with_subscriber_counts(&pool)
+        .await
+        .map_err(e500)?
+    {
+        writeln!(
+            lists_html,
+            "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+            htmlescape::encode_minimal(&l.slug),
+            htmlescape::encode_minimal(&l.name),
+            l.n_subscribers.unwrap_or_default()
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Mailing lists</title>
+</head>
+<body>
+    {msg_html}
+    <table>
+        <thead>
+            <tr><th>Slug</th><th>Name</th><th>Confirmed subscribers</th></tr>
+        </thead>
+        <tbody>
+            {lists_html}
+        </tbody>
+    </table>
+    <form action="/admin/lists" method="post">
+        <label>Slug
+            <input type="text" placeholder="engineering-blog" name="slug">
+        </label>
+        <label>Name
+            <input type="text" placeholder="Engineering blog" name="name">
+        </label>
+        <button type="submit">Create list</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+struct ListRow {
+    slug: String,
+    name: String,
+    n_subscribers: Option<i64>,
+}
+
+#[tracing::instrument(name = "Get mailing lists with their subscriber counts", skip(pool))]
+async fn get_lists_with_subscriber_counts(pool: &PgPool) -> Result<Vec<ListRow>, sqlx::Error> {
+    sqlx::query_as!(
+        ListRow,
+        r#"
+        SELECT l.slug, l.name, COUNT(s.id) AS n_subscribers
+        FROM lists l
+        LEFT JOIN list_subscriptions ls ON ls.list_id = l.list_id
+        LEFT JOIN subscriptions s ON s.id = ls.subscriber_id AND s.status = 'confirmed'
+        GROUP BY l.list_id
+        ORDER BY l.created_at, l.name
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
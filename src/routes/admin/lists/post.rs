@@ -0,0 +1,53 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
+
+use crate::utils::{e500, see_other};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    slug: String,
+    name: String,
+}
+
+#[tracing::instrument(name = "Create a mailing list", skip(form, pool), fields(slug = %form.slug))]
+pub async fn create_list(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData { slug, name } = form.0;
+    let slug = slug.trim();
+    let name = name.trim();
+
+    if !is_valid_slug(slug) {
+        FlashMessage::error(
+            "The slug must be made of lowercase letters, digits and dashes (at most 64 characters).",
+        )
+        .send();
+        return Ok(see_other("/admin/lists"));
+    }
+
+    if name.is_empty() {
+        FlashMessage::error("The list must have a name.").send();
+        return Ok(see_other("/admin/lists"));
+    }
+
+    if crate::lists::create_list(&pool, slug, name)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::info(format!("The {} list has been created.", slug)).send();
+    } else {
+        FlashMessage::error(format!("A list with the {} slug already exists.", slug)).send();
+    }
+
+    Ok(see_other("/admin/lists"))
+}
+
+fn is_valid_slug(slug: &str) -> bool {
+    !slug.is_empty()
+        && slug.len() <= 64
+        && slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+}
@@ -1,9 +1,19 @@
 mod dashboard;
+mod issues;
+mod layouts;
+mod lists;
 mod logout;
 mod newsletter;
 mod password;
+mod subscribers;
+mod suppressions;
 
 pub use dashboard::admin_dashboard;
+pub use issues::*;
+pub use layouts::*;
+pub use lists::*;
 pub use logout::*;
 pub use newsletter::*;
 pub use password::*;
+pub use subscribers::*;
+pub use suppressions::*;
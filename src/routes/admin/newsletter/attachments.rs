@@ -0,0 +1,114 @@
+use actix_multipart::{Field, Multipart};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use futures_util::StreamExt;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    attachments::{self, store_attachment, MAX_ATTACHMENT_SIZE},
+    utils::{e400, e500, see_other},
+};
+
+/// Upload a file to attach to the next issue.
+///
+/// The form sends the ids of the files uploaded so far in `attachments`:
+/// they are handed back to the publish form along with the new one.
+#[tracing::instrument(name = "Upload an attachment", skip(payload, pool))]
+pub async fn upload_attachment(
+    mut payload: Multipart,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut attachment_ids = Vec::new();
+    let mut inline = false;
+    let mut upload = None;
+
+    while let Some(field) = payload.next().await {
+        let mut field = field?;
+        let field_name = field.content_disposition().get_name().map(str::to_owned);
+
+        match field_name.as_deref() {
+            Some("attachments") => {
+                let ids = String::from_utf8(read_field(&mut field).await?).map_err(e400)?;
+                attachment_ids = parse_attachment_ids(&ids)?;
+            }
+            Some("inline") => inline = true,
+            Some("file") => {
+                let filename = field
+                    .content_disposition()
+                    .get_filename()
+                    .unwrap_or_default()
+                    .to_owned();
+                let content_type = field.content_type().essence_str().to_owned();
+                upload = Some((filename, content_type, read_field(&mut field).await));
+            }
+            _ => {}
+        }
+    }
+
+    let redirect_to = |ids: &[Uuid]| {
+        let ids: Vec<_> = ids.iter().map(Uuid::to_string).collect();
+        see_other(&format!("/admin/newsletters?attachments={}", ids.join(",")))
+    };
+
+    let (filename, content_type, content) = match upload {
+        Some((filename, content_type, Ok(content))) => (filename, content_type, content),
+        Some((filename, _, Err(_))) => {
+            FlashMessage::error(format!(
+                "{} is too large, attachments are limited to {} MB",
+                htmlescape::encode_minimal(&filename),
+                MAX_ATTACHMENT_SIZE / 1024 / 1024
+            ))
+            .send();
+            return Ok(redirect_to(&attachment_ids));
+        }
+        None => return Err(e400("No file has been uploaded")),
+    };
+
+    if let Err(e) = attachments::validate(&filename, &content_type, &content, inline) {
+        FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+        return Ok(redirect_to(&attachment_ids));
+    }
+
+    let attachment_id = store_attachment(&pool, &filename, &content_type, &content, inline)
+        .await
+        .map_err(e500)?;
+    attachment_ids.push(attachment_id);
+
+    let filename = htmlescape::encode_minimal(&filename);
+    let message = if inline {
+        format!(
+            r#"{} has been uploaded - display it with &lt;img src="cid:{}"&gt;"#,
+            filename, attachment_id
+        )
+    } else {
+        format!("{} has been uploaded", filename)
+    };
+    FlashMessage::info(message).send();
+
+    Ok(redirect_to(&attachment_ids))
+}
+
+/// Parse a comma-separated list of attachment ids.
+pub fn parse_attachment_ids(s: &str) -> Result<Vec<Uuid>, actix_web::Error> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| Uuid::parse_str(id).map_err(e400))
+        .collect()
+}
+
+/// Read the content of `field`, giving up past `MAX_ATTACHMENT_SIZE`
+/// rather than buffering an arbitrarily large upload.
+async fn read_field(field: &mut Field) -> Result<Vec<u8>, actix_web::Error> {
+    let mut bytes = Vec::new();
+
+    while let Some(chunk) = field.next().await {
+        bytes.extend_from_slice(&chunk?);
+        if bytes.len() > MAX_ATTACHMENT_SIZE {
+            return Err(e400("The field is too large"));
+        }
+    }
+
+    Ok(bytes)
+}
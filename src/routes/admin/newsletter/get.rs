@@ -1,9 +1,29 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::{http::header::ContentType, web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
 use std::fmt::Write;
 
+use super::recipients::{count_recipients, parse_segment};
+use crate::{
+    ab_testing::{DEFAULT_TEST_SLICE_PERCENT, DEFAULT_TEST_WINDOW_HOURS},
+    layouts::get_layouts,
+    lists::{get_list_id, get_lists, MailingList, DEFAULT_LIST_SLUG},
+    utils::e500,
+};
+
+/// Filled in when previewing the audience of an issue.
+#[derive(serde::Deserialize)]
+pub struct PreviewQuery {
+    list: Option<String>,
+    segment: Option<String>,
+    /// Ids of the files uploaded for the issue, comma-separated.
+    attachments: Option<String>,
+}
+
 pub async fn publish_newsletter_form(
     flash_message: IncomingFlashMessages,
+    query: web::Query<PreviewQuery>,
+    pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let mut msg_html = String::new();
 
@@ -11,7 +31,39 @@ pub async fn publish_newsletter_form(
         writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
     }
 
+    let list = query.list.as_deref().unwrap_or(DEFAULT_LIST_SLUG);
+    let segment = query.segment.as_deref().unwrap_or_default();
+
+    if query.list.is_some() || query.segment.is_some() {
+        let preview = preview_recipients(&pool, list, segment)
+            .await
+            .map_err(e500)?;
+        writeln!(
+            msg_html,
+            "<p><i>{}</i></p>",
+            htmlescape::encode_minimal(&preview)
+        )
+        .unwrap();
+    }
+
     let idempotency_key = uuid::Uuid::new_v4();
+    let lists = get_lists(&pool).await.map_err(e500)?;
+    let list_options_html = MailingList::options_html(&lists, list);
+    let segment = htmlescape::encode_attribute(segment);
+    let attachments =
+        htmlescape::encode_attribute(query.attachments.as_deref().unwrap_or_default());
+
+    let mut layout_options_html = String::new();
+    for l in get_layouts(&pool).await.map_err(e500)? {
+        writeln!(
+            layout_options_html,
+            r#"<option value="{}"{}>{}</option>"#,
+            l.layout_id,
+            if l.is_default { " selected" } else { "" },
+            htmlescape::encode_minimal(&l.name)
+        )
+        .unwrap();
+    }
 
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
@@ -27,22 +79,120 @@ pub async fn publish_newsletter_form(
   </head>
   <body>
     {msg_html}
+    <form action="/admin/newsletters" method="get">
+      <label>
+        List
+        <select name="list">
+          {list_options_html}
+        </select>
+      </label>
+
+      <label>
+        Segment
+        <input type="text" placeholder="tag beta AND NOT tag churned" name="segment" value="{segment}" />
+      </label>
+
+      <button type="submit">Preview recipients</button>
+    </form>
+
+    <form action="/admin/newsletters/attachments" method="post" enctype="multipart/form-data">
+      <input hidden type="text" name="attachments" value="{attachments}" />
+
+      <label>
+        Attachment
+        <input type="file" name="file" />
+      </label>
+
+      <label>
+        <input type="checkbox" name="inline" value="true" />
+        Display inline (images only)
+      </label>
+
+      <button type="submit">Upload</button>
+    </form>
+
     <form action="/admin/newsletters" method="post">
       <label>
         Title
         <input type="text" placeholder="Enter title" name="title" />
       </label>
 
+      <label>
+        List
+        <select name="list">
+          {list_options_html}
+        </select>
+      </label>
+
+      <label>
+        Segment
+        <input type="text" placeholder="Everybody on the list" name="segment" value="{segment}" />
+      </label>
+
+      <fieldset>
+        <legend>Subject A/B test</legend>
+        <label>
+          Alternative subjects, one per line
+          <textarea name="subject_variants" cols="30" rows="3"></textarea>
+        </label>
+
+        <label>
+          Pick the winner by
+          <select name="ab_test_metric">
+            <option value="opens" selected>Open rate</option>
+            <option value="clicks">Click rate</option>
+          </select>
+        </label>
+
+        <label>
+          Test slice (% of recipients)
+          <input type="number" name="test_slice_percent" min="1" max="100" value="{DEFAULT_TEST_SLICE_PERCENT}" />
+        </label>
+
+        <label>
+          Test window (hours)
+          <input type="number" name="test_window_hours" min="0" max="168" value="{DEFAULT_TEST_WINDOW_HOURS}" />
+        </label>
+      </fieldset>
+
+      <label>
+        Layout
+        <select name="layout">
+          {layout_options_html}
+          <option value="none">No layout</option>
+        </select>
+      </label>
+
       <label>
         Content
         <textarea
-          name="content"
+          name="markdown"
           cols="30"
           rows="10"
-          placeholder="Enter content"
+          placeholder="Write the issue in Markdown"
         ></textarea>
       </label>
 
+      <label>
+        Attachments
+        <input type="text" name="attachments" value="{attachments}" />
+      </label>
+
+      <label>
+        <input type="checkbox" name="track_opens" value="true" checked />
+        Track opens
+      </label>
+
+      <label>
+        <input type="checkbox" name="track_clicks" value="true" checked />
+        Track clicks
+      </label>
+
+      <label>
+        <input type="checkbox" name="exclude_from_archive" value="true" />
+        Keep out of the public archive
+      </label>
+
       <input hidden type="text" name="idempotency_key" value="{idempotency_key}" />
       <button type="submit">Login</button>
     </form>
@@ -50,3 +200,28 @@ pub async fn publish_newsletter_form(
 </html>"#
         )))
 }
+
+/// Describe how many subscribers an issue sent to `list` and `segment` would reach.
+#[tracing::instrument(name = "Preview the recipients of a newsletter issue", skip(pool))]
+async fn preview_recipients(
+    pool: &PgPool,
+    list: &str,
+    segment: &str,
+) -> Result<String, sqlx::Error> {
+    let list_id = match get_list_id(pool, list).await? {
+        Some(list_id) => list_id,
+        None => return Ok(format!("{} is not a known mailing list.", list)),
+    };
+
+    let segment = match parse_segment(Some(segment)) {
+        Ok(segment) => segment,
+        Err(e) => return Ok(format!("Invalid segment: {}", e)),
+    };
+
+    let n_recipients = count_recipients(pool, list_id, segment.as_ref()).await?;
+
+    Ok(format!(
+        "The issue would be sent to {} subscriber(s).",
+        n_recipients
+    ))
+}
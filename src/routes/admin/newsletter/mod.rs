@@ -1,5 +1,8 @@
+mod attachments;
 mod get;
 mod post;
+mod recipients;
 
+pub use attachments::upload_attachment;
 pub use get::publish_newsletter_form;
 pub use post::publish_newsletter;
@@ -4,18 +4,55 @@ use anyhow::Context;
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
+use super::{
+    attachments::parse_attachment_ids,
+    recipients::{enqueue_delivery_tasks, parse_segment},
+};
 use crate::{
+    ab_testing::{
+        assign_test_slice, store_variants, TestMetric, DEFAULT_TEST_SLICE_PERCENT,
+        DEFAULT_TEST_WINDOW_HOURS,
+    },
+    attachments::{attach_to_issue, MAX_ISSUE_ATTACHMENTS_SIZE},
     authentication::UserId,
+    domain::{MergeTemplate, Segment},
     idempotency::{save_response, try_processing, IdempotencyKey, NextAction},
+    layouts::{get_default_layout, get_layout, EmailLayout},
+    lists::{get_list_id, DEFAULT_LIST_SLUG},
+    markdown,
     utils::{e400, e500, see_other},
 };
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     title: String,
-    html: String,
-    text: String,
+    /// When given, `html` and `text` are generated from it.
+    markdown: Option<String>,
+    html: Option<String>,
+    text: Option<String>,
     idempotency_key: String,
+    /// Slug of the list the issue goes out to.
+    list: Option<String>,
+    /// Only subscribers matching the segment receive the issue.
+    segment: Option<String>,
+    /// Id of the layout wrapping the issue, `none` to send it bare.
+    /// Defaults to the default layout.
+    layout: Option<String>,
+    /// Set to keep the issue out of the public archive.
+    exclude_from_archive: Option<String>,
+    /// Ids of the uploaded files to attach, comma-separated.
+    attachments: Option<String>,
+    /// Alternative subjects, one per line, A/B tested against the title.
+    subject_variants: Option<String>,
+    ab_test_metric: Option<String>,
+    /// Share of the recipients the variants are tried on.
+    test_slice_percent: Option<u32>,
+    /// How long to wait before picking the winning variant.
+    test_window_hours: Option<u32>,
+    /// Set to add an open pixel to the HTML body.
+    track_opens: Option<String>,
+    /// Set to route links through the click redirector.
+    track_clicks: Option<String>,
 }
 
 #[tracing::instrument(
@@ -32,13 +69,66 @@ pub async fn publish_newsletter(
 
     let FormData {
         title,
+        markdown,
         html,
         text,
         idempotency_key,
+        list,
+        segment,
+        layout,
+        exclude_from_archive,
+        attachments,
+        subject_variants,
+        ab_test_metric,
+        test_slice_percent,
+        test_window_hours,
+        track_opens,
+        track_clicks,
     } = form.0;
 
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
 
+    let list = list.as_deref().unwrap_or(DEFAULT_LIST_SLUG);
+    let list_id = get_list_id(&pool, list)
+        .await
+        .map_err(e500)?
+        .ok_or_else(|| e400(format!("{} is not a known mailing list", list)))?;
+    let segment = parse_segment(segment.as_deref()).map_err(e400)?;
+    let attachment_ids = parse_attachment_ids(attachments.as_deref().unwrap_or_default())?;
+    let subject_test = SubjectTest::parse(
+        &title,
+        subject_variants.as_deref().unwrap_or_default(),
+        ab_test_metric,
+        test_slice_percent,
+        test_window_hours,
+        track_opens.is_some(),
+        track_clicks.is_some(),
+    )
+    .map_err(e400)?;
+
+    let markdown = markdown.filter(|m| !m.trim().is_empty());
+    let (html, text) = match (&markdown, html, text) {
+        (Some(markdown), _, _) => {
+            let rendered = markdown::render(markdown);
+            (rendered.html, rendered.text)
+        }
+        (None, Some(html), Some(text)) => (html, text),
+        _ => {
+            return Err(e400(
+                "The issue needs either a Markdown body or both an HTML and a plain text body",
+            ))
+        }
+    };
+    let layout = get_issue_layout(&pool, layout.as_deref()).await?;
+    let html = match &layout {
+        Some(layout) => layout.wrap(&html).map_err(e500)?,
+        None => html,
+    };
+
+    // Merge tags are only rendered by the delivery worker, catch mistakes now
+    MergeTemplate::parse(&html).map_err(e400)?;
+    MergeTemplate::parse(&text).map_err(e400)?;
+
     let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
         .await
         .map_err(e500)?
@@ -50,15 +140,63 @@ pub async fn publish_newsletter(
         }
     };
 
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text, &html)
+    let issue = NewIssue {
+        title: &title,
+        text_content: &text,
+        html_content: &html,
+        markdown_content: markdown.as_deref(),
+        list_id,
+        segment: segment.as_ref(),
+        layout_id: layout.map(|l| l.layout_id),
+        in_archive: exclude_from_archive.is_none(),
+        subject_test: subject_test.as_ref(),
+        track_opens: track_opens.is_some(),
+        track_clicks: track_clicks.is_some(),
+    };
+
+    let issue_id = insert_newsletter_issue(&mut transaction, &issue)
         .await
         .context("Failed to store newsletter issue details")
         .map_err(e500)?;
 
-    enqueue_delivery_tasks(&mut transaction, issue_id)
+    let (n_attached, attachments_size) =
+        attach_to_issue(&mut transaction, issue_id, &attachment_ids)
+            .await
+            .context("Failed to attach files to the newsletter issue")
+            .map_err(e500)?;
+    if n_attached != attachment_ids.len() {
+        return Err(e400(
+            "Some attachments do not exist or belong to another issue",
+        ));
+    }
+    if attachments_size > MAX_ISSUE_ATTACHMENTS_SIZE {
+        return Err(e400(format!(
+            "The attachments are too large, they are limited to {} MB per issue",
+            MAX_ISSUE_ATTACHMENTS_SIZE / 1024 / 1024
+        )));
+    }
+
+    let n_recipients =
+        enqueue_delivery_tasks(&mut transaction, issue_id, list_id, segment.as_ref())
+            .await
+            .context("Failed to enqueue delivery tasks")
+            .map_err(e500)?;
+
+    if let Some(subject_test) = &subject_test {
+        store_variants(&mut transaction, issue_id, &subject_test.subjects)
+            .await
+            .context("Failed to store the subject variants")
+            .map_err(e500)?;
+        assign_test_slice(
+            &mut transaction,
+            issue_id,
+            subject_test.slice_size(n_recipients),
+            subject_test.subjects.len() as i32,
+        )
         .await
-        .context("Failed to enqueue delivery tasks")
+        .context("Failed to assign subject variants to the test slice")
         .map_err(e500)?;
+    }
 
     let response = see_other("/admin/newsletters");
     let response = save_response(transaction, &idempotency_key, *user_id, response)
@@ -70,16 +208,119 @@ pub async fn publish_newsletter(
     Ok(response)
 }
 
+async fn get_issue_layout(
+    pool: &PgPool,
+    layout: Option<&str>,
+) -> Result<Option<EmailLayout>, actix_web::Error> {
+    match layout {
+        None => get_default_layout(pool).await.map_err(e500),
+        Some("none") => Ok(None),
+        Some(layout_id) => {
+            let layout_id = Uuid::parse_str(layout_id).map_err(e400)?;
+            let layout = get_layout(pool, layout_id)
+                .await
+                .map_err(e500)?
+                .ok_or_else(|| e400(format!("{} is not a known layout", layout_id)))?;
+            Ok(Some(layout))
+        }
+    }
+}
+
 fn success_message() -> FlashMessage {
     FlashMessage::info("The newsletter issue has been accepted - emails will go out shortly")
 }
 
+/// A subject line A/B test, set up when alternative subjects are given.
+struct SubjectTest {
+    /// The title comes first, then the alternatives.
+    subjects: Vec<String>,
+    metric: TestMetric,
+    slice_percent: u32,
+    window_hours: u32,
+}
+
+impl SubjectTest {
+    /// The metric picking the winner must be tracked, as per `track_opens`
+    /// and `track_clicks`.
+    fn parse(
+        title: &str,
+        subject_variants: &str,
+        metric: Option<String>,
+        slice_percent: Option<u32>,
+        window_hours: Option<u32>,
+        track_opens: bool,
+        track_clicks: bool,
+    ) -> Result<Option<Self>, String> {
+        let alternatives: Vec<String> = subject_variants
+            .lines()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_owned)
+            .collect();
+
+        if alternatives.is_empty() {
+            return Ok(None);
+        }
+
+        let metric = match metric {
+            Some(metric) => TestMetric::try_from(metric)?,
+            None => TestMetric::Opens,
+        };
+        let is_tracked = match metric {
+            TestMetric::Opens => track_opens,
+            TestMetric::Clicks => track_clicks,
+        };
+        if !is_tracked {
+            return Err(format!(
+                "The test is decided on {}, which are not tracked for this issue",
+                metric.as_str()
+            ));
+        }
+        let slice_percent = slice_percent.unwrap_or(DEFAULT_TEST_SLICE_PERCENT);
+        if !(1..=100).contains(&slice_percent) {
+            return Err("The test slice must be between 1% and 100% of the recipients".into());
+        }
+        let window_hours = window_hours.unwrap_or(DEFAULT_TEST_WINDOW_HOURS);
+        if window_hours > 7 * 24 {
+            return Err("The test window cannot be longer than a week".into());
+        }
+
+        let mut subjects = vec![title.to_owned()];
+        subjects.extend(alternatives);
+
+        Ok(Some(Self {
+            subjects,
+            metric,
+            slice_percent,
+            window_hours,
+        }))
+    }
+
+    /// Every variant is tried on at least one recipient.
+    fn slice_size(&self, n_recipients: u64) -> i64 {
+        let slice_size = (n_recipients * u64::from(self.slice_percent)).div_ceil(100);
+        slice_size.max(self.subjects.len() as u64) as i64
+    }
+}
+
+struct NewIssue<'a> {
+    title: &'a str,
+    text_content: &'a str,
+    html_content: &'a str,
+    markdown_content: Option<&'a str>,
+    list_id: Uuid,
+    segment: Option<&'a Segment>,
+    layout_id: Option<Uuid>,
+    in_archive: bool,
+    subject_test: Option<&'a SubjectTest>,
+    track_opens: bool,
+    track_clicks: bool,
+}
+
 #[tracing::instrument(skip_all)]
 async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
-    title: &str,
-    text_content: &str,
-    html_content: &str,
+    issue: &NewIssue<'_>,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
 
@@ -90,14 +331,37 @@ async fn insert_newsletter_issue(
             title,
             text_content,
             html_content,
-            published_at
+            markdown_content,
+            published_at,
+            list_id,
+            segment,
+            layout_id,
+            in_archive,
+            slug,
+            ab_test_metric,
+            ab_test_ends_at,
+            track_opens,
+            track_clicks
+        )
+        VALUES (
+            $1, $2, $3, $4, $5, now(), $6, $7, $8, $9, $10, $11,
+            now() + make_interval(hours => $12), $13, $14
         )
-        VALUES ($1, $2, $3, $4, now())
         "#,
         newsletter_issue_id,
-        title,
-        text_content,
-        html_content
+        issue.title,
+        issue.text_content,
+        issue.html_content,
+        issue.markdown_content,
+        issue.list_id,
+        issue.segment.map(|s| s.to_string()),
+        issue.layout_id,
+        issue.in_archive,
+        issue_slug(issue.title, newsletter_issue_id),
+        issue.subject_test.map(|t| t.metric.as_str()),
+        issue.subject_test.map(|t| t.window_hours as i32),
+        issue.track_opens,
+        issue.track_clicks
     )
     .execute(transaction)
     .await?;
@@ -105,25 +369,23 @@ async fn insert_newsletter_issue(
     Ok(newsletter_issue_id)
 }
 
-#[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
-    transaction: &mut Transaction<'_, Postgres>,
-    newsletter_issue_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"
-        INSERT INTO issue_delivery_queue (
-            newsletter_issue_id,
-            subscriber_email
-        )
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#,
-        newsletter_issue_id
-    )
-    .execute(transaction)
-    .await?;
+/// The slug of an issue in the archive: its title, followed by the beginning
+/// of its id to keep it unique - e.g. `release-notes-8c1f4b2e`.
+fn issue_slug(title: &str, issue_id: Uuid) -> String {
+    let mut slug = String::new();
+
+    for c in title.to_lowercase().chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    if !slug.is_empty() && !slug.ends_with('-') {
+        slug.push('-');
+    }
+    slug.push_str(&issue_id.to_string()[..8]);
 
-    Ok(())
+    slug
 }
@@ -0,0 +1,100 @@
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::Segment;
+use crate::issue_delivery_worker::NEW_TASKS_CHANNEL;
+
+/// A blank segment targets the whole list.
+pub fn parse_segment(segment: Option<&str>) -> Result<Option<Segment>, String> {
+    match segment.map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(segment) => Segment::parse(segment).map(Some),
+    }
+}
+
+/// The `FROM ... WHERE ...` clause selecting the subscribers (aliased `s`)
+/// an issue goes out to: confirmed, not suppressed subscribers of the list
+/// bound to `$list_placeholder`, matching `segment` if any.
+///
+/// The segment tags must be bound right after the list id, in the
+/// returned order.
+fn recipients_sql(list_placeholder: usize, segment: Option<&Segment>) -> (String, Vec<String>) {
+    let (segment_condition, tags) = match segment {
+        Some(segment) => segment.to_sql(list_placeholder + 1),
+        None => ("TRUE".to_owned(), Vec::new()),
+    };
+
+    let sql = format!(
+        r#"
+        FROM subscriptions s
+        JOIN list_subscriptions ls ON ls.subscriber_id = s.id
+        WHERE
+            ls.list_id = ${list_placeholder} AND
+            s.status = 'confirmed' AND
+            NOT EXISTS (
+                SELECT 1
+                FROM suppressions
+                WHERE
+                    email_hash = encode(sha256(convert_to(lower(s.email), 'UTF8')), 'hex') AND
+                    reason <> 'erasure'
+            ) AND
+            {segment_condition}
+        "#
+    );
+
+    (sql, tags)
+}
+
+/// Queue a delivery task per recipient, returning how many there are.
+///
+/// Idle delivery workers are notified once the transaction commits.
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    list_id: Uuid,
+    segment: Option<&Segment>,
+) -> Result<u64, sqlx::Error> {
+    let (recipients_sql, tags) = recipients_sql(2, segment);
+    let sql = format!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email
+        )
+        SELECT $1, s.email
+        {recipients_sql}
+        "#
+    );
+
+    let mut query = sqlx::query(&sql).bind(newsletter_issue_id).bind(list_id);
+    for tag in tags {
+        query = query.bind(tag);
+    }
+    let n_enqueued = query.execute(&mut *transaction).await?.rows_affected();
+
+    sqlx::query("SELECT pg_notify($1, $2)")
+        .bind(NEW_TASKS_CHANNEL)
+        .bind(newsletter_issue_id.to_string())
+        .execute(transaction)
+        .await?;
+
+    Ok(n_enqueued)
+}
+
+#[tracing::instrument(name = "Count the recipients of a newsletter issue", skip(pool))]
+pub async fn count_recipients(
+    pool: &PgPool,
+    list_id: Uuid,
+    segment: Option<&Segment>,
+) -> Result<i64, sqlx::Error> {
+    let (recipients_sql, tags) = recipients_sql(1, segment);
+    let sql = format!("SELECT COUNT(*) {recipients_sql}");
+
+    let mut query = sqlx::query_scalar(&sql).bind(list_id);
+    for tag in tags {
+        query = query.bind(tag);
+    }
+
+    query.fetch_one(pool).await
+}
@@ -0,0 +1,61 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::{
+    lists::{get_lists, MailingList, DEFAULT_LIST_SLUG},
+    utils::e500,
+};
+
+pub async fn import_subscribers_form(
+    flash_message: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+
+    for m in flash_message.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let lists = get_lists(&pool).await.map_err(e500)?;
+    let list_options_html = MailingList::options_html(&lists, DEFAULT_LIST_SLUG);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Import subscribers</title>
+</head>
+<body>
+    {msg_html}
+    <p>Upload a CSV file with an <code>email</code> and a <code>name</code> column.
+    An optional <code>tags</code> column holds tags separated by spaces or semicolons.</p>
+    <form action="/admin/subscribers/import" method="post" enctype="multipart/form-data">
+        <label>Import mode
+            <select name="mode">
+                <option value="send_confirmation">Send confirmation emails</option>
+                <option value="confirmed">Import as confirmed</option>
+            </select>
+        </label>
+        <br>
+        <label>List
+            <select name="list">
+                {list_options_html}
+            </select>
+        </label>
+        <br>
+        <label>CSV file
+            <input type="file" name="file" accept=".csv,text/csv">
+        </label>
+        <br>
+        <button type="submit">Import</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
@@ -0,0 +1,7 @@
+mod get;
+mod post;
+mod tags;
+
+pub use get::import_subscribers_form;
+pub use post::import_subscribers;
+pub use tags::{subscriber_tags_form, update_subscriber_tags};
@@ -0,0 +1,578 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use actix_multipart::{Field, Multipart};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use futures_util::StreamExt;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{
+    csv_stream::{CsvHeader, CsvRecord, CsvStream},
+    domain::{EmailPolicy, NewSubscriber, SubscriberEmail, SubscriberName, SubscriberTag},
+    email_client::EmailClient,
+    layouts::get_default_layout,
+    lists::{get_list_id, DEFAULT_LIST_SLUG},
+    routes::{generate_subscription_token, send_confirmation_email, ConfirmationEmail},
+    startup::{ApplicationBaseUrl, HmacSecret},
+    suppression::find_suppressed,
+    utils::{e400, e500},
+};
+
+/// How many rows are inserted within a single transaction.
+const BATCH_SIZE: usize = 500;
+
+#[derive(Clone, Copy)]
+enum ImportMode {
+    Confirmed,
+    SendConfirmation,
+}
+
+impl ImportMode {
+    fn status(&self) -> &'static str {
+        match self {
+            ImportMode::Confirmed => "confirmed",
+            ImportMode::SendConfirmation => "pending_confirmation",
+        }
+    }
+}
+
+impl TryFrom<String> for ImportMode {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "confirmed" => Ok(Self::Confirmed),
+            "send_confirmation" => Ok(Self::SendConfirmation),
+            other => Err(format!("{} is not a supported import mode", other)),
+        }
+    }
+}
+
+struct CsvRow {
+    email: String,
+    name: String,
+}
+
+/// Position of the columns we read, known once the header has been parsed.
+#[derive(Clone, Copy)]
+struct Columns {
+    email: usize,
+    name: usize,
+    tags: Option<usize>,
+}
+
+impl TryFrom<CsvRow> for NewSubscriber {
+    type Error = String;
+
+    fn try_from(value: CsvRow) -> Result<Self, Self::Error> {
+        let name = SubscriberName::parse(value.name)?;
+        let email = SubscriberEmail::parse(value.email)?;
+        Ok(Self { email, name })
+    }
+}
+
+#[tracing::instrument(
+    name = "Import subscribers from a CSV file",
+    skip(payload, pool, email_client, base_url, hmac_secret, email_policy)
+)]
+pub async fn import_subscribers(
+    mut payload: Multipart,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    email_policy: web::Data<EmailPolicy>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // The form sends `mode` and `list` before `file`, hence we know them
+    // by the time we start streaming the rows.
+    let mut mode = ImportMode::SendConfirmation;
+    let mut list = DEFAULT_LIST_SLUG.to_owned();
+    let mut import = None;
+
+    while let Some(field) = payload.next().await {
+        let mut field = field?;
+        let field_name = field.content_disposition().get_name().map(str::to_owned);
+
+        match field_name.as_deref() {
+            Some("mode") => {
+                mode = read_text_field(&mut field)
+                    .await?
+                    .try_into()
+                    .map_err(e400)?;
+            }
+            Some("list") => {
+                list = read_text_field(&mut field).await?;
+            }
+            Some("file") => {
+                let list_id = get_list_id(&pool, &list)
+                    .await
+                    .map_err(e500)?
+                    .ok_or_else(|| e400(format!("{} is not a known mailing list", list)))?;
+                let mut subscriber_import =
+                    SubscriberImport::new(&pool, &email_policy, list_id, mode);
+                let mut stream = CsvStream::new();
+                let mut records = Vec::new();
+
+                while let Some(chunk) = field.next().await {
+                    stream.feed(&chunk?, &mut records);
+                    for record in records.drain(..) {
+                        subscriber_import.process_record(record).await?;
+                    }
+                }
+
+                stream.finish(&mut records);
+                for record in records.drain(..) {
+                    subscriber_import.process_record(record).await?;
+                }
+                subscriber_import.flush().await;
+
+                import = Some(subscriber_import);
+            }
+            _ => {}
+        }
+    }
+
+    let import = import.ok_or_else(|| e400("No CSV file has been uploaded"))?;
+
+    tracing::info!(
+        n_imported = import.n_imported,
+        n_rejected = import.errors.len(),
+        "Finished importing subscribers"
+    );
+
+    let report_html = import.report_html();
+
+    // Large imports would keep the admin waiting on the rate limiter:
+    // confirmation emails go out once the report has been sent back.
+    if !import.confirmations.is_empty() {
+        tokio::spawn(send_confirmation_emails(
+            pool.clone(),
+            email_client,
+            base_url,
+            hmac_secret,
+            import.confirmations,
+        ));
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(report_html))
+}
+
+#[tracing::instrument(
+    name = "Send confirmation emails to imported subscribers",
+    skip_all,
+    fields(n_subscribers = confirmations.len())
+)]
+async fn send_confirmation_emails(
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    confirmations: Vec<PendingConfirmation>,
+) {
+    let layout = match get_default_layout(&pool).await {
+        Ok(layout) => layout,
+        Err(e) => {
+            tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to retrieve the default email layout, no confirmation email has been sent"
+            );
+            return;
+        }
+    };
+    let confirmation_email = ConfirmationEmail {
+        base_url: &base_url.0,
+        hmac_secret: &hmac_secret.0,
+        layout,
+    };
+
+    // Subscribers who do not get their email can subscribe again through
+    // the form to receive a new one.
+    for c in confirmations {
+        if let Err(e) = send_confirmation_email(
+            &email_client,
+            &confirmation_email,
+            c.subscriber,
+            c.subscriber_id,
+            &c.subscription_token,
+        )
+        .await
+        {
+            tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            subscriber_id = %c.subscriber_id,
+            "Failed to send a confirmation email to an imported subscriber"
+            );
+        }
+    }
+}
+
+async fn read_text_field(field: &mut Field) -> Result<String, actix_web::Error> {
+    let mut bytes = Vec::new();
+
+    while let Some(chunk) = field.next().await {
+        bytes.extend_from_slice(&chunk?);
+    }
+
+    String::from_utf8(bytes).map_err(e400)
+}
+
+struct RowError {
+    row: usize,
+    email: String,
+    reason: String,
+}
+
+struct ImportedRow {
+    row: usize,
+    subscriber_id: Uuid,
+    subscriber: NewSubscriber,
+    tags: Vec<SubscriberTag>,
+}
+
+/// An imported subscriber still to be sent a confirmation email.
+struct PendingConfirmation {
+    subscriber: NewSubscriber,
+    subscriber_id: Uuid,
+    subscription_token: String,
+}
+
+struct SubscriberImport<'a> {
+    pool: &'a PgPool,
+    email_policy: &'a EmailPolicy,
+    list_id: Uuid,
+    mode: ImportMode,
+    columns: Option<Columns>,
+    n_rows: usize,
+    n_imported: usize,
+    batch: Vec<ImportedRow>,
+    errors: Vec<RowError>,
+    confirmations: Vec<PendingConfirmation>,
+}
+
+impl<'a> SubscriberImport<'a> {
+    fn new(
+        pool: &'a PgPool,
+        email_policy: &'a EmailPolicy,
+        list_id: Uuid,
+        mode: ImportMode,
+    ) -> Self {
+        Self {
+            pool,
+            email_policy,
+            list_id,
+            mode,
+            columns: None,
+            n_rows: 0,
+            n_imported: 0,
+            batch: Vec::with_capacity(BATCH_SIZE),
+            errors: Vec::new(),
+            confirmations: Vec::new(),
+        }
+    }
+
+    async fn process_record(&mut self, record: CsvRecord) -> Result<(), actix_web::Error> {
+        let columns = match self.columns {
+            Some(columns) => columns,
+            None => {
+                let header = CsvHeader::parse(record.map_err(e400)?);
+                let (email, name) = header
+                    .position("email")
+                    .zip(header.position("name"))
+                    .ok_or_else(|| e400("The CSV file must have an `email` and a `name` column"))?;
+                self.columns = Some(Columns {
+                    email,
+                    name,
+                    tags: header.position("tags"),
+                });
+                return Ok(());
+            }
+        };
+
+        self.n_rows += 1;
+        let row = self.n_rows;
+
+        let fields = match record {
+            Ok(fields) => fields,
+            Err(reason) => {
+                self.errors.push(RowError {
+                    row,
+                    email: String::new(),
+                    reason,
+                });
+                return Ok(());
+            }
+        };
+
+        let csv_row = CsvRow {
+            email: fields.get(columns.email).cloned().unwrap_or_default(),
+            name: fields.get(columns.name).cloned().unwrap_or_default(),
+        };
+        let email = csv_row.email.clone();
+        let tags = columns
+            .tags
+            .and_then(|c| fields.get(c))
+            .map(String::as_str)
+            .unwrap_or_default();
+
+        let parsed = NewSubscriber::try_from(csv_row).and_then(|subscriber| {
+            self.email_policy.check(&subscriber.email)?;
+            Ok((subscriber, SubscriberTag::parse_list(tags)?))
+        });
+
+        match parsed {
+            Ok((subscriber, tags)) => {
+                self.batch.push(ImportedRow {
+                    row,
+                    subscriber_id: Uuid::new_v4(),
+                    subscriber,
+                    tags,
+                });
+                if self.batch.len() >= BATCH_SIZE {
+                    self.flush().await;
+                }
+            }
+            Err(reason) => self.errors.push(RowError { row, email, reason }),
+        }
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) {
+        let batch = std::mem::take(&mut self.batch);
+
+        if batch.is_empty() {
+            return;
+        }
+
+        let suppressed = {
+            let emails: Vec<&str> = batch.iter().map(|r| r.subscriber.email.as_ref()).collect();
+            find_suppressed(self.pool, &emails).await
+        };
+        let suppressed = match suppressed {
+            Ok(suppressed) => suppressed,
+            Err(e) => return self.reject_batch(batch, e),
+        };
+
+        let (suppressed_rows, batch): (Vec<_>, Vec<_>) = batch
+            .into_iter()
+            .partition(|r| suppressed.contains(r.subscriber.email.as_ref()));
+
+        for r in suppressed_rows {
+            self.errors.push(RowError {
+                row: r.row,
+                email: r.subscriber.email.as_ref().to_owned(),
+                reason: "The address is on the suppression list".into(),
+            });
+        }
+
+        let mut stored = match store_batch(self.pool, &batch, self.list_id, self.mode).await {
+            Ok(stored) => stored,
+            Err(e) => return self.reject_batch(batch, e),
+        };
+
+        for r in batch {
+            let email = r.subscriber.email.as_ref().to_owned();
+
+            match stored.remove(&r.subscriber_id) {
+                None => self.errors.push(RowError {
+                    row: r.row,
+                    email,
+                    reason: "The email is already subscribed".into(),
+                }),
+                Some(None) => self.n_imported += 1,
+                Some(Some(subscription_token)) => {
+                    self.n_imported += 1;
+                    self.confirmations.push(PendingConfirmation {
+                        subscriber: r.subscriber,
+                        subscriber_id: r.subscriber_id,
+                        subscription_token,
+                    });
+                }
+            }
+        }
+    }
+
+    fn reject_batch(&mut self, batch: Vec<ImportedRow>, e: sqlx::Error) {
+        tracing::error!(
+        error.cause_chain = ?e,
+        error.message = %e,
+        "Failed to store a batch of imported subscribers"
+        );
+
+        for r in batch {
+            self.errors.push(RowError {
+                row: r.row,
+                email: r.subscriber.email.as_ref().to_owned(),
+                reason: "Failed to store the subscriber".into(),
+            });
+        }
+    }
+
+    fn report_html(&self) -> String {
+        let mut errors_html = String::new();
+
+        for e in &self.errors {
+            writeln!(
+                errors_html,
+                "<tr><td>{}</td><td>{}</td><td>{}</td></tr>",
+                e.row,
+                htmlescape::encode_minimal(&e.email),
+                htmlescape::encode_minimal(&e.reason)
+            )
+            .unwrap();
+        }
+
+        let n_imported = self.n_imported;
+        let n_rows = self.n_rows;
+        let confirmations_html = match self.confirmations.len() {
+            0 => String::new(),
+            n => format!("<p>Confirmation emails are being sent to {n} new subscribers.</p>"),
+        };
+
+        format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Import report</title>
+</head>
+<body>
+    <p>Imported {n_imported} out of {n_rows} subscribers.</p>
+    {confirmations_html}
+    <table>
+        <thead>
+            <tr><th>Row</th><th>Email</th><th>Error</th></tr>
+        </thead>
+        <tbody>
+            {errors_html}
+        </tbody>
+    </table>
+    <p><a href="/admin/subscribers/import">&lt;- Back</a></p>
+</body>
+</html>"#
+        )
+    }
+}
+
+/// Insert a batch of subscribers within a single transaction.
+///
+/// Returns the ids of the subscribers that have been inserted - rows whose email
+/// is already subscribed are skipped - alongside their confirmation token, if
+/// the import mode requires one. Every address in the batch, new or not, ends
+/// up subscribed to the list and tagged with the tags of its row - except for
+/// those who unsubscribed from everything: an import must not undo it.
+#[tracing::instrument(skip_all, fields(batch_size = batch.len()))]
+async fn store_batch(
+    pool: &PgPool,
+    batch: &[ImportedRow],
+    list_id: Uuid,
+    mode: ImportMode,
+) -> Result<HashMap<Uuid, Option<String>>, sqlx::Error> {
+    let ids: Vec<Uuid> = batch.iter().map(|r| r.subscriber_id).collect();
+    let emails: Vec<String> = batch
+        .iter()
+        .map(|r| r.subscriber.email.as_ref().to_owned())
+        .collect();
+    let email_keys: Vec<String> = batch.iter().map(|r| r.subscriber.email.key()).collect();
+    let names: Vec<String> = batch
+        .iter()
+        .map(|r| r.subscriber.name.as_ref().to_owned())
+        .collect();
+
+    let mut transaction = pool.begin().await?;
+
+    let inserted_ids: Vec<Uuid> = sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, email_key, name, subscribe_at, status)
+        SELECT id, email, email_key, name, now(), $5
+        FROM UNNEST($1::uuid[], $2::text[], $3::text[], $4::text[]) AS s(id, email, email_key, name)
+        ON CONFLICT (email_key) DO NOTHING
+        RETURNING id
+        "#,
+        &ids[..],
+        &emails[..],
+        &email_keys[..],
+        &names[..],
+        mode.status()
+    )
+    .fetch_all(&mut transaction)
+    .await?
+    .into_iter()
+    .map(|r| r.id)
+    .collect();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (list_id, subscriber_id, subscribed_at)
+        SELECT $1, id, now()
+        FROM subscriptions
+        WHERE
+            email_key = ANY($2) AND
+            status <> 'unsubscribed'
+        ON CONFLICT DO NOTHING
+        "#,
+        list_id,
+        &email_keys[..]
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    let (tagged_keys, tags): (Vec<String>, Vec<String>) = batch
+        .iter()
+        .flat_map(|r| {
+            r.tags
+                .iter()
+                .map(move |t| (r.subscriber.email.key(), t.as_ref().to_owned()))
+        })
+        .unzip();
+
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag, created_at)
+        SELECT s.id, t.tag, now()
+        FROM UNNEST($1::text[], $2::text[]) AS t(email_key, tag)
+        JOIN subscriptions s ON s.email_key = t.email_key
+        WHERE s.status <> 'unsubscribed'
+        ON CONFLICT DO NOTHING
+        "#,
+        &tagged_keys[..],
+        &tags[..]
+    )
+    .execute(&mut transaction)
+    .await?;
+
+    let stored = match mode {
+        ImportMode::Confirmed => inserted_ids.into_iter().map(|id| (id, None)).collect(),
+        ImportMode::SendConfirmation => {
+            let tokens: Vec<String> = inserted_ids
+                .iter()
+                .map(|_| generate_subscription_token())
+                .collect();
+
+            sqlx::query!(
+                r#"
+                INSERT INTO subscription_tokens (subscription_token, subscriber_id)
+                SELECT * FROM UNNEST($1::text[], $2::uuid[])
+                "#,
+                &tokens[..],
+                &inserted_ids[..]
+            )
+            .execute(&mut transaction)
+            .await?;
+
+            inserted_ids
+                .into_iter()
+                .zip(tokens.into_iter().map(Some))
+                .collect()
+        }
+    };
+
+    transaction.commit().await?;
+
+    Ok(stored)
+}
@@ -0,0 +1,159 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{
+    domain::{SubscriberEmail, SubscriberTag},
+    utils::{e500, see_other},
+};
+
+pub async fn subscriber_tags_form(
+    flash_message: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+
+    for m in flash_message.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Tag subscribers</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/admin/subscribers/tags" method="post">
+        <label>Email
+            <input type="text" placeholder="Enter the subscriber email" name="email">
+        </label>
+        <label>Tags
+            <input type="text" placeholder="beta region:eu" name="tags">
+        </label>
+        <label>Action
+            <select name="action">
+                <option value="add">Add tags</option>
+                <option value="remove">Remove tags</option>
+            </select>
+        </label>
+        <button type="submit">Update tags</button>
+    </form>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TagAction {
+    Add,
+    Remove,
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+    tags: String,
+    action: TagAction,
+}
+
+#[tracing::instrument(
+    name = "Update the tags of a subscriber",
+    skip(form, pool),
+    fields(email = %form.email, tags = %form.tags)
+)]
+pub async fn update_subscriber_tags(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let FormData {
+        email,
+        tags,
+        action,
+    } = form.0;
+
+    let parsed = SubscriberEmail::parse(email).and_then(|email| {
+        let tags = SubscriberTag::parse_list(&tags)?;
+        if tags.is_empty() {
+            return Err("Enter at least one tag.".to_owned());
+        }
+        Ok((email, tags))
+    });
+    let (email, tags) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other("/admin/subscribers/tags"));
+        }
+    };
+
+    let subscriber_id = match get_subscriber_id(&pool, &email).await.map_err(e500)? {
+        Some(subscriber_id) => subscriber_id,
+        None => {
+            FlashMessage::error(format!(
+                "{} is not subscribed.",
+                htmlescape::encode_minimal(email.as_ref())
+            ))
+            .send();
+            return Ok(see_other("/admin/subscribers/tags"));
+        }
+    };
+
+    let tags: Vec<String> = tags.iter().map(|t| t.as_ref().to_owned()).collect();
+
+    match action {
+        TagAction::Add => sqlx::query!(
+            r#"
+            INSERT INTO subscriber_tags (subscriber_id, tag, created_at)
+            SELECT $1, tag, now()
+            FROM UNNEST($2::text[]) AS t(tag)
+            ON CONFLICT DO NOTHING
+            "#,
+            subscriber_id,
+            &tags[..]
+        )
+        .execute(pool.get_ref())
+        .await
+        .context("Failed to tag a subscriber"),
+        TagAction::Remove => sqlx::query!(
+            r#"DELETE FROM subscriber_tags WHERE subscriber_id = $1 AND tag = ANY($2)"#,
+            subscriber_id,
+            &tags[..]
+        )
+        .execute(pool.get_ref())
+        .await
+        .context("Failed to untag a subscriber"),
+    }
+    .map_err(e500)?;
+
+    FlashMessage::info(format!(
+        "The tags of {} have been updated.",
+        htmlescape::encode_minimal(email.as_ref())
+    ))
+    .send();
+    Ok(see_other("/admin/subscribers/tags"))
+}
+
+#[tracing::instrument(name = "Get subscriber_id from email", skip(pool, email))]
+async fn get_subscriber_id(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE email_key = $1"#,
+        email.key()
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a subscriber by email")?;
+
+    Ok(row.map(|r| r.id))
+}
@@ -0,0 +1,118 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::utils::e500;
+
+struct Suppression {
+    email_hash: String,
+    email: Option<String>,
+    reason: String,
+    created_at: DateTime<Utc>,
+}
+
+pub async fn suppressions_page(
+    pool: web::Data<PgPool>,
+    flash_message: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+
+    for m in flash_message.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let suppressions = get_latest_suppressions(&pool).await.map_err(e500)?;
+    let mut suppressions_html = String::new();
+
+    for s in suppressions {
+        let email = s
+            .email
+            .as_deref()
+            .map(htmlescape::encode_minimal)
+            .unwrap_or_else(|| "<i>erased</i>".into());
+
+        writeln!(
+            suppressions_html,
+            r#"<tr>
+                <td>{email}</td>
+                <td>{}</td>
+                <td>{}</td>
+                <td>
+                    <form action="/admin/suppressions/remove" method="post">
+                        <input hidden type="text" name="email_hash" value="{}">
+                        <button type="submit">Remove</button>
+                    </form>
+                </td>
+            </tr>"#,
+            s.reason,
+            s.created_at.to_rfc3339(),
+            s.email_hash,
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Suppression list</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/admin/suppressions" method="post">
+        <label>Email
+            <input type="text" placeholder="Enter email" name="email">
+        </label>
+        <label>Reason
+            <select name="reason">
+                <option value="manual">Manual</option>
+                <option value="hard_bounce">Hard bounce</option>
+                <option value="complaint">Complaint</option>
+            </select>
+        </label>
+        <button type="submit">Suppress</button>
+    </form>
+    <form action="/admin/suppressions/import" method="post" enctype="multipart/form-data">
+        <label>Import a CSV file with an <code>email</code> column
+            <input type="file" name="file" accept=".csv,text/csv">
+        </label>
+        <button type="submit">Import</button>
+    </form>
+    <p>Most recent entries:</p>
+    <table>
+        <thead>
+            <tr><th>Email</th><th>Reason</th><th>Suppressed at</th><th></th></tr>
+        </thead>
+        <tbody>
+            {suppressions_html}
+        </tbody>
+    </table>
+    <p><a href="/admin/dashboard">&lt;- Back</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_latest_suppressions(pool: &PgPool) -> Result<Vec<Suppression>, anyhow::Error> {
+    let suppressions = sqlx::query_as!(
+        Suppression,
+        r#"
+        SELECT email_hash, email, reason, created_at
+        FROM suppressions
+        ORDER BY created_at DESC
+        LIMIT 100
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the suppression list")?;
+
+    Ok(suppressions)
+}
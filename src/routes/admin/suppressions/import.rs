@@ -0,0 +1,137 @@
+use actix_multipart::Multipart;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use futures_util::StreamExt;
+use sqlx::PgPool;
+
+use crate::{
+    csv_stream::{CsvHeader, CsvRecord, CsvStream},
+    domain::SubscriberEmail,
+    suppression::{suppress_batch, SuppressionReason},
+    utils::{e400, e500, see_other},
+};
+
+/// How many addresses are inserted with a single statement.
+const BATCH_SIZE: usize = 1000;
+
+/// Import a suppression list exported from another provider.
+#[tracing::instrument(name = "Import a suppression list", skip(payload, pool))]
+pub async fn import_suppressions(
+    mut payload: Multipart,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut import = SuppressionImport::new(&pool);
+
+    while let Some(field) = payload.next().await {
+        let mut field = field?;
+
+        if field.content_disposition().get_name() != Some("file") {
+            continue;
+        }
+
+        let mut stream = CsvStream::new();
+        let mut records = Vec::new();
+
+        while let Some(chunk) = field.next().await {
+            stream.feed(&chunk?, &mut records);
+            for record in records.drain(..) {
+                import.process_record(record).await?;
+            }
+        }
+
+        stream.finish(&mut records);
+        for record in records.drain(..) {
+            import.process_record(record).await?;
+        }
+        import.flush().await?;
+    }
+
+    FlashMessage::info(format!(
+        "{} addresses have been added to the suppression list.",
+        import.n_suppressed
+    ))
+    .send();
+
+    if !import.rejected_rows.is_empty() {
+        let rows: Vec<String> = import.rejected_rows.iter().map(usize::to_string).collect();
+        FlashMessage::error(format!(
+            "Rows with an invalid email have been skipped: {}.",
+            rows.join(", ")
+        ))
+        .send();
+    }
+
+    Ok(see_other("/admin/suppressions"))
+}
+
+struct SuppressionImport<'a> {
+    pool: &'a PgPool,
+    // Position of the `email` column, known once the header has been read
+    email_column: Option<usize>,
+    n_rows: usize,
+    n_suppressed: u64,
+    batch: Vec<String>,
+    rejected_rows: Vec<usize>,
+}
+
+impl<'a> SuppressionImport<'a> {
+    fn new(pool: &'a PgPool) -> Self {
+        Self {
+            pool,
+            email_column: None,
+            n_rows: 0,
+            n_suppressed: 0,
+            batch: Vec::with_capacity(BATCH_SIZE),
+            rejected_rows: Vec::new(),
+        }
+    }
+
+    async fn process_record(&mut self, record: CsvRecord) -> Result<(), actix_web::Error> {
+        let email_column = match self.email_column {
+            Some(column) => column,
+            None => {
+                let header = CsvHeader::parse(record.map_err(e400)?);
+                self.email_column = Some(
+                    header
+                        .position("email")
+                        .ok_or_else(|| e400("The CSV file must have an `email` column"))?,
+                );
+                return Ok(());
+            }
+        };
+
+        self.n_rows += 1;
+
+        let email = record
+            .ok()
+            .and_then(|fields| fields.get(email_column).cloned())
+            .ok_or_else(|| "Missing email".to_string())
+            .and_then(SubscriberEmail::parse);
+
+        match email {
+            Ok(email) => {
+                self.batch.push(email.as_ref().to_owned());
+                if self.batch.len() >= BATCH_SIZE {
+                    self.flush().await?;
+                }
+            }
+            Err(_) => self.rejected_rows.push(self.n_rows),
+        }
+
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), actix_web::Error> {
+        let batch = std::mem::take(&mut self.batch);
+
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        self.n_suppressed += suppress_batch(self.pool, &batch, SuppressionReason::Imported)
+            .await
+            .map_err(e500)?;
+
+        Ok(())
+    }
+}
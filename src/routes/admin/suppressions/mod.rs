@@ -0,0 +1,7 @@
+mod get;
+mod import;
+mod post;
+
+pub use get::suppressions_page;
+pub use import::import_suppressions;
+pub use post::{add_suppression, remove_suppression};
@@ -0,0 +1,81 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::{
+    domain::SubscriberEmail,
+    suppression::{suppress, unsuppress, SuppressionReason},
+    utils::{e400, e500, see_other},
+};
+
+#[derive(serde::Deserialize)]
+pub struct AddFormData {
+    email: String,
+    reason: String,
+}
+
+#[tracing::instrument(
+    name = "Suppress an address",
+    skip(form, pool),
+    fields(email = %form.email, reason = %form.reason)
+)]
+pub async fn add_suppression(
+    form: web::Form<AddFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let AddFormData { email, reason } = form.0;
+
+    let reason = SuppressionReason::try_from(reason).map_err(e400)?;
+
+    let email = match SubscriberEmail::parse(email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other("/admin/suppressions"));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+
+    suppress(&mut transaction, email.as_ref(), reason)
+        .await
+        .context("Failed to suppress an address")
+        .map_err(e500)?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to suppress an address")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!(
+        "{} has been suppressed.",
+        htmlescape::encode_minimal(email.as_ref())
+    ))
+    .send();
+    Ok(see_other("/admin/suppressions"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RemoveFormData {
+    email_hash: String,
+}
+
+#[tracing::instrument(name = "Lift a suppression", skip(form, pool))]
+pub async fn remove_suppression(
+    form: web::Form<RemoveFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    unsuppress(&pool, &form.email_hash)
+        .await
+        .context("Failed to remove an address from the suppression list")
+        .map_err(e500)?;
+
+    FlashMessage::info("The suppression has been lifted.").send();
+    Ok(see_other("/admin/suppressions"))
+}
@@ -1,7 +1,50 @@
-use actix_web::{http::header::ContentType, HttpResponse};
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use sqlx::PgPool;
 
-pub async fn home() -> HttpResponse {
-    HttpResponse::Ok()
+use crate::{
+    lists::{get_lists, MailingList, DEFAULT_LIST_SLUG},
+    utils::e500,
+};
+
+pub async fn home(pool: web::Data<PgPool>) -> Result<HttpResponse, actix_web::Error> {
+    let lists = get_lists(&pool).await.map_err(e500)?;
+    let list_options_html = MailingList::options_html(&lists, DEFAULT_LIST_SLUG);
+
+    Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(include_str!("home.html"))
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="pl">
+  <head>
+    <meta charset="UTF-8" />
+    <meta http-equiv="X-UA-Compatible" content="IE=edge" />
+    <meta name="viewport" content="width=device-width, initial-scale=1.0" />
+    <meta http-equiv="content-type" content="text/html; charset=utf-8" />
+    <title>Home</title>
+  </head>
+  <body>
+    <p>Welcome to our newsletter!</p>
+    <form action="/subscriptions" method="post">
+      <label>
+        Name
+        <input type="text" placeholder="Enter your name" name="name" />
+      </label>
+
+      <label>
+        Email
+        <input type="email" placeholder="Enter your email" name="email" />
+      </label>
+
+      <label>
+        Newsletter
+        <select name="list">
+          {list_options_html}
+        </select>
+      </label>
+
+      <button type="submit">Subscribe</button>
+    </form>
+  </body>
+</html>"#
+        )))
 }
@@ -0,0 +1,116 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::utils::e500;
+
+const PAGE_SIZE: i64 = 20;
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    /// Starts at 1, the most recent issues come first.
+    page: Option<i64>,
+}
+
+#[tracing::instrument(name = "Show the archive of newsletter issues", skip(query, pool))]
+pub async fn issues_archive(
+    query: web::Query<QueryParams>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let page = query.page.unwrap_or(1).max(1);
+    // No archive is large enough to reach a page whose offset overflows
+    let offset = match page.checked_sub(1).and_then(|p| p.checked_mul(PAGE_SIZE)) {
+        Some(offset) => offset,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let mut issues = get_archived_issues(&pool, offset).await.map_err(e500)?;
+    // One more issue than displayed is fetched to know whether there is a next page
+    let has_next_page = issues.len() as i64 > PAGE_SIZE;
+    issues.truncate(PAGE_SIZE as usize);
+
+    let mut issues_html = String::new();
+
+    for i in &issues {
+        writeln!(
+            issues_html,
+            r#"<li><a href="/issues/{}">{}</a> - {}</li>"#,
+            htmlescape::encode_attribute(&i.slug),
+            htmlescape::encode_minimal(&i.title),
+            i.published_at.format("%Y-%m-%d")
+        )
+        .unwrap();
+    }
+
+    if issues.is_empty() {
+        issues_html.push_str("<li>No issues yet.</li>");
+    }
+
+    let mut pagination_html = String::new();
+
+    if page > 1 {
+        write!(
+            pagination_html,
+            r#"<a href="/issues?page={}">Newer issues</a> "#,
+            page - 1
+        )
+        .unwrap();
+    }
+    if has_next_page {
+        write!(
+            pagination_html,
+            r#"<a href="/issues?page={}">Older issues</a>"#,
+            page + 1
+        )
+        .unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Newsletter archive</title>
+</head>
+<body>
+    <h1>Newsletter archive</h1>
+    <ul>
+        {issues_html}
+    </ul>
+    <p>{pagination_html}</p>
+    <p><a href="/">Subscribe</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+struct ArchivedIssue {
+    slug: String,
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_archived_issues(
+    pool: &PgPool,
+    offset: i64,
+) -> Result<Vec<ArchivedIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT slug, title, published_at
+        FROM newsletter_issues
+        WHERE in_archive
+        ORDER BY published_at DESC, newsletter_issue_id
+        LIMIT $1
+        OFFSET $2
+        "#,
+        PAGE_SIZE + 1,
+        offset
+    )
+    .fetch_all(pool)
+    .await
+}
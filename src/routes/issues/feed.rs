@@ -0,0 +1,282 @@
+use std::fmt::Write;
+use std::time::{Duration, SystemTime};
+
+use actix_web::{
+    http::header::{self, ContentType, EntityTag, Header, HttpDate, IfModifiedSince, IfNoneMatch},
+    web, HttpRequest, HttpResponse, HttpResponseBuilder,
+};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{archive_html, archive_text};
+use crate::{startup::ApplicationBaseUrl, utils::e500};
+
+/// How many of the most recent issues feeds carry.
+const FEED_SIZE: i64 = 20;
+const SUMMARY_LENGTH: usize = 200;
+
+const FEED_TITLE: &str = "zero2prod newsletter";
+
+#[tracing::instrument(name = "Serve the RSS feed of newsletter issues", skip_all)]
+pub async fn rss_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let versions = get_feed_versions(&pool).await.map_err(e500)?;
+    let validators = FeedValidators::new("rss", &versions);
+    if validators.are_fresh(&request) {
+        return Ok(validators.not_modified());
+    }
+
+    let issues = get_feed_issues(&pool).await.map_err(e500)?;
+    let base_url = &base_url.0;
+
+    let mut items_xml = String::new();
+
+    for i in &issues {
+        let link = format!("{}/issues/{}", base_url, i.slug);
+        writeln!(
+            items_xml,
+            r#"<item>
+    <title>{}</title>
+    <link>{}</link>
+    <guid isPermaLink="false">urn:uuid:{}</guid>
+    <pubDate>{}</pubDate>
+    <description>{}</description>
+    <content:encoded>{}</content:encoded>
+</item>"#,
+            escape(&i.title),
+            escape(&link),
+            i.newsletter_issue_id,
+            i.published_at.to_rfc2822(),
+            escape(&summary(&i.text_content)),
+            escape(&archive_html(&i.html_content))
+        )
+        .unwrap();
+    }
+
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:content="http://purl.org/rss/1.0/modules/content/">
+<channel>
+<title>{title}</title>
+<link>{base_url}/issues</link>
+<description>Past issues of the {title}</description>
+<atom:link href="{base_url}/feed.rss" rel="self" type="application/rss+xml" />
+{items_xml}</channel>
+</rss>"#,
+        title = FEED_TITLE,
+        base_url = escape(base_url),
+    );
+
+    Ok(validators.ok(body, "application/rss+xml; charset=utf-8"))
+}
+
+#[tracing::instrument(name = "Serve the Atom feed of newsletter issues", skip_all)]
+pub async fn atom_feed(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let versions = get_feed_versions(&pool).await.map_err(e500)?;
+    let validators = FeedValidators::new("atom", &versions);
+    if validators.are_fresh(&request) {
+        return Ok(validators.not_modified());
+    }
+
+    let issues = get_feed_issues(&pool).await.map_err(e500)?;
+    let base_url = &base_url.0;
+
+    let updated = issues
+        .first()
+        .map(|i| i.published_at)
+        .unwrap_or_else(|| DateTime::<Utc>::from(SystemTime::UNIX_EPOCH));
+
+    let mut entries_xml = String::new();
+
+    for i in &issues {
+        let link = format!("{}/issues/{}", base_url, i.slug);
+        writeln!(
+            entries_xml,
+            r#"<entry>
+    <title>{}</title>
+    <link href="{}" />
+    <id>urn:uuid:{}</id>
+    <updated>{}</updated>
+    <summary>{}</summary>
+    <content type="html">{}</content>
+</entry>"#,
+            escape(&i.title),
+            escape(&link),
+            i.newsletter_issue_id,
+            i.published_at.to_rfc3339(),
+            escape(&summary(&i.text_content)),
+            escape(&archive_html(&i.html_content))
+        )
+        .unwrap();
+    }
+
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<title>{title}</title>
+<id>{base_url}/issues</id>
+<link href="{base_url}/issues" />
+<link href="{base_url}/feed.atom" rel="self" />
+<updated>{updated}</updated>
+<author><name>zero2prod</name></author>
+{entries_xml}</feed>"#,
+        title = FEED_TITLE,
+        base_url = escape(base_url),
+        updated = updated.to_rfc3339(),
+    );
+
+    Ok(validators.ok(body, "application/atom+xml; charset=utf-8"))
+}
+
+/// The validators of a feed, derived from which issues it carries rather than
+/// from its body: a conditional request is answered without rendering it.
+/// Published issues do not change, a new one changes the feed.
+struct FeedValidators {
+    etag: EntityTag,
+    last_modified: Option<HttpDate>,
+}
+
+impl FeedValidators {
+    /// `feed_kind` tells apart the feeds of the same issues.
+    fn new(feed_kind: &str, versions: &[FeedVersion]) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(feed_kind.as_bytes());
+        for v in versions {
+            hasher.update(format!(
+                "\n{} {}",
+                v.newsletter_issue_id,
+                v.published_at.to_rfc3339()
+            ));
+        }
+        let etag = EntityTag::new_strong(format!("{:x}", hasher.finalize()));
+        // HTTP dates have a one second resolution
+        let last_modified = versions.first().map(|v| {
+            HttpDate::from(
+                SystemTime::UNIX_EPOCH + Duration::from_secs(v.published_at.timestamp() as u64),
+            )
+        });
+
+        Self {
+            etag,
+            last_modified,
+        }
+    }
+
+    /// Whether the client already has the latest version of the feed,
+    /// according to either `If-None-Match` or `If-Modified-Since`.
+    fn are_fresh(&self, request: &HttpRequest) -> bool {
+        // `If-Modified-Since` is ignored when `If-None-Match` is present
+        if request.headers().contains_key(header::IF_NONE_MATCH) {
+            match IfNoneMatch::parse(request) {
+                Ok(IfNoneMatch::Any) => true,
+                Ok(IfNoneMatch::Items(etags)) => etags.iter().any(|e| e.weak_eq(&self.etag)),
+                Err(_) => false,
+            }
+        } else {
+            match (IfModifiedSince::parse(request), self.last_modified) {
+                (Ok(IfModifiedSince(since)), Some(last_modified)) => {
+                    SystemTime::from(last_modified) <= SystemTime::from(since)
+                }
+                _ => false,
+            }
+        }
+    }
+
+    fn not_modified(&self) -> HttpResponse {
+        self.insert_headers(HttpResponse::NotModified()).finish()
+    }
+
+    fn ok(&self, body: String, content_type: &str) -> HttpResponse {
+        self.insert_headers(HttpResponse::Ok())
+            .content_type(ContentType(content_type.parse().unwrap()))
+            .body(body)
+    }
+
+    fn insert_headers(&self, mut response: HttpResponseBuilder) -> HttpResponseBuilder {
+        response.insert_header(header::ETag(self.etag.clone()));
+        if let Some(last_modified) = self.last_modified {
+            response.insert_header(header::LastModified(last_modified));
+        }
+        response
+    }
+}
+
+/// The beginning of the plain-text content, cut on a word boundary.
+fn summary(text_content: &str) -> String {
+    let text = archive_text(text_content);
+    let text = text.trim();
+
+    if text.chars().count() <= SUMMARY_LENGTH {
+        return text.to_owned();
+    }
+
+    let cut: String = text.chars().take(SUMMARY_LENGTH).collect();
+    let cut = match cut.rfind(char::is_whitespace) {
+        Some(i) => &cut[..i],
+        None => &cut,
+    };
+    format!("{}…", cut.trim_end())
+}
+
+fn escape(s: &str) -> String {
+    htmlescape::encode_minimal(s)
+}
+
+struct FeedIssue {
+    newsletter_issue_id: Uuid,
+    slug: String,
+    title: String,
+    text_content: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+/// What the validators of a feed are derived from, see `FeedValidators`.
+struct FeedVersion {
+    newsletter_issue_id: Uuid,
+    published_at: DateTime<Utc>,
+}
+
+/// The issues a feed carries, without their content.
+#[tracing::instrument(skip(pool))]
+async fn get_feed_versions(pool: &PgPool) -> Result<Vec<FeedVersion>, sqlx::Error> {
+    sqlx::query_as!(
+        FeedVersion,
+        r#"
+        SELECT newsletter_issue_id, published_at
+        FROM newsletter_issues
+        WHERE in_archive
+        ORDER BY published_at DESC, newsletter_issue_id
+        LIMIT $1
+        "#,
+        FEED_SIZE
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_feed_issues(pool: &PgPool) -> Result<Vec<FeedIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        FeedIssue,
+        r#"
+        SELECT newsletter_issue_id, slug, title, text_content, html_content, published_at
+        FROM newsletter_issues
+        WHERE in_archive
+        ORDER BY published_at DESC, newsletter_issue_id
+        LIMIT $1
+        "#,
+        FEED_SIZE
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,70 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::archive_html;
+use crate::utils::e500;
+
+#[tracing::instrument(name = "Show an archived newsletter issue", skip(pool))]
+pub async fn archived_issue(
+    id_or_slug: web::Path<String>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue = match get_archived_issue(&pool, &id_or_slug).await.map_err(e500)? {
+        Some(issue) => issue,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let title = htmlescape::encode_minimal(&issue.title);
+    let published_at = issue.published_at.format("%Y-%m-%d");
+    let content_html = archive_html(&issue.html_content);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>{title}</title>
+</head>
+<body>
+    <h1>{title}</h1>
+    <p>Published on {published_at}</p>
+    {content_html}
+    <p><a href="/issues">&lt;- All issues</a></p>
+</body>
+</html>"#,
+        )))
+}
+
+struct ArchivedIssue {
+    title: String,
+    html_content: String,
+    published_at: DateTime<Utc>,
+}
+
+/// Issues are found by id as well as slug, issues kept out of the archive are not.
+#[tracing::instrument(skip(pool))]
+async fn get_archived_issue(
+    pool: &PgPool,
+    id_or_slug: &str,
+) -> Result<Option<ArchivedIssue>, sqlx::Error> {
+    let issue_id = Uuid::parse_str(id_or_slug).ok();
+
+    sqlx::query_as!(
+        ArchivedIssue,
+        r#"
+        SELECT title, html_content, published_at
+        FROM newsletter_issues
+        WHERE
+            in_archive AND
+            (newsletter_issue_id = $1 OR slug = $2)
+        "#,
+        issue_id,
+        id_or_slug
+    )
+    .fetch_optional(pool)
+    .await
+}
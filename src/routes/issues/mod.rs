@@ -0,0 +1,42 @@
+mod archive;
+mod feed;
+mod issue;
+
+pub use archive::issues_archive;
+pub use feed::{atom_feed, rss_feed};
+pub use issue::archived_issue;
+
+use crate::domain::{MergeTemplate, MergeValues};
+
+/// Issues are personalised for each recipient: in the public archive merge
+/// tags are filled with neutral values, so that no one's details or signed
+/// links end up on the page.
+const ARCHIVE_MERGE_VALUES: MergeValues<'static> = MergeValues {
+    name: "reader",
+    email: "your email address",
+    unsubscribe_url: "/",
+};
+
+/// Prepare the HTML of an issue for display within an archive page.
+///
+/// Issues may be full documents (when sent with a layout): only their body is
+/// kept, stripped of anything unsafe. Styles have been inlined, hence survive.
+fn archive_html(html_content: &str) -> String {
+    let html = match MergeTemplate::parse(html_content) {
+        Ok(template) => template.render_html(&ARCHIVE_MERGE_VALUES),
+        Err(_) => html_content.to_owned(),
+    };
+
+    ammonia::Builder::default()
+        .add_generic_attributes(&["style"])
+        .clean(&html)
+        .to_string()
+}
+
+/// Prepare the plain-text content of an issue for the archive.
+fn archive_text(text_content: &str) -> String {
+    match MergeTemplate::parse(text_content) {
+        Ok(template) => template.render_text(&ARCHIVE_MERGE_VALUES),
+        Err(_) => text_content.to_owned(),
+    }
+}
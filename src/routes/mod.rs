@@ -1,16 +1,26 @@
 mod admin;
 mod health_check;
 mod home;
+mod issues;
 mod login;
+mod preferences;
+mod subscriber_data;
 mod subscriptions;
 mod subscriptions_confirmation;
+mod tracking;
+mod webhooks;
 
 pub use admin::*;
 pub use health_check::*;
 pub use home::*;
+pub use issues::*;
 pub use login::*;
+pub use preferences::*;
+pub use subscriber_data::*;
 pub use subscriptions::*;
 pub use subscriptions_confirmation::*;
+pub use tracking::*;
+pub use webhooks::*;
 
 fn error_chain_fmt(
     e: &impl std::error::Error,
@@ -0,0 +1,144 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use super::PreferencesParameters;
+use crate::{startup::HmacSecret, utils::e500};
+
+#[tracing::instrument(
+    name = "Show the subscriber preferences",
+    skip(flash_message, parameters, pool, hmac_secret),
+    fields(subscriber_id = %parameters.subscriber_id)
+)]
+pub async fn preferences_form(
+    flash_message: IncomingFlashMessages,
+    parameters: web::Query<PreferencesParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !parameters.verify(&hmac_secret.0) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let subscriber = match get_subscriber(&pool, parameters.subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    let mut msg_html = String::new();
+
+    for m in flash_message.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    let mut lists_html = String::new();
+
+    for l in get_list_choices(&pool, parameters.subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        writeln!(
+            lists_html,
+            r#"<label><input type="checkbox" name="lists" value="{}"{}> {}</label><br>"#,
+            htmlescape::encode_attribute(&l.slug),
+            if l.subscribed { " checked" } else { "" },
+            htmlescape::encode_minimal(&l.name)
+        )
+        .unwrap();
+    }
+
+    let name = htmlescape::encode_attribute(&subscriber.name);
+    let text_only = if subscriber.text_only { " checked" } else { "" };
+    let subscriber_id = parameters.subscriber_id;
+    let signature = htmlescape::encode_attribute(&parameters.signature);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Your subscription</title>
+</head>
+<body>
+    {msg_html}
+    <form action="/subscriptions/preferences" method="post">
+        <input hidden type="text" name="subscriber_id" value="{subscriber_id}">
+        <input hidden type="text" name="signature" value="{signature}">
+        <label>Name
+            <input type="text" name="name" value="{name}">
+        </label>
+        <p>Send me:</p>
+        {lists_html}
+        <label><input type="checkbox" name="text_only" value="true"{text_only}> Plain-text emails only</label>
+        <br>
+        <button type="submit">Save my preferences</button>
+    </form>
+    <form action="/subscriptions/preferences/unsubscribe" method="post">
+        <input hidden type="text" name="subscriber_id" value="{subscriber_id}">
+        <input hidden type="text" name="signature" value="{signature}">
+        <button type="submit">Unsubscribe from everything</button>
+    </form>
+</body>
+</html>"#,
+        )))
+}
+
+struct Subscriber {
+    name: String,
+    text_only: bool,
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Subscriber>, anyhow::Error> {
+    sqlx::query_as!(
+        Subscriber,
+        r#"SELECT name, text_only FROM subscriptions WHERE id = $1"#,
+        subscriber_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the subscriber")
+}
+
+struct ListChoice {
+    slug: String,
+    name: String,
+    subscribed: bool,
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_list_choices(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<ListChoice>, anyhow::Error> {
+    sqlx::query_as!(
+        ListChoice,
+        r#"
+        SELECT
+            l.slug,
+            l.name,
+            EXISTS (
+                SELECT 1
+                FROM list_subscriptions ls
+                WHERE ls.list_id = l.list_id AND ls.subscriber_id = $1
+            ) AS "subscribed!"
+        FROM lists l
+        ORDER BY l.created_at, l.name
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the mailing lists")
+}
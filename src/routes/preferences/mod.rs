@@ -0,0 +1,56 @@
+mod get;
+mod post;
+mod unsubscribe;
+
+pub use get::preferences_form;
+pub use post::update_preferences;
+pub use unsubscribe::{unsubscribe, unsubscribe_all};
+
+use secrecy::Secret;
+use uuid::Uuid;
+
+use crate::signature::{sign, verify};
+
+/// Identifies the subscriber whose preferences are managed. The signature
+/// proves the link was issued by us, there is no need to log in.
+#[derive(serde::Deserialize)]
+pub struct PreferencesParameters {
+    subscriber_id: Uuid,
+    signature: String,
+}
+
+impl PreferencesParameters {
+    fn verify(&self, hmac_secret: &Secret<String>) -> bool {
+        verify(
+            hmac_secret,
+            signed_payload(self.subscriber_id).as_bytes(),
+            &self.signature,
+        )
+    }
+
+    fn query_string(&self) -> String {
+        format!(
+            "subscriber_id={}&signature={}",
+            self.subscriber_id,
+            urlencoding::encode(&self.signature)
+        )
+    }
+}
+
+fn signed_payload(subscriber_id: Uuid) -> String {
+    format!("preferences:{}", subscriber_id)
+}
+
+/// Build the signed link to the preference centre of a subscriber.
+pub fn preferences_link(
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    subscriber_id: Uuid,
+) -> String {
+    let signature = sign(hmac_secret, signed_payload(subscriber_id).as_bytes());
+
+    format!(
+        "{}/subscriptions/preferences?subscriber_id={}&signature={}",
+        base_url, subscriber_id, signature
+    )
+}
@@ -0,0 +1,160 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::PreferencesParameters;
+use crate::{
+    domain::SubscriberName,
+    startup::HmacSecret,
+    utils::{e400, e500, see_other},
+};
+
+/// The form has a checkbox per list, all named `lists`: we read the
+/// submitted pairs by hand since `serde_urlencoded` cannot collect
+/// repeated keys.
+struct PreferencesForm {
+    parameters: PreferencesParameters,
+    name: String,
+    lists: Vec<String>,
+    text_only: bool,
+}
+
+impl TryFrom<Vec<(String, String)>> for PreferencesForm {
+    type Error = String;
+
+    fn try_from(pairs: Vec<(String, String)>) -> Result<Self, Self::Error> {
+        let mut subscriber_id = None;
+        let mut signature = None;
+        let mut name = None;
+        let mut lists = Vec::new();
+        let mut text_only = false;
+
+        for (key, value) in pairs {
+            match key.as_str() {
+                "subscriber_id" => {
+                    subscriber_id = Some(Uuid::parse_str(&value).map_err(|e| e.to_string())?)
+                }
+                "signature" => signature = Some(value),
+                "name" => name = Some(value),
+                "lists" => lists.push(value),
+                "text_only" => text_only = value == "true",
+                _ => {}
+            }
+        }
+
+        Ok(Self {
+            parameters: PreferencesParameters {
+                subscriber_id: subscriber_id.ok_or("Missing subscriber_id")?,
+                signature: signature.ok_or("Missing signature")?,
+            },
+            name: name.ok_or("Missing name")?,
+            lists,
+            text_only,
+        })
+    }
+}
+
+#[tracing::instrument(name = "Update subscriber preferences", skip(form, pool, hmac_secret))]
+pub async fn update_preferences(
+    form: web::Form<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let form = PreferencesForm::try_from(form.into_inner()).map_err(e400)?;
+
+    if !form.parameters.verify(&hmac_secret.0) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let preferences_page = format!(
+        "/subscriptions/preferences?{}",
+        form.parameters.query_string()
+    );
+
+    let name = match SubscriberName::parse(form.name) {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(htmlescape::encode_minimal(&e)).send();
+            return Ok(see_other(&preferences_page));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+
+    // Joining a list is no way to opt back in after unsubscribing from
+    // everything: that takes a new, confirmed, subscription.
+    let status = sqlx::query!(
+        r#"SELECT status FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        form.parameters.subscriber_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve the subscriber status")
+    .map_err(e500)?
+    .map(|r| r.status);
+    if status.as_deref() == Some("unsubscribed") && !form.lists.is_empty() {
+        FlashMessage::error(
+            "You have unsubscribed from all our emails, \
+            subscribe again from our home page to join a mailing list.",
+        )
+        .send();
+        return Ok(see_other(&preferences_page));
+    }
+
+    sqlx::query!(
+        r#"UPDATE subscriptions SET name = $2, text_only = $3 WHERE id = $1"#,
+        form.parameters.subscriber_id,
+        name.as_ref(),
+        form.text_only
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to update the subscriber")
+    .map_err(e500)?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM list_subscriptions
+        WHERE
+            subscriber_id = $1 AND
+            list_id NOT IN (SELECT list_id FROM lists WHERE slug = ANY($2))
+        "#,
+        form.parameters.subscriber_id,
+        &form.lists[..]
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to leave mailing lists")
+    .map_err(e500)?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO list_subscriptions (list_id, subscriber_id, subscribed_at)
+        SELECT list_id, $1, now()
+        FROM lists
+        WHERE slug = ANY($2)
+        ON CONFLICT DO NOTHING
+        "#,
+        form.parameters.subscriber_id,
+        &form.lists[..]
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to join mailing lists")
+    .map_err(e500)?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update subscriber preferences")
+        .map_err(e500)?;
+
+    FlashMessage::info("Your preferences have been saved.").send();
+    Ok(see_other(&preferences_page))
+}
@@ -0,0 +1,101 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::PreferencesParameters;
+use crate::{startup::HmacSecret, utils::e500};
+
+#[tracing::instrument(
+    name = "Unsubscribe from all mailing lists",
+    skip(form, pool, hmac_secret),
+    fields(subscriber_id = %form.subscriber_id)
+)]
+pub async fn unsubscribe_all(
+    form: web::Form<PreferencesParameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if !form.verify(&hmac_secret.0) {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+
+    unsubscribe(&mut transaction, form.subscriber_id)
+        .await
+        .map_err(e500)?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to unsubscribe a subscriber")
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Your subscription</title>
+</head>
+<body>
+    <p>You have been unsubscribed, you will not receive any more emails from us.</p>
+</body>
+</html>"#,
+    ))
+}
+
+/// Unsubscribe from everything: mailing lists and pending deliveries.
+///
+/// Returns `false` if there is no such subscriber.
+pub async fn unsubscribe(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let unsubscribed = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'unsubscribed'
+        WHERE id = $1
+        RETURNING email
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .context("Failed to unsubscribe a subscriber")?;
+
+    let subscriber = match unsubscribed {
+        Some(subscriber) => subscriber,
+        None => return Ok(false),
+    };
+
+    sqlx::query!(
+        r#"DELETE FROM list_subscriptions WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to leave mailing lists")?;
+
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE
+            subscriber_email = $1 AND
+            delivered_at IS NULL AND
+            failed_at IS NULL
+        "#,
+        subscriber.email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete pending deliveries")?;
+
+    Ok(true)
+}
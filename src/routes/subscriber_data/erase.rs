@@ -0,0 +1,151 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::{get_subscriber_id_from_data_access_token, DataAccessParameters};
+use crate::{
+    suppression::{suppress, SuppressionReason},
+    utils::e500,
+};
+
+#[tracing::instrument(name = "Erase subscriber data", skip(form, pool))]
+pub async fn erase_subscriber_data(
+    form: web::Form<DataAccessParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = match get_subscriber_id_from_data_access_token(&pool, &form.token)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber_id) => subscriber_id,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")
+        .map_err(e500)?;
+
+    let email = delete_subscriber(&mut transaction, subscriber_id)
+        .await
+        .map_err(e500)?;
+
+    // Keep a hashed record of the address so that it does not come back
+    // with the next bulk import.
+    suppress(&mut transaction, &email, SuppressionReason::Erasure)
+        .await
+        .context("Failed to suppress an erased address")
+        .map_err(e500)?;
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to erase a subscriber")
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Ok().content_type(ContentType::html()).body(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Your data</title>
+</head>
+<body>
+    <p>Your data has been erased.</p>
+</body>
+</html>"#,
+    ))
+}
+
+/// Delete every row referencing the subscriber, returning their email.
+#[tracing::instrument(skip(transaction))]
+async fn delete_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<String, anyhow::Error> {
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete subscription tokens")?;
+
+    sqlx::query!(
+        r#"DELETE FROM data_access_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete data access tokens")?;
+
+    sqlx::query!(
+        r#"DELETE FROM list_subscriptions WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete list subscriptions")?;
+
+    sqlx::query!(
+        r#"DELETE FROM subscriber_tags WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete subscriber tags")?;
+
+    sqlx::query!(
+        r#"DELETE FROM email_events WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete tracked opens and clicks")?;
+
+    let email = sqlx::query!(
+        r#"DELETE FROM subscriptions WHERE id = $1 RETURNING email"#,
+        subscriber_id
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .context("Failed to delete the subscription")?
+    .email;
+
+    // Events reported by the email provider only carry the address
+    sqlx::query!(
+        r#"DELETE FROM email_events WHERE lower(subscriber_email) = lower($1)"#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete email events")?;
+
+    sqlx::query!(
+        r#"DELETE FROM issue_delivery_queue WHERE subscriber_email = $1"#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete pending deliveries")?;
+
+    sqlx::query!(
+        r#"DELETE FROM cancelled_deliveries WHERE subscriber_email = $1"#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete cancelled deliveries")?;
+
+    sqlx::query!(
+        r#"DELETE FROM deliveries WHERE subscriber_email = $1"#,
+        email
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete the send log")?;
+
+    Ok(email)
+}
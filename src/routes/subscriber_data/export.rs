@@ -0,0 +1,291 @@
+use actix_web::{http::header::CONTENT_DISPOSITION, web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::{get_subscriber_id_from_data_access_token, DataAccessParameters};
+use crate::utils::e500;
+
+#[derive(serde::Serialize)]
+struct SubscriberDataExport {
+    subscription: Subscription,
+    lists: Vec<ListSubscription>,
+    tags: Vec<String>,
+    subscription_tokens: Vec<String>,
+    pending_deliveries: Vec<PendingDelivery>,
+    delivery_history: Vec<PastDelivery>,
+    cancelled_deliveries: Vec<CancelledDelivery>,
+    send_attempts: Vec<SendAttempt>,
+    email_events: Vec<EmailEvent>,
+}
+
+#[derive(serde::Serialize)]
+struct Subscription {
+    id: Uuid,
+    email: String,
+    name: String,
+    subscribed_at: String,
+    status: String,
+    text_only: bool,
+}
+
+#[derive(serde::Serialize)]
+struct ListSubscription {
+    slug: String,
+    name: String,
+    subscribed_at: String,
+}
+
+#[derive(serde::Serialize)]
+struct PendingDelivery {
+    newsletter_issue_id: Uuid,
+    title: String,
+}
+
+/// An issue we sent, or gave up sending, to the subscriber.
+#[derive(serde::Serialize)]
+struct PastDelivery {
+    newsletter_issue_id: Uuid,
+    title: String,
+    delivered_at: Option<String>,
+    failed_at: Option<String>,
+}
+
+/// An issue whose delivery was cancelled before it was sent to the subscriber.
+#[derive(serde::Serialize)]
+struct CancelledDelivery {
+    newsletter_issue_id: Uuid,
+    title: String,
+    cancelled_at: String,
+}
+
+/// An attempt to send an issue to the subscriber, from the send log.
+#[derive(serde::Serialize)]
+struct SendAttempt {
+    newsletter_issue_id: Uuid,
+    attempted_at: String,
+    sent_at: Option<String>,
+    error: Option<String>,
+    provider_message_id: Option<String>,
+}
+
+/// A delivery event reported by the email provider, or an open or a click
+/// we tracked ourselves.
+#[derive(serde::Serialize)]
+struct EmailEvent {
+    newsletter_issue_id: Option<Uuid>,
+    event_type: String,
+    /// The link followed, for clicks.
+    url: Option<String>,
+    occurred_at: String,
+}
+
+#[tracing::instrument(name = "Export subscriber data", skip(parameters, pool))]
+pub async fn export_subscriber_data(
+    parameters: web::Query<DataAccessParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = match get_subscriber_id_from_data_access_token(&pool, &parameters.token)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber_id) => subscriber_id,
+        None => return Ok(HttpResponse::Unauthorized().finish()),
+    };
+
+    let export = get_subscriber_data(&pool, subscriber_id)
+        .await
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            CONTENT_DISPOSITION,
+            "attachment; filename=\"subscriber-data.json\"",
+        ))
+        .json(export))
+}
+
+#[tracing::instrument(name = "Collect subscriber data", skip(pool))]
+async fn get_subscriber_data(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<SubscriberDataExport, anyhow::Error> {
+    let subscription = sqlx::query!(
+        r#"
+        SELECT id, email, name, subscribe_at, status, text_only
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to retrieve the subscription")?;
+
+    let lists = sqlx::query!(
+        r#"
+        SELECT l.slug, l.name, ls.subscribed_at
+        FROM list_subscriptions ls
+        JOIN lists l ON l.list_id = ls.list_id
+        WHERE ls.subscriber_id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve list subscriptions")?
+    .into_iter()
+    .map(|r| ListSubscription {
+        slug: r.slug,
+        name: r.name,
+        subscribed_at: r.subscribed_at.to_rfc3339(),
+    })
+    .collect();
+
+    let tags = sqlx::query!(
+        r#"SELECT tag FROM subscriber_tags WHERE subscriber_id = $1 ORDER BY tag"#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve subscriber tags")?
+    .into_iter()
+    .map(|r| r.tag)
+    .collect();
+
+    let subscription_tokens = sqlx::query!(
+        r#"SELECT subscription_token FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve subscription tokens")?
+    .into_iter()
+    .map(|r| r.subscription_token)
+    .collect();
+
+    let pending_deliveries = sqlx::query_as!(
+        PendingDelivery,
+        r#"
+        SELECT q.newsletter_issue_id, i.title
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+        WHERE
+            q.subscriber_email = $1 AND
+            q.delivered_at IS NULL AND
+            q.failed_at IS NULL
+        "#,
+        subscription.email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve pending deliveries")?;
+
+    let delivery_history = sqlx::query!(
+        r#"
+        SELECT q.newsletter_issue_id, i.title, q.delivered_at, q.failed_at
+        FROM issue_delivery_queue q
+        JOIN newsletter_issues i ON i.newsletter_issue_id = q.newsletter_issue_id
+        WHERE
+            q.subscriber_email = $1 AND
+            (q.delivered_at IS NOT NULL OR q.failed_at IS NOT NULL)
+        "#,
+        subscription.email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the delivery history")?
+    .into_iter()
+    .map(|r| PastDelivery {
+        newsletter_issue_id: r.newsletter_issue_id,
+        title: r.title,
+        delivered_at: r.delivered_at.map(|t| t.to_rfc3339()),
+        failed_at: r.failed_at.map(|t| t.to_rfc3339()),
+    })
+    .collect();
+
+    let cancelled_deliveries = sqlx::query!(
+        r#"
+        SELECT c.newsletter_issue_id, i.title, c.cancelled_at
+        FROM cancelled_deliveries c
+        JOIN newsletter_issues i ON i.newsletter_issue_id = c.newsletter_issue_id
+        WHERE c.subscriber_email = $1
+        "#,
+        subscription.email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve cancelled deliveries")?
+    .into_iter()
+    .map(|r| CancelledDelivery {
+        newsletter_issue_id: r.newsletter_issue_id,
+        title: r.title,
+        cancelled_at: r.cancelled_at.to_rfc3339(),
+    })
+    .collect();
+
+    let send_attempts = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id, attempted_at, sent_at, error, provider_message_id
+        FROM deliveries
+        WHERE subscriber_email = $1
+        ORDER BY attempted_at
+        "#,
+        subscription.email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the send log")?
+    .into_iter()
+    .map(|r| SendAttempt {
+        newsletter_issue_id: r.newsletter_issue_id,
+        attempted_at: r.attempted_at.to_rfc3339(),
+        sent_at: r.sent_at.map(|t| t.to_rfc3339()),
+        error: r.error,
+        provider_message_id: r.provider_message_id,
+    })
+    .collect();
+
+    let email_events = sqlx::query!(
+        r#"
+        SELECT newsletter_issue_id, event_type, url, occurred_at
+        FROM email_events
+        WHERE
+            subscriber_id = $1 OR
+            lower(subscriber_email) = lower($2)
+        ORDER BY occurred_at
+        "#,
+        subscriber_id,
+        subscription.email
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve email events")?
+    .into_iter()
+    .map(|r| EmailEvent {
+        newsletter_issue_id: r.newsletter_issue_id,
+        event_type: r.event_type,
+        url: r.url,
+        occurred_at: r.occurred_at.to_rfc3339(),
+    })
+    .collect();
+
+    Ok(SubscriberDataExport {
+        subscription: Subscription {
+            id: subscription.id,
+            email: subscription.email,
+            name: subscription.name,
+            subscribed_at: subscription.subscribe_at.to_rfc3339(),
+            status: subscription.status,
+            text_only: subscription.text_only,
+        },
+        lists,
+        tags,
+        subscription_tokens,
+        pending_deliveries,
+        delivery_history,
+        cancelled_deliveries,
+        send_attempts,
+        email_events,
+    })
+}
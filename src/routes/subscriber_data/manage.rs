@@ -0,0 +1,41 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use sqlx::PgPool;
+
+use super::{get_subscriber_id_from_data_access_token, DataAccessParameters};
+use crate::utils::e500;
+
+#[tracing::instrument(name = "Show the subscriber data page", skip(parameters, pool))]
+pub async fn manage_subscriber_data(
+    parameters: web::Query<DataAccessParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if get_subscriber_id_from_data_access_token(&pool, &parameters.token)
+        .await
+        .map_err(e500)?
+        .is_none()
+    {
+        return Ok(HttpResponse::Unauthorized().finish());
+    }
+
+    let token = urlencoding::encode(&parameters.token);
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Your data</title>
+</head>
+<body>
+    <p><a href="/subscriptions/data/export?token={token}">Download all your data (JSON)</a></p>
+    <form action="/subscriptions/data/erase" method="post">
+        <input hidden type="text" name="token" value="{token}">
+        <p>Erasing your data unsubscribes you and cannot be undone.</p>
+        <button type="submit">Erase my data</button>
+    </form>
+</body>
+</html>"#,
+        )))
+}
@@ -0,0 +1,38 @@
+mod erase;
+mod export;
+mod manage;
+mod request;
+
+pub use erase::erase_subscriber_data;
+pub use export::export_subscriber_data;
+pub use manage::manage_subscriber_data;
+pub use request::{request_data_access, request_data_access_form};
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct DataAccessParameters {
+    token: String,
+}
+
+#[tracing::instrument(name = "Get subscriber_id from data access token", skip(token, pool))]
+async fn get_subscriber_id_from_data_access_token(
+    pool: &PgPool,
+    token: &str,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        SELECT subscriber_id
+        FROM data_access_tokens
+        WHERE
+            data_access_token = $1 AND
+            created_at > now() - interval '1 day'
+        "#,
+        token
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(result.map(|r| r.subscriber_id))
+}
@@ -0,0 +1,161 @@
+use actix_web::{http::header::ContentType, web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use sqlx::PgPool;
+use std::fmt::Write;
+use uuid::Uuid;
+
+use crate::{
+    domain::SubscriberEmail,
+    email_client::{EmailClient, SendEmailError},
+    routes::generate_subscription_token,
+    startup::ApplicationBaseUrl,
+    utils::{e500, see_other},
+};
+
+pub async fn request_data_access_form(
+    flash_message: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut msg_html = String::new();
+
+    for m in flash_message.iter() {
+        writeln!(msg_html, "<p><i>{}</i></p>", m.content()).unwrap();
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+    <meta http-equiv="content-type" content="text/html; charset=utf-8">
+    <title>Your data</title>
+</head>
+<body>
+    {msg_html}
+    <p>Enter the address you subscribed with - we will email you a link to download or erase your data.</p>
+    <form action="/subscriptions/data" method="post">
+        <label>Email
+            <input
+                type="text"
+                placeholder="Enter your email"
+                name="email"
+            >
+        </label>
+        <button type="submit">Send me a link</button>
+    </form>
+</body>
+</html>"#,
+        )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+#[tracing::instrument(
+    name = "Request access to subscriber data",
+    skip(form, pool, email_client, base_url),
+    fields(subscriber_email = %form.email)
+)]
+pub async fn request_data_access(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // We answer in the same way whether the address is subscribed or not,
+    // to avoid disclosing who is on our list.
+    FlashMessage::info(
+        "If the address is subscribed to our newsletter, you will receive an email with a link to manage your data.",
+    )
+    .send();
+
+    let email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(_) => return Ok(see_other("/subscriptions/data")),
+    };
+
+    let subscriber_id = match get_subscriber_id_from_email(&pool, &email)
+        .await
+        .map_err(e500)?
+    {
+        Some(subscriber_id) => subscriber_id,
+        None => return Ok(see_other("/subscriptions/data")),
+    };
+
+    let token = generate_subscription_token();
+    store_data_access_token(&pool, subscriber_id, &token)
+        .await
+        .map_err(e500)?;
+
+    send_data_access_email(&email_client, &email, &base_url.0, &token)
+        .await
+        .context("Failed to send a data access email")
+        .map_err(e500)?;
+
+    Ok(see_other("/subscriptions/data"))
+}
+
+#[tracing::instrument(name = "Get subscriber_id from email", skip(pool, email))]
+async fn get_subscriber_id_from_email(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    let result = sqlx::query!(
+        r#"SELECT id FROM subscriptions WHERE email_key = $1"#,
+        email.key()
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve a subscriber by email")?;
+
+    Ok(result.map(|r| r.id))
+}
+
+#[tracing::instrument(name = "Store data access token in the database", skip(pool, token))]
+async fn store_data_access_token(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    token: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO data_access_tokens (data_access_token, subscriber_id, created_at)
+        VALUES ($1, $2, now())
+        "#,
+        token,
+        subscriber_id
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store a data access token")?;
+
+    Ok(())
+}
+
+#[tracing::instrument(name = "Send a data access email", skip(email_client, email, token))]
+async fn send_data_access_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    base_url: &str,
+    token: &str,
+) -> Result<(), SendEmailError> {
+    let manage_link = format!("{}/subscriptions/data/manage?token={}", base_url, token);
+
+    let plain_body = format!(
+        "Visit {} to download or erase the data we store about you. The link expires in 24 hours.",
+        manage_link
+    );
+
+    let html_body = format!(
+        "Click <a href=\"{}\">here</a> to download or erase the data we store about you.<br/>\
+                The link expires in 24 hours.",
+        manage_link
+    );
+
+    email_client
+        .send_email(email, "Your subscription data", &html_body, &plain_body)
+        .await
+}
@@ -1,13 +1,20 @@
 use crate::{
-    domain::{NewSubscriber, SubscriberEmail, SubscriberName},
+    domain::{
+        EmailPolicy, MergeTemplate, MergeValues, NewSubscriber, SubscriberEmail, SubscriberName,
+    },
     email_client::EmailClient,
-    startup::ApplicationBaseUrl,
+    layouts::{get_default_layout, EmailLayout},
+    lists::{add_to_list, get_list_id, DEFAULT_LIST_SLUG},
+    routes::preferences_link,
+    startup::{ApplicationBaseUrl, HmacSecret},
+    suppression::is_suppressed,
 };
 use actix_web::{web, HttpResponse, ResponseError};
 use anyhow::Context;
 use chrono::Utc;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
 use reqwest::StatusCode;
+use secrecy::Secret;
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
@@ -17,6 +24,10 @@ use super::error_chain_fmt;
 pub struct FormData {
     email: String,
     name: String,
+    /// Slug of the list to subscribe to.
+    list: Option<String>,
+    /// Subscribe the address as typed, even though it looks mistyped.
+    keep_email: Option<bool>,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
@@ -54,7 +65,7 @@ impl ResponseError for SubscribeError {
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool, email_client, base_url),
+    skip(form, pool, email_client, base_url, hmac_secret, email_policy),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name
@@ -65,17 +76,73 @@ pub async fn subscribe(
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
+    hmac_secret: web::Data<HmacSecret>,
+    email_policy: web::Data<EmailPolicy>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    let list = form.list.as_deref().unwrap_or(DEFAULT_LIST_SLUG);
+    let list_id = get_list_id(&pool, list)
+        .await
+        .context("Failed to retrieve the mailing list")?
+        .ok_or_else(|| {
+            SubscribeError::ValidationError(format!("{} is not a known mailing list", list))
+        })?;
+
+    let keep_email = form.keep_email.unwrap_or(false);
+    let new_subscriber: NewSubscriber =
+        form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    email_policy
+        .check(&new_subscriber.email)
+        .map_err(SubscribeError::ValidationError)?;
+    if !keep_email {
+        if let Some(suggestion) = email_policy.suggest(&new_subscriber.email) {
+            return Err(SubscribeError::ValidationError(format!(
+                "{} looks mistyped, did you mean {}? Subscribe again with `keep_email` \
+                set to keep the address as typed.",
+                new_subscriber.email, suggestion
+            )));
+        }
+    }
+
+    // Bounced or complaining addresses must not receive a confirmation email.
+    // We answer as usual to avoid disclosing what is on the suppression list.
+    if is_suppressed(&pool, new_subscriber.email.as_ref())
+        .await
+        .context("Failed to check the suppression list")?
+    {
+        return Ok(HttpResponse::Ok().finish());
+    }
 
     let mut transaction = pool
         .begin()
         .await
         .context("Failed to acquire a Postgres connection from the pool")?;
 
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
+    let existing_subscriber = get_subscriber_by_email(&mut transaction, &new_subscriber.email)
+        .await
+        .context("Failed to look up an existing subscriber")?;
+
+    let subscriber_id = match existing_subscriber {
+        Some((subscriber_id, _)) => subscriber_id,
+        None => insert_subscriber(&mut transaction, &new_subscriber)
+            .await
+            .context("Failed to insert a new subscriber in the database")?,
+    };
+
+    add_to_list(&mut transaction, list_id, &[subscriber_id])
         .await
-        .context("Failed to insert a new subscriber in the database")?;
+        .context("Failed to add the subscriber to the mailing list")?;
+
+    // Confirmed subscribers joining another list do not need to confirm their address again
+    if let Some((_, status)) = existing_subscriber {
+        if status == "confirmed" {
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to store a list subscription")?;
+
+            return Ok(HttpResponse::Ok().finish());
+        }
+    }
 
     let subscription_token = generate_subscription_token();
 
@@ -88,10 +155,19 @@ pub async fn subscribe(
         .await
         .context("Failed to commit SQL transaction to store a new subscriber")?;
 
+    let confirmation_email = ConfirmationEmail {
+        base_url: &base_url.0,
+        hmac_secret: &hmac_secret.0,
+        layout: get_default_layout(&pool)
+            .await
+            .context("Failed to retrieve the default email layout")?,
+    };
+
     send_confirmation_email(
         &email_client,
+        &confirmation_email,
         new_subscriber,
-        &base_url.0,
+        subscriber_id,
         &subscription_token,
     )
     .await
@@ -101,7 +177,7 @@ pub async fn subscribe(
 }
 
 // Generate a random 25-characters-long case-sensetive subscription token
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -109,19 +185,29 @@ fn generate_subscription_token() -> String {
         .collect()
 }
 
+/// What confirmation emails are built from, shared by all the emails sent
+/// while handling a request.
+pub struct ConfirmationEmail<'a> {
+    pub base_url: &'a str,
+    pub hmac_secret: &'a Secret<String>,
+    /// Confirmation emails use the default layout, if there is one.
+    pub layout: Option<EmailLayout>,
+}
+
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber)
+    skip(email_client, confirmation_email, new_subscriber)
 )]
 pub async fn send_confirmation_email(
     email_client: &EmailClient,
+    confirmation_email: &ConfirmationEmail<'_>,
     new_subscriber: NewSubscriber,
-    base_url: &str,
+    subscriber_id: Uuid,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), anyhow::Error> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
-        base_url, subscription_token
+        confirmation_email.base_url, subscription_token
     );
 
     let plain_body = format!(
@@ -135,9 +221,47 @@ pub async fn send_confirmation_email(
         confirmation_link
     );
 
+    let html_body = match &confirmation_email.layout {
+        Some(layout) => {
+            let preferences_link = preferences_link(
+                confirmation_email.base_url,
+                confirmation_email.hmac_secret,
+                subscriber_id,
+            );
+            let values = MergeValues {
+                name: new_subscriber.name.as_ref(),
+                email: new_subscriber.email.as_ref(),
+                unsubscribe_url: &preferences_link,
+            };
+
+            MergeTemplate::parse(&layout.wrap(&html_body).map_err(anyhow::Error::msg)?)
+                .map_err(anyhow::Error::msg)?
+                .render_html(&values)
+        }
+        None => html_body,
+    };
+
     email_client
         .send_email(&new_subscriber.email, "Welcome!", &html_body, &plain_body)
-        .await
+        .await?;
+
+    Ok(())
+}
+
+/// Return the id and status of the subscriber using `email`, if any.
+#[tracing::instrument(name = "Get subscriber by email", skip(transaction, email))]
+async fn get_subscriber_by_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &SubscriberEmail,
+) -> Result<Option<(Uuid, String)>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT id, status FROM subscriptions WHERE email_key = $1"#,
+        email.key()
+    )
+    .fetch_optional(transaction)
+    .await?;
+
+    Ok(row.map(|r| (r.id, r.status)))
 }
 
 #[tracing::instrument(
@@ -152,11 +276,12 @@ pub async fn insert_subscriber(
 
     sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribe_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        INSERT INTO subscriptions (id, email, email_key, name, subscribe_at, status)
+        VALUES ($1, $2, $3, $4, $5, 'pending_confirmation')
         "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
+        new_subscriber.email.key(),
         new_subscriber.name.as_ref(),
         Utc::now()
     )
@@ -0,0 +1,70 @@
+use actix_web::{
+    http::header::{self, CacheControl, CacheDirective},
+    web, HttpResponse,
+};
+use anyhow::Context;
+use sqlx::PgPool;
+
+use crate::{
+    startup::HmacSecret,
+    tracking::{record_tracking_event, TrackingToken},
+    utils::e500,
+};
+
+/// A transparent 1x1 GIF.
+const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+#[tracing::instrument(name = "Track a click", skip(token, pool, hmac_secret))]
+pub async fn track_click(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let token = match TrackingToken::decode(&token, &hmac_secret.0) {
+        Some(token) => token,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let url = match &token.url {
+        Some(url) => url.clone(),
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    record_tracking_event(&pool, &token, "click")
+        .await
+        .context("Failed to record a click")
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Found()
+        .insert_header((header::LOCATION, url))
+        .finish())
+}
+
+#[tracing::instrument(name = "Track an open", skip(token, pool, hmac_secret))]
+pub async fn track_open(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match TrackingToken::decode(&token, &hmac_secret.0) {
+        Some(token) if token.url.is_none() => {
+            record_tracking_event(&pool, &token, "opened")
+                .await
+                .context("Failed to record an open")
+                .map_err(e500)?;
+        }
+        _ => return Ok(HttpResponse::NotFound().finish()),
+    }
+
+    // Email clients must fetch the pixel every time the email is opened
+    Ok(HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header(CacheControl(vec![
+            CacheDirective::NoStore,
+            CacheDirective::MaxAge(0),
+        ]))
+        .body(PIXEL))
+}
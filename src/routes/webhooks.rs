@@ -0,0 +1,215 @@
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, TimeZone, Utc};
+use reqwest::StatusCode;
+use serde_aux::field_attributes::deserialize_number_from_string;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use super::error_chain_fmt;
+use crate::{
+    signature,
+    startup::WebhookSecret,
+    suppression::{suppress, SuppressionReason},
+};
+
+/// Header carrying the hex-encoded HMAC-SHA256 signature of the request body.
+const SIGNATURE_HEADER: &str = "X-Signature";
+
+/// A delivery event, as reported by the email provider.
+#[derive(serde::Deserialize, Debug)]
+pub struct EmailEvent {
+    #[serde(rename = "EVENT")]
+    event: String,
+    #[serde(rename = "EMAIL")]
+    email: String,
+    #[serde(
+        rename = "TIMESTAMP",
+        deserialize_with = "deserialize_number_from_string"
+    )]
+    timestamp: i64,
+    // Echoes the `x-apiheader` we attach to newsletter issues
+    #[serde(rename = "X-APIHEADER", default)]
+    api_header: Option<String>,
+    #[serde(rename = "BOUNCE_TYPE", default)]
+    bounce_type: Option<String>,
+}
+
+impl EmailEvent {
+    fn event_type(&self) -> String {
+        let event = self.event.to_lowercase();
+
+        if event == "bounce" {
+            if self.is_hard_bounce() {
+                "hard_bounce".into()
+            } else {
+                "soft_bounce".into()
+            }
+        } else {
+            event
+        }
+    }
+
+    fn is_hard_bounce(&self) -> bool {
+        self.bounce_type
+            .as_deref()
+            .map(|t| t.to_lowercase().contains("hard"))
+            .unwrap_or(false)
+    }
+
+    /// The subscriber status and suppression reason this event calls for, if any.
+    fn outcome(&self) -> Option<(&'static str, SuppressionReason)> {
+        match self.event_type().as_str() {
+            "hard_bounce" => Some(("bounced", SuppressionReason::HardBounce)),
+            "spam" => Some(("complained", SuppressionReason::Complaint)),
+            _ => None,
+        }
+    }
+
+    fn newsletter_issue_id(&self) -> Option<Uuid> {
+        self.api_header
+            .as_deref()
+            .and_then(|h| Uuid::parse_str(h).ok())
+    }
+
+    fn occurred_at(&self) -> DateTime<Utc> {
+        Utc.timestamp_opt(self.timestamp, 0)
+            .single()
+            .unwrap_or_else(Utc::now)
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("The request signature is missing or invalid")]
+    InvalidSignature,
+    #[error("The request body is not a valid list of events")]
+    InvalidPayload(#[source] serde_json::Error),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            WebhookError::InvalidSignature => StatusCode::UNAUTHORIZED,
+            WebhookError::InvalidPayload(_) => StatusCode::BAD_REQUEST,
+            WebhookError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Receive email events",
+    skip(request, body, pool, secret),
+    fields(n_events = tracing::field::Empty)
+)]
+pub async fn receive_email_events(
+    request: HttpRequest,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    secret: web::Data<WebhookSecret>,
+) -> Result<HttpResponse, WebhookError> {
+    let signature = request
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .and_then(|h| h.to_str().ok())
+        .ok_or(WebhookError::InvalidSignature)?;
+
+    if !signature::verify(&secret.0, &body, signature) {
+        return Err(WebhookError::InvalidSignature);
+    }
+
+    let events: Vec<EmailEvent> =
+        serde_json::from_slice(&body).map_err(WebhookError::InvalidPayload)?;
+
+    tracing::Span::current().record("n_events", &events.len());
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+
+    for event in &events {
+        record_event(&mut transaction, event)
+            .await
+            .context("Failed to record an email event")?;
+
+        if let Some((status, reason)) = event.outcome() {
+            update_subscriber_status(&mut transaction, &event.email, status)
+                .await
+                .context("Failed to update the status of a subscriber")?;
+            suppress(&mut transaction, &event.email, reason)
+                .await
+                .context("Failed to suppress an address")?;
+        }
+    }
+
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store email events")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(skip(transaction))]
+async fn record_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    event: &EmailEvent,
+) -> Result<(), sqlx::Error> {
+    // Events for an issue we do not know about are still recorded,
+    // just not tied to any issue.
+    sqlx::query!(
+        r#"
+        INSERT INTO email_events (
+            email_event_id,
+            newsletter_issue_id,
+            subscriber_email,
+            event_type,
+            occurred_at,
+            received_at
+        )
+        VALUES (
+            $1,
+            (SELECT newsletter_issue_id FROM newsletter_issues WHERE newsletter_issue_id = $2),
+            $3,
+            $4,
+            $5,
+            now()
+        )
+        "#,
+        Uuid::new_v4(),
+        event.newsletter_issue_id(),
+        event.email,
+        event.event_type(),
+        event.occurred_at()
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+#[tracing::instrument(skip(transaction))]
+async fn update_subscriber_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+    status: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"UPDATE subscriptions SET status = $2 WHERE email_key = lower($1)"#,
+        email,
+        status
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
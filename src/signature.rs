@@ -0,0 +1,52 @@
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+fn mac(secret: &Secret<String>, payload: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret.expose_secret().as_bytes())
+        .expect("HMAC can take a key of any size");
+    mac.update(payload);
+    mac
+}
+
+/// Compute the hex-encoded HMAC-SHA256 signature of `payload`.
+pub fn sign(secret: &Secret<String>, payload: &[u8]) -> String {
+    hex::encode(mac(secret, payload).finalize().into_bytes())
+}
+
+/// Check a hex-encoded HMAC-SHA256 signature in constant time.
+pub fn verify(secret: &Secret<String>, payload: &[u8], signature: &str) -> bool {
+    match hex::decode(signature) {
+        Ok(signature) => mac(secret, payload).verify_slice(&signature).is_ok(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sign, verify};
+    use secrecy::Secret;
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-secret".to_string())
+    }
+
+    #[test]
+    fn a_valid_signature_is_accepted() {
+        let signature = sign(&secret(), b"payload");
+        assert!(verify(&secret(), b"payload", &signature));
+    }
+
+    #[test]
+    fn a_signature_for_a_different_payload_is_rejected() {
+        let signature = sign(&secret(), b"payload");
+        assert!(!verify(&secret(), b"another payload", &signature));
+    }
+
+    #[test]
+    fn a_signature_that_is_not_hex_is_rejected() {
+        assert!(!verify(&secret(), b"payload", "not-hex"));
+    }
+}
@@ -1,11 +1,13 @@
 use crate::authentication::reject_anonymus_users;
 use crate::configuration::{DatabaseSettings, Settings};
+use crate::domain::EmailPolicy;
 use crate::email_client::EmailClient;
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
 use actix_web::cookie::Key;
+use actix_web::dev::{Server, ServerHandle};
 use actix_web::web::Data;
-use actix_web::{dev::Server, web, App, HttpServer};
+use actix_web::{web, App, HttpServer};
 use actix_web_flash_messages::storage::CookieMessageStore;
 use actix_web_flash_messages::FlashMessagesFramework;
 use actix_web_lab::middleware::from_fn;
@@ -16,8 +18,15 @@ use std::net::TcpListener;
 use tracing_actix_web::TracingLogger;
 
 use crate::routes::{
-    admin_dashboard, change_password, change_password_form, confirm, health_check, home, log_out,
-    login, login_form, publish_newsletter, publish_newsletter_form, subscribe,
+    add_suppression, admin_dashboard, archived_issue, atom_feed, cancel_delivery, change_password,
+    change_password_form, confirm, create_layout, create_list, erase_subscriber_data,
+    export_subscriber_data, health_check, home, import_subscribers, import_subscribers_form,
+    import_suppressions, issue_history, issues_archive, issues_page, layouts_page, lists_page,
+    log_out, login, login_form, manage_subscriber_data, pause_delivery, preferences_form,
+    publish_newsletter, publish_newsletter_form, receive_email_events, remove_suppression,
+    request_data_access, request_data_access_form, resume_delivery, rss_feed, set_default_layout,
+    subscribe, subscriber_tags_form, suppressions_page, track_click, track_open, unsubscribe_all,
+    update_preferences, update_subscriber_tags, upload_attachment,
 };
 
 pub fn get_connection_pool(config: &DatabaseSettings) -> PgPool {
@@ -34,10 +43,14 @@ pub struct Application {
 }
 
 impl Application {
-    pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
+    /// `email_client` is shared with the delivery workers, along with its rate limit.
+    pub async fn build(
+        configuration: Settings,
+        email_client: EmailClient,
+    ) -> Result<Self, anyhow::Error> {
         let connection_pool = get_connection_pool(&configuration.database);
 
-        let email_client = configuration.email_client.client();
+        let webhook_secret = configuration.email_client.webhook_secret.clone();
 
         let address = format!(
             "{}:{}",
@@ -52,7 +65,10 @@ impl Application {
             email_client,
             configuration.application.base_url,
             configuration.application.hmac_secret,
+            webhook_secret,
+            configuration.email_policy.policy(),
             configuration.redis_uri,
+            configuration.application.shutdown_timeout_seconds,
         )
         .await?;
 
@@ -63,22 +79,33 @@ impl Application {
         self.port
     }
 
+    /// Stops the server, see `ServerHandle::stop`. The server does not listen
+    /// for signals itself: they are handled along with the background workers.
+    pub fn handle(&self) -> ServerHandle {
+        self.server.handle()
+    }
+
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
         self.server.await
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
     base_url: String,
     hmac_secret: Secret<String>,
+    webhook_secret: Secret<String>,
+    email_policy: EmailPolicy,
     redis_uri: Secret<String>,
+    shutdown_timeout_seconds: u64,
 ) -> Result<Server, anyhow::Error> {
     let db_pool = Data::new(db_pool);
     let email_client = Data::new(email_client);
     let base_url = Data::new(ApplicationBaseUrl(base_url));
+    let email_policy = Data::new(email_policy);
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
@@ -96,8 +123,47 @@ pub async fn run(
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
             .route("/health_check", web::get().to(health_check))
+            .route("/feed.rss", web::get().to(rss_feed))
+            .route("/feed.atom", web::get().to(atom_feed))
+            .route("/issues", web::get().to(issues_archive))
+            .route("/issues/{id_or_slug}", web::get().to(archived_issue))
+            .route("/t/c/{token}", web::get().to(track_click))
+            .route("/t/o/{token}.gif", web::get().to(track_open))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route(
+                "/subscriptions/preferences",
+                web::get().to(preferences_form),
+            )
+            .route(
+                "/subscriptions/preferences",
+                web::post().to(update_preferences),
+            )
+            .route(
+                "/subscriptions/preferences/unsubscribe",
+                web::post().to(unsubscribe_all),
+            )
+            .route(
+                "/webhooks/email-events",
+                web::post().to(receive_email_events),
+            )
+            .route(
+                "/subscriptions/data",
+                web::get().to(request_data_access_form),
+            )
+            .route("/subscriptions/data", web::post().to(request_data_access))
+            .route(
+                "/subscriptions/data/manage",
+                web::get().to(manage_subscriber_data),
+            )
+            .route(
+                "/subscriptions/data/export",
+                web::get().to(export_subscriber_data),
+            )
+            .route(
+                "/subscriptions/data/erase",
+                web::post().to(erase_subscriber_data),
+            )
             .service(
                 web::scope("/admin")
                     .wrap(from_fn(reject_anonymus_users))
@@ -105,14 +171,43 @@ pub async fn run(
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
                     .route("/logout", web::post().to(log_out))
+                    .route("/issues", web::get().to(issues_page))
+                    .route("/issues/{issue_id}", web::get().to(issue_history))
+                    .route("/issues/{issue_id}/pause", web::post().to(pause_delivery))
+                    .route("/issues/{issue_id}/resume", web::post().to(resume_delivery))
+                    .route("/issues/{issue_id}/cancel", web::post().to(cancel_delivery))
+                    .route("/layouts", web::get().to(layouts_page))
+                    .route("/layouts", web::post().to(create_layout))
+                    .route("/layouts/default", web::post().to(set_default_layout))
+                    .route("/lists", web::get().to(lists_page))
+                    .route("/lists", web::post().to(create_list))
                     .route("/newsletters", web::get().to(publish_newsletter_form))
-                    .route("/newsletters", web::post().to(publish_newsletter)),
+                    .route("/newsletters", web::post().to(publish_newsletter))
+                    .route(
+                        "/newsletters/attachments",
+                        web::post().to(upload_attachment),
+                    )
+                    .route(
+                        "/subscribers/import",
+                        web::get().to(import_subscribers_form),
+                    )
+                    .route("/subscribers/import", web::post().to(import_subscribers))
+                    .route("/subscribers/tags", web::get().to(subscriber_tags_form))
+                    .route("/subscribers/tags", web::post().to(update_subscriber_tags))
+                    .route("/suppressions", web::get().to(suppressions_page))
+                    .route("/suppressions", web::post().to(add_suppression))
+                    .route("/suppressions/remove", web::post().to(remove_suppression))
+                    .route("/suppressions/import", web::post().to(import_suppressions)),
             )
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(email_policy.clone())
             .app_data(Data::new(HmacSecret(hmac_secret.clone())))
+            .app_data(Data::new(WebhookSecret(webhook_secret.clone())))
     })
+    .disable_signals()
+    .shutdown_timeout(shutdown_timeout_seconds)
     .listen(listener)?
     .run();
 
@@ -121,3 +216,7 @@ pub async fn run(
 
 #[derive(Clone)]
 pub struct HmacSecret(pub Secret<String>);
+
+/// Shared secret used by the email provider to sign the events it sends us.
+#[derive(Clone)]
+pub struct WebhookSecret(pub Secret<String>);
@@ -0,0 +1,207 @@
+use std::collections::HashSet;
+
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+
+/// Why an address must not be emailed (or imported) anymore.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SuppressionReason {
+    /// The subscriber asked us to erase their data. It keeps the address
+    /// out of bulk imports, but a new opt-in through the subscription form
+    /// is honoured.
+    Erasure,
+    HardBounce,
+    Complaint,
+    Manual,
+    Imported,
+}
+
+impl SuppressionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionReason::Erasure => "erasure",
+            SuppressionReason::HardBounce => "hard_bounce",
+            SuppressionReason::Complaint => "complaint",
+            SuppressionReason::Manual => "manual",
+            SuppressionReason::Imported => "imported",
+        }
+    }
+}
+
+impl TryFrom<String> for SuppressionReason {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.as_str() {
+            "erasure" => Ok(Self::Erasure),
+            "hard_bounce" => Ok(Self::HardBounce),
+            "complaint" => Ok(Self::Complaint),
+            "manual" => Ok(Self::Manual),
+            "imported" => Ok(Self::Imported),
+            other => Err(format!("{} is not a valid suppression reason", other)),
+        }
+    }
+}
+
+/// Suppressed addresses are looked up by their hash - we must be able to
+/// recognise an erased subscriber without keeping their email around.
+///
+/// Queries that check the suppression list in SQL compute the same hash with
+/// `encode(sha256(convert_to(lower(email), 'UTF8')), 'hex')`.
+pub fn email_hash(email: &str) -> String {
+    format!("{:x}", Sha256::digest(email.to_lowercase().as_bytes()))
+}
+
+#[tracing::instrument(
+    name = "Add an address to the suppression list",
+    skip(transaction, email)
+)]
+pub async fn suppress(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+    reason: SuppressionReason,
+) -> Result<(), sqlx::Error> {
+    // We do not hold on to the address of a subscriber who asked to be forgotten,
+    // even if it was suppressed for another reason before: the existing row
+    // keeps its reason but loses the address.
+    let stored_email = match reason {
+        SuppressionReason::Erasure => None,
+        _ => Some(email),
+    };
+
+    sqlx::query!(
+        r#"
+        INSERT INTO suppressions (email_hash, email, reason, created_at)
+        VALUES ($1, $2, $3, now())
+        ON CONFLICT (email_hash) DO UPDATE
+        SET email = NULL
+        WHERE excluded.reason = 'erasure'
+        "#,
+        email_hash(email),
+        stored_email,
+        reason.as_str()
+    )
+    .execute(transaction)
+    .await?;
+
+    Ok(())
+}
+
+/// Suppress a batch of addresses at once, returning how many were not suppressed already.
+#[tracing::instrument(
+    name = "Add a batch of addresses to the suppression list",
+    skip(pool, emails),
+    fields(batch_size = emails.len())
+)]
+pub async fn suppress_batch(
+    pool: &PgPool,
+    emails: &[String],
+    reason: SuppressionReason,
+) -> Result<u64, sqlx::Error> {
+    let hashes: Vec<String> = emails.iter().map(|e| email_hash(e)).collect();
+    // Same as `suppress`: erased addresses are only kept as a hash
+    let stored_emails: Vec<Option<&str>> = emails
+        .iter()
+        .map(|e| match reason {
+            SuppressionReason::Erasure => None,
+            _ => Some(e.as_str()),
+        })
+        .collect();
+
+    let n_inserted_rows = sqlx::query!(
+        r#"
+        INSERT INTO suppressions (email_hash, email, reason, created_at)
+        SELECT email_hash, email, $3, now()
+        FROM UNNEST($1::text[], $2::text[]) AS s(email_hash, email)
+        ON CONFLICT (email_hash) DO UPDATE
+        SET email = NULL
+        WHERE excluded.reason = 'erasure'
+        "#,
+        &hashes[..],
+        &stored_emails as &[Option<&str>],
+        reason.as_str()
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+
+    Ok(n_inserted_rows)
+}
+
+#[tracing::instrument(name = "Remove an address from the suppression list", skip(pool))]
+pub async fn unsuppress(pool: &PgPool, email_hash: &str) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM suppressions WHERE email_hash = $1"#,
+        email_hash
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Whether we must refrain from sending emails to `email`.
+#[tracing::instrument(name = "Check if an address is suppressed", skip(pool, email))]
+pub async fn is_suppressed(pool: &PgPool, email: &str) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT email_hash
+        FROM suppressions
+        WHERE
+            email_hash = $1 AND
+            reason <> 'erasure'
+        "#,
+        email_hash(email)
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(row.is_some())
+}
+
+/// Return the subset of `emails` that is on the suppression list, for any reason.
+#[tracing::instrument(name = "Find suppressed addresses", skip_all)]
+pub async fn find_suppressed(
+    pool: &PgPool,
+    emails: &[&str],
+) -> Result<HashSet<String>, sqlx::Error> {
+    let hashes: Vec<String> = emails.iter().map(|e| email_hash(e)).collect();
+
+    let suppressed_hashes: HashSet<String> = sqlx::query!(
+        r#"SELECT email_hash FROM suppressions WHERE email_hash = ANY($1)"#,
+        &hashes[..]
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| r.email_hash)
+    .collect();
+
+    Ok(emails
+        .iter()
+        .zip(hashes)
+        .filter(|(_, hash)| suppressed_hashes.contains(hash))
+        .map(|(email, _)| email.to_string())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::email_hash;
+
+    #[test]
+    fn email_hash_is_case_insensitive() {
+        assert_eq!(
+            email_hash("Ursula@Domain.com"),
+            email_hash("ursula@domain.com")
+        );
+    }
+
+    #[test]
+    fn email_hash_is_a_hex_encoded_sha256_digest() {
+        assert_eq!(
+            email_hash("ursula@domain.com"),
+            "5082ac3515f6c85882c7ed7914912ec42ec416ed7c28da2e90c8d3c7d1f72057"
+        );
+    }
+}
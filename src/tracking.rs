@@ -0,0 +1,304 @@
+use secrecy::Secret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::email_client::placeholder;
+use crate::signature::{sign, verify};
+
+/// Who a tracking link was sent to, and where a click should lead.
+#[derive(Debug, PartialEq)]
+pub struct TrackingToken {
+    pub newsletter_issue_id: Uuid,
+    pub subscriber_id: Uuid,
+    /// The original link, for clicks. `None` for the open pixel.
+    pub url: Option<String>,
+}
+
+impl TrackingToken {
+    /// Serialise and sign the token, the result is safe to use in a URL path.
+    ///
+    /// Tokens are not encrypted: the link they point to can be read from them.
+    pub fn encode(&self, hmac_secret: &Secret<String>) -> String {
+        let payload = self.payload();
+        format!(
+            "{}.{}",
+            base64::encode_config(&payload, base64::URL_SAFE_NO_PAD),
+            sign(hmac_secret, payload.as_bytes())
+        )
+    }
+
+    /// `None` if the token has been tampered with.
+    pub fn decode(token: &str, hmac_secret: &Secret<String>) -> Option<Self> {
+        let (payload, signature) = token.split_once('.')?;
+        let payload = base64::decode_config(payload, base64::URL_SAFE_NO_PAD).ok()?;
+        if !verify(hmac_secret, &payload, signature) {
+            return None;
+        }
+
+        let payload = String::from_utf8(payload).ok()?;
+        let mut parts = payload.splitn(4, ':');
+        let url = match parts.next()? {
+            "open" => None,
+            "click" => Some(parts.clone().nth(2)?.to_owned()),
+            _ => return None,
+        };
+
+        Some(Self {
+            newsletter_issue_id: Uuid::parse_str(parts.next()?).ok()?,
+            subscriber_id: Uuid::parse_str(parts.next()?).ok()?,
+            url,
+        })
+    }
+
+    fn payload(&self) -> String {
+        match &self.url {
+            Some(url) => format!(
+                "click:{}:{}:{}",
+                self.newsletter_issue_id, self.subscriber_id, url
+            ),
+            None => format!("open:{}:{}", self.newsletter_issue_id, self.subscriber_id),
+        }
+    }
+}
+
+/// Replace the links of an HTML email with the placeholders of the `LINK_0`,
+/// `LINK_1`, ... attributes, returning the original links in that order.
+///
+/// Each recipient's attributes then point to the click redirector with their
+/// own token - see `tracked_link`.
+/// Only web links are tracked, as long as they do not point back to us -
+/// e.g. the preference centre - nor contain a placeholder themselves.
+pub fn extract_links(html: &str, base_url: &str) -> (String, Vec<String>) {
+    let mut extracted = String::with_capacity(html.len());
+    let mut links: Vec<String> = Vec::new();
+    let mut rest = html;
+
+    while let Some((start, quote)) = find_href(rest) {
+        let value_start = start + "href=".len() + 1;
+        let value_end = match rest[value_start..].find(quote) {
+            Some(i) => value_start + i,
+            None => break,
+        };
+        extracted.push_str(&rest[..value_start]);
+
+        let url = htmlescape::decode_html(&rest[value_start..value_end])
+            .unwrap_or_else(|_| rest[value_start..value_end].to_owned());
+        let is_trackable = (url.starts_with("http://") || url.starts_with("https://"))
+            && !url.starts_with(base_url)
+            && !url.contains("[%");
+
+        if is_trackable {
+            let i = match links.iter().position(|l| *l == url) {
+                Some(i) => i,
+                None => {
+                    links.push(url);
+                    links.len() - 1
+                }
+            };
+            extracted.push_str(&placeholder(&link_attribute(i)));
+        } else {
+            extracted.push_str(&rest[value_start..value_end]);
+        }
+
+        rest = &rest[value_end..];
+    }
+
+    extracted.push_str(rest);
+    (extracted, links)
+}
+
+/// The attribute the `i`-th link returned by `extract_links` is sent as.
+pub fn link_attribute(i: usize) -> String {
+    format!("LINK_{}", i)
+}
+
+/// `url`, routed through the click redirector for a given recipient.
+pub fn tracked_link(
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    url: &str,
+) -> String {
+    let token = TrackingToken {
+        newsletter_issue_id,
+        subscriber_id,
+        url: Some(url.to_owned()),
+    };
+    // Tokens only contain URL-safe characters, no need to escape them
+    format!("{}/t/c/{}", base_url, token.encode(hmac_secret))
+}
+
+/// Position of the next `href="` (or `href='`), along with its quote.
+fn find_href(html: &str) -> Option<(usize, char)> {
+    let lowercase = html.to_ascii_lowercase();
+    let mut from = 0;
+
+    while let Some(i) = lowercase[from..].find("href=") {
+        let start = from + i;
+        match html[start + "href=".len()..].chars().next() {
+            Some(quote @ ('"' | '\'')) => return Some((start, quote)),
+            _ => from = start + "href=".len(),
+        }
+    }
+
+    None
+}
+
+/// The address of the invisible image reporting that an HTML email has been opened.
+pub fn open_pixel_url(
+    base_url: &str,
+    hmac_secret: &Secret<String>,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+) -> String {
+    let token = TrackingToken {
+        newsletter_issue_id,
+        subscriber_id,
+        url: None,
+    };
+
+    format!("{}/t/o/{}.gif", base_url, token.encode(hmac_secret))
+}
+
+/// Record an open or a click alongside the events reported by the email provider.
+#[tracing::instrument(skip(pool))]
+pub async fn record_tracking_event(
+    pool: &PgPool,
+    token: &TrackingToken,
+    event_type: &str,
+) -> Result<(), sqlx::Error> {
+    // Nothing is recorded for subscribers who have since been erased
+    sqlx::query!(
+        r#"
+        INSERT INTO email_events (
+            email_event_id,
+            newsletter_issue_id,
+            subscriber_id,
+            subscriber_email,
+            event_type,
+            url,
+            occurred_at,
+            received_at
+        )
+        SELECT $1, $2, s.id, s.email, $4, $5, now(), now()
+        FROM subscriptions s
+        WHERE s.id = $3
+        "#,
+        Uuid::new_v4(),
+        token.newsletter_issue_id,
+        token.subscriber_id,
+        event_type,
+        token.url
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_links, open_pixel_url, tracked_link, TrackingToken};
+    use claim::{assert_none, assert_some_eq};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    const BASE_URL: &str = "https://zero2prod.com";
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-secret".to_string())
+    }
+
+    fn token(url: Option<&str>) -> TrackingToken {
+        TrackingToken {
+            newsletter_issue_id: Uuid::new_v4(),
+            subscriber_id: Uuid::new_v4(),
+            url: url.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn tokens_round_trip() {
+        for url in [None, Some("https://example.com/a:b?c=d&e=f")] {
+            let token = token(url);
+            let encoded = token.encode(&secret());
+            assert_some_eq!(TrackingToken::decode(&encoded, &secret()), token);
+        }
+    }
+
+    #[test]
+    fn tampered_tokens_are_rejected() {
+        let encoded = token(Some("https://example.com")).encode(&secret());
+        let other = token(Some("https://evil.com")).encode(&secret());
+        let (_, signature) = encoded.split_once('.').unwrap();
+        let (payload, _) = other.split_once('.').unwrap();
+
+        assert_none!(TrackingToken::decode(
+            &format!("{}.{}", payload, signature),
+            &secret()
+        ));
+        assert_none!(TrackingToken::decode("garbage", &secret()));
+    }
+
+    #[test]
+    fn web_links_are_replaced_with_placeholders() {
+        let html = r#"<a href="https://example.com/?a=1&amp;b=2">Docs</a> <A HREF='http://example.com'>x</A>"#;
+
+        let (extracted, links) = extract_links(html, BASE_URL);
+
+        assert_eq!(
+            extracted,
+            r#"<a href="[%LINK_0%]">Docs</a> <A HREF='[%LINK_1%]'>x</A>"#
+        );
+        assert_eq!(
+            links,
+            ["https://example.com/?a=1&b=2", "http://example.com"]
+        );
+    }
+
+    #[test]
+    fn repeated_links_share_a_placeholder() {
+        let html = r#"<a href="https://example.com">a</a><a href="https://example.com">b</a>"#;
+
+        let (extracted, links) = extract_links(html, BASE_URL);
+
+        assert_eq!(extracted.matches("[%LINK_0%]").count(), 2);
+        assert_eq!(links.len(), 1);
+    }
+
+    #[test]
+    fn other_links_are_left_untouched() {
+        let html = format!(
+            r#"<a href="mailto:a@b.com">Mail</a><a href="{}/subscriptions/preferences">Manage</a><a href="[%PREFERENCES_URL%]">x</a><a href="https://example.com/?ref=[%EMAIL%]">y</a>"#,
+            BASE_URL
+        );
+
+        let (extracted, links) = extract_links(&html, BASE_URL);
+
+        assert_eq!(extracted, html);
+        assert!(links.is_empty());
+    }
+
+    #[test]
+    fn the_original_link_is_kept_in_the_token() {
+        let link = tracked_link(
+            BASE_URL,
+            &secret(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            "https://example.com/?a=1&b=2",
+        );
+
+        let token = link.strip_prefix("https://zero2prod.com/t/c/").unwrap();
+        let token = TrackingToken::decode(token, &secret()).unwrap();
+        assert_some_eq!(token.url, "https://example.com/?a=1&b=2");
+    }
+
+    #[test]
+    fn the_open_pixel_points_to_the_tracker() {
+        let url = open_pixel_url(BASE_URL, &secret(), Uuid::new_v4(), Uuid::new_v4());
+        assert!(url.starts_with("https://zero2prod.com/t/o/"));
+        assert!(url.ends_with(".gif"));
+    }
+}
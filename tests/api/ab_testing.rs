@@ -0,0 +1,190 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+const SUBSCRIBERS: &str = "email,name
+ursula@domain.com,Ursula Le Guin
+octavia@domain.com,Octavia Butler
+ted@domain.com,Ted Chiang
+nnedi@domain.com,Nnedi Okorafor
+";
+
+async fn import_subscribers(app: &TestApp) {
+    app.post_subscribers_import("confirmed", SUBSCRIBERS)
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+async fn publish_ab_test(app: &TestApp, test_slice_percent: u32) -> reqwest::Response {
+    app.post_newsletters(&serde_json::json!({
+        "title": "Subject A",
+        "text": "Newsletter body as plain text",
+        "html": "<p>Newsletter body as HTML</p>",
+        "subject_variants": "Subject B",
+        "ab_test_metric": "opens",
+        "track_opens": "true",
+        "test_slice_percent": test_slice_percent,
+        "test_window_hours": 1,
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    }))
+    .await
+}
+
+async fn sent_subjects(app: &TestApp) -> Vec<String> {
+    app.get_delivered_emails()
+        .await
+        .iter()
+        .map(|body| body["subject"].as_str().unwrap().to_owned())
+        .collect()
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_issues() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_issues().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subject_variants_are_spread_over_the_test_slice() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    import_subscribers(&app).await;
+
+    // A batch per variant
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = publish_ab_test(&app, 100).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let mut subjects = sent_subjects(&app).await;
+    subjects.sort();
+    assert_eq!(
+        subjects,
+        vec!["Subject A", "Subject A", "Subject B", "Subject B"]
+    );
+
+    let n_assigned = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue WHERE subject_variant IS NOT NULL"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_assigned, 4);
+}
+
+#[tokio::test]
+async fn the_rest_of_the_queue_gets_the_winning_subject_once_the_test_is_over() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    import_subscribers(&app).await;
+
+    // A batch per variant, then one for the rest of the queue
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Send the test slice
+    let response = publish_ab_test(&app, 50).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+    assert_eq!(sent_subjects(&app).await.len(), 2);
+
+    // Act - Part 2 - The recipient of `Subject B` opens the issue
+    let test_slice = sqlx::query!(
+        "SELECT newsletter_issue_id, subscriber_email FROM issue_delivery_queue WHERE subject_variant = 1"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    let issue_id = test_slice.newsletter_issue_id;
+    sqlx::query!(
+        r#"
+        INSERT INTO email_events (
+            email_event_id, newsletter_issue_id, subscriber_email, event_type, occurred_at, received_at
+        )
+        VALUES ($1, $2, $3, 'opened', now(), now())
+        "#,
+        uuid::Uuid::new_v4(),
+        issue_id,
+        test_slice.subscriber_email
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act - Part 3 - End the test window
+    sqlx::query!("UPDATE newsletter_issues SET ab_test_ends_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let subjects = sent_subjects(&app).await;
+    assert_eq!(&subjects[2..], &["Subject B", "Subject B"]);
+
+    let html_page = app.get_issue_history_html(issue_id).await;
+    assert!(html_page.contains("Delivered to 4 out of 4 recipient(s)."));
+    assert!(html_page
+        .contains("<tr><td>Subject B (winner)</td><td>1</td><td>1</td><td>100.0%</td></tr>"));
+}
+
+#[tokio::test]
+async fn invalid_test_slices_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = publish_ab_test(&app, 0).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn tests_decided_on_an_untracked_metric_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Subject A",
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+            "subject_variants": "Subject B",
+            "ab_test_metric": "clicks",
+            "track_opens": "true",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
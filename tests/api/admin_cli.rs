@@ -0,0 +1,175 @@
+use secrecy::Secret;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::domain::SubscriberEmail;
+use zero2prod::operations::{
+    confirm_address, get_failed_deliveries, get_queue_depth, requeue_failed_deliveries,
+    set_admin_user, unsubscribe_address,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn admin_users_can_be_created_and_have_their_password_reset() {
+    // Arrange
+    let app = spawn_app().await;
+    let old_password = "a-long-enough-password";
+    let new_password = "another-long-enough-password";
+
+    // Act - Part 1 - Create
+    set_admin_user(&app.db_pool, "operator", Secret::new(old_password.into()))
+        .await
+        .unwrap();
+
+    // Assert
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "operator",
+            "password": old_password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.post_logout().await;
+
+    // Act - Part 2 - Reset
+    set_admin_user(&app.db_pool, "operator", Secret::new(new_password.into()))
+        .await
+        .unwrap();
+
+    // Assert
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "operator",
+            "password": old_password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "operator",
+            "password": new_password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn short_admin_passwords_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let outcome = set_admin_user(&app.db_pool, "operator", Secret::new("short".into())).await;
+
+    // Assert
+    assert!(outcome.is_err());
+}
+
+#[tokio::test]
+async fn failed_deliveries_can_be_requeued() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let email = SubscriberEmail::parse("ursula_le_guin@gmail.com".into()).unwrap();
+    confirm_address(&app.db_pool, &email).await.unwrap();
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // The workers gave up on it
+    sqlx::query!("UPDATE issue_delivery_queue SET n_retries = 5, failed_at = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let failed = get_failed_deliveries(&app.db_pool).await.unwrap();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].subscriber_email, "ursula_le_guin@gmail.com");
+    let depth = get_queue_depth(&app.db_pool).await.unwrap();
+    assert_eq!((depth[0].n_pending, depth[0].n_failed), (0, 1));
+
+    // Act
+    let n_requeued = requeue_failed_deliveries(&app.db_pool, None).await.unwrap();
+
+    // Assert
+    assert_eq!(n_requeued, 1);
+    assert!(get_failed_deliveries(&app.db_pool)
+        .await
+        .unwrap()
+        .is_empty());
+    let depth = get_queue_depth(&app.db_pool).await.unwrap();
+    assert_eq!((depth[0].n_pending, depth[0].n_failed), (1, 0));
+
+    app.dispatch_all_pending_emails().await;
+    assert!(get_queue_depth(&app.db_pool).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn subscribers_can_be_confirmed_and_unsubscribed_by_address() {
+    // Arrange
+    let app = spawn_app().await;
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let email = SubscriberEmail::parse("ursula_le_guin@gmail.com".into()).unwrap();
+
+    // Act - Part 1 - Confirm
+    assert!(confirm_address(&app.db_pool, &email).await.unwrap());
+
+    // Assert
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+
+    // Act - Part 2 - Unsubscribe
+    assert!(unsubscribe_address(&app.db_pool, &email).await.unwrap());
+
+    // Assert
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn unknown_addresses_are_reported() {
+    // Arrange
+    let app = spawn_app().await;
+    let email = SubscriberEmail::parse("ursula_le_guin@gmail.com".into()).unwrap();
+
+    // Act
+    let confirmed = confirm_address(&app.db_pool, &email).await.unwrap();
+    let unsubscribed = unsubscribe_address(&app.db_pool, &email).await.unwrap();
+
+    // Assert
+    assert!(!confirmed);
+    assert!(!unsubscribed);
+}
@@ -0,0 +1,207 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+async fn get_attachment_id(app: &TestApp, filename: &str) -> uuid::Uuid {
+    sqlx::query!(
+        "SELECT attachment_id FROM attachments WHERE filename = $1",
+        filename
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch the saved attachment")
+    .attachment_id
+}
+
+async fn publish_with_attachments(app: &TestApp, attachments: &str) -> reqwest::Response {
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "text": "Newsletter body as plain text",
+        "html": "<p>Newsletter body as HTML</p>",
+        "attachments": attachments,
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_upload_attachments() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_attachment("notes.txt", "text/plain", b"Notes".to_vec(), false)
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn uploaded_files_are_handed_back_to_the_publish_form() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_attachment("notes.txt", "text/plain", b"Notes".to_vec(), false)
+        .await;
+
+    // Assert
+    let attachment_id = get_attachment_id(&app, "notes.txt").await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters?attachments={}", attachment_id),
+    );
+}
+
+#[tokio::test]
+async fn files_uploaded_so_far_are_kept_when_uploading_another() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_attachment("notes.txt", "text/plain", b"Notes".to_vec(), false)
+        .await;
+    let notes_id = get_attachment_id(&app, "notes.txt").await;
+
+    // Act
+    let response = app
+        .post_attachment_after(
+            &notes_id.to_string(),
+            "logo.png",
+            "image/png",
+            PNG.to_vec(),
+            true,
+        )
+        .await;
+
+    // Assert
+    let logo_id = get_attachment_id(&app, "logo.png").await;
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/newsletters?attachments={},{}", notes_id, logo_id),
+    );
+}
+
+#[tokio::test]
+async fn dangerous_files_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let test_cases = vec![
+        (
+            "setup.exe",
+            "application/x-msdownload",
+            b"MZ\x90\0".to_vec(),
+        ),
+        (
+            "page.html",
+            "text/html",
+            b"<script>alert('hi')</script>".to_vec(),
+        ),
+        // Announced as an image, but it is not one
+        ("photo.png", "image/png", b"MZ\x90\0".to_vec()),
+    ];
+
+    for (filename, content_type, content) in test_cases {
+        // Act
+        let response = app
+            .post_attachment(filename, content_type, content, false)
+            .await;
+
+        // Assert
+        assert_is_redirect_to(&response, "/admin/newsletters?attachments=");
+        let n_attachments = sqlx::query!("SELECT count(*) AS \"count!\" FROM attachments")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .count;
+        assert_eq!(n_attachments, 0, "{} was accepted", filename);
+    }
+}
+
+#[tokio::test]
+async fn oversized_files_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let content = vec![b'a'; 5 * 1024 * 1024 + 1];
+
+    // Act - Part 1 - Upload the file
+    let response = app
+        .post_attachment("large.txt", "text/plain", content, false)
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters?attachments=");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_send_newsletter_issue_html().await;
+
+    // Assert
+    assert!(html_page.contains("large.txt is too large, attachments are limited to 5 MB"));
+}
+
+#[tokio::test]
+async fn attachments_are_sent_with_the_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_subscribers_import(
+        "confirmed",
+        "email,name\nursula@domain.com,Ursula Le Guin\n",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    app.post_attachment("notes.txt", "text/plain", b"Notes".to_vec(), false)
+        .await;
+    app.post_attachment("logo.png", "image/png", PNG.to_vec(), true)
+        .await;
+    let notes_id = get_attachment_id(&app, "notes.txt").await;
+    let logo_id = get_attachment_id(&app, "logo.png").await;
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = publish_with_attachments(&app, &format!("{},{}", notes_id, logo_id)).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let attachments = &body["attachments"];
+
+    assert_eq!(attachments[0]["name"], "notes.txt");
+    assert_eq!(attachments[0]["type"], "text/plain");
+    assert_eq!(attachments[0]["content"], base64::encode("Notes"));
+    assert_eq!(attachments[0]["disposition"], "attachment");
+
+    assert_eq!(attachments[1]["name"], "logo.png");
+    assert_eq!(attachments[1]["disposition"], "inline");
+    assert_eq!(attachments[1]["content_id"], logo_id.to_string());
+}
+
+#[tokio::test]
+async fn unknown_attachments_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = publish_with_attachments(&app, &uuid::Uuid::new_v4().to_string()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -0,0 +1,124 @@
+use uuid::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::domain::SubscriberEmail;
+use zero2prod::operations::confirm_address;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// Publish an issue to a single confirmed subscriber, returning its id.
+async fn publish_issue(app: &TestApp) -> Uuid {
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let email = SubscriberEmail::parse("ursula_le_guin@gmail.com".into()).unwrap();
+    confirm_address(&app.db_pool, &email).await.unwrap();
+
+    app.test_user.login(app).await;
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+async fn count_delivered(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(delivered_at) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn paused_deliveries_are_held_until_resumed() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = publish_issue(&app).await;
+
+    // Act - Part 1 - Pause
+    let response = app.post_delivery_action(issue_id, "pause").await;
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}", issue_id));
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(count_delivered(&app).await, 0);
+    let html_page = app.get_issue_history_html(issue_id).await;
+    assert!(html_page.contains("<p><i>The delivery has been paused.</i></p>"));
+    assert!(html_page.contains("Delivery paused, 1 email(s) on hold."));
+
+    // Act - Part 2 - Resume
+    let response = app.post_delivery_action(issue_id, "resume").await;
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}", issue_id));
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(count_delivered(&app).await, 1);
+    let html_page = app.get_issue_history_html(issue_id).await;
+    assert!(html_page.contains("<p><i>The delivery has been resumed.</i></p>"));
+}
+
+#[tokio::test]
+async fn cancelled_deliveries_are_moved_out_of_the_queue() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = publish_issue(&app).await;
+
+    // Act
+    let response = app.post_delivery_action(issue_id, "cancel").await;
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}", issue_id));
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(count_delivered(&app).await, 0);
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+    let cancelled = sqlx::query!("SELECT subscriber_email FROM cancelled_deliveries")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(cancelled.len(), 1);
+    assert_eq!(cancelled[0].subscriber_email, "ursula_le_guin@gmail.com");
+
+    let html_page = app.get_issue_history_html(issue_id).await;
+    assert!(html_page
+        .contains("<p><i>The delivery has been cancelled, 1 email(s) will not be sent.</i></p>"));
+    assert!(html_page.contains("Delivery cancelled, 1 email(s) were not sent."));
+}
+
+#[tokio::test]
+async fn cancelled_deliveries_cannot_be_resumed() {
+    // Arrange
+    let app = spawn_app().await;
+    let issue_id = publish_issue(&app).await;
+    app.post_delivery_action(issue_id, "cancel").await;
+
+    // Act
+    let response = app.post_delivery_action(issue_id, "resume").await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/admin/issues/{}", issue_id));
+    let html_page = app.get_issue_history_html(issue_id).await;
+    assert!(html_page.contains("<p><i>Only paused deliveries can be resumed.</i></p>"));
+}
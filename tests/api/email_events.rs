@@ -0,0 +1,168 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+async fn create_confirmed_subscriber(app: &TestApp) {
+    app.test_user.login(app).await;
+    app.post_subscribers_import("confirmed", &format!("email,name\n{},Ursula\n", EMAIL))
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+fn event(event: &str, extra: serde_json::Value) -> serde_json::Value {
+    let mut event = serde_json::json!({
+        "EVENT": event,
+        "EMAIL": EMAIL,
+        "TIMESTAMP": "1690000000",
+    });
+    event
+        .as_object_mut()
+        .unwrap()
+        .extend(extra.as_object().unwrap().clone());
+    event
+}
+
+#[tokio::test]
+async fn events_with_an_invalid_signature_are_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+    let body =
+        serde_json::to_vec(&serde_json::json!([event("open", serde_json::json!({}))])).unwrap();
+
+    for signature in [None, Some("not-hex"), Some("deadbeef")] {
+        let mut request = app
+            .api_client
+            .post(format!("{}/webhooks/email-events", &app.address))
+            .body(body.clone());
+        if let Some(signature) = signature {
+            request = request.header("X-Signature", signature);
+        }
+
+        // Act
+        let response = request.send().await.unwrap();
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 401);
+    }
+}
+
+#[tokio::test]
+async fn a_malformed_payload_is_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_email_events(&serde_json::json!({"EVENT": "open"}))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn a_hard_bounce_marks_the_subscriber_as_bounced_and_suppresses_it() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    let response = app
+        .post_email_events(&serde_json::json!([event(
+            "bounce",
+            serde_json::json!({"BOUNCE_TYPE": "HARDBOUNCE"})
+        )]))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "bounced");
+
+    let suppression = sqlx::query!("SELECT email, reason FROM suppressions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(suppression.email.as_deref(), Some(EMAIL));
+    assert_eq!(suppression.reason, "hard_bounce");
+}
+
+#[tokio::test]
+async fn a_spam_complaint_marks_the_subscriber_as_complained() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // Act
+    app.post_email_events(&serde_json::json!([event("spam", serde_json::json!({}))]))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "complained");
+}
+
+#[tokio::test]
+async fn events_are_recorded_against_the_originating_newsletter_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // The issue id is attached to the email we send out
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let issue_id = body["personalization"][0]["x-apiheader"]
+        .as_str()
+        .unwrap()
+        .to_owned();
+
+    // Act
+    app.post_email_events(&serde_json::json!([event(
+        "open",
+        serde_json::json!({ "X-APIHEADER": issue_id })
+    )]))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Assert
+    let saved = sqlx::query!("SELECT newsletter_issue_id, event_type FROM email_events")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.newsletter_issue_id.unwrap().to_string(), issue_id);
+    assert_eq!(saved.event_type, "open");
+}
@@ -0,0 +1,137 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn publish_issue(app: &TestApp, title: &str) {
+    app.test_user.login(app).await;
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": title,
+            "text": "Hi {{ name }}, here is what's new.",
+            "html": "<p>Here is what's new.</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+async fn get_feed(app: &TestApp, feed: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}/{}", &app.address, feed))
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+#[tokio::test]
+async fn feeds_list_archived_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    publish_issue(&app, "Release notes & more").await;
+
+    for (feed, content_type) in [
+        ("feed.rss", "application/rss+xml"),
+        ("feed.atom", "application/atom+xml"),
+    ] {
+        // Act
+        let response = get_feed(&app, feed).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+        assert!(response.headers()["Content-Type"]
+            .to_str()
+            .unwrap()
+            .starts_with(content_type));
+
+        let body = response.text().await.unwrap();
+        assert!(body.contains("Release notes &amp; more"));
+        assert!(body.contains("Hi reader, here is what"));
+        assert!(body.contains("/issues/release-notes-more-"));
+    }
+}
+
+#[tokio::test]
+async fn feeds_answer_304_when_the_etag_matches() {
+    // Arrange
+    let app = spawn_app().await;
+    publish_issue(&app, "Release notes").await;
+    let response = get_feed(&app, "feed.rss").await;
+    let etag = response.headers()["ETag"].to_str().unwrap().to_owned();
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/feed.rss", &app.address))
+        .header("If-None-Match", &etag)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 304);
+    assert!(response.text().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn feed_etags_change_when_an_issue_is_published() {
+    // Arrange
+    let app = spawn_app().await;
+    publish_issue(&app, "Release notes").await;
+    let response = get_feed(&app, "feed.rss").await;
+    let etag = response.headers()["ETag"].to_str().unwrap().to_owned();
+    let atom_etag = get_feed(&app, "feed.atom").await.headers()["ETag"]
+        .to_str()
+        .unwrap()
+        .to_owned();
+    assert_ne!(etag, atom_etag);
+
+    // Act
+    publish_issue(&app, "More release notes").await;
+    let response = app
+        .api_client
+        .get(format!("{}/feed.rss", &app.address))
+        .header("If-None-Match", &etag)
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("More release notes"));
+}
+
+#[tokio::test]
+async fn feeds_answer_304_when_there_is_no_new_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    publish_issue(&app, "Release notes").await;
+    let response = get_feed(&app, "feed.atom").await;
+    let last_modified = response.headers()["Last-Modified"]
+        .to_str()
+        .unwrap()
+        .to_owned();
+
+    // Act - Part 1 - Nothing new
+    let response = app
+        .api_client
+        .get(format!("{}/feed.atom", &app.address))
+        .header("If-Modified-Since", &last_modified)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 304);
+
+    // Act - Part 2 - A new issue has been published
+    tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    publish_issue(&app, "More release notes").await;
+    let response = app
+        .api_client
+        .get(format!("{}/feed.atom", &app.address))
+        .header("If-Modified-Since", &last_modified)
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(response.status().as_u16(), 200);
+}
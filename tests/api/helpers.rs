@@ -2,14 +2,19 @@ use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
 use chrono::{DateTime, Utc};
 use once_cell::sync::Lazy;
+use secrecy::Secret;
 
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use uuid::Uuid;
 use wiremock::MockServer;
-use zero2prod::configuration::{get_configuration, DatabaseSettings};
+use zero2prod::configuration::{get_configuration, DatabaseSettings, Settings};
 use zero2prod::email_client::EmailClient;
 use zero2prod::idempotency::delete_expired_idempotency_keys;
-use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use zero2prod::issue_delivery_worker::{
+    run_worker_until_stopped, try_execute_task, ExecutionOutcome,
+};
+use zero2prod::shutdown::Shutdown;
+use zero2prod::signature::sign;
 use zero2prod::startup::{get_connection_pool, Application};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
@@ -21,6 +26,11 @@ pub struct TestApp {
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
     pub email_client: EmailClient,
+    pub webhook_secret: Secret<String>,
+    pub base_url: String,
+    pub hmac_secret: Secret<String>,
+    pub delivery_batch_size: u16,
+    pub configuration: Settings,
 }
 
 pub struct ConfirmationLinks {
@@ -81,6 +91,142 @@ impl TestApp {
         }
     }
 
+    /// Extract the link to the preference centre from the plain-text body of an issue,
+    /// as delivered to a recipient - see `get_delivered_emails`.
+    pub fn get_preferences_link(&self, email: &serde_json::Value) -> reqwest::Url {
+        let content = email["content"].as_array().unwrap();
+        let text = content.last().unwrap()["value"].as_str().unwrap();
+
+        let link = linkify::LinkFinder::new()
+            .links(text)
+            .find(|l| l.as_str().contains("/subscriptions/preferences"))
+            .unwrap();
+        let mut preferences_link = reqwest::Url::parse(link.as_str()).unwrap();
+
+        assert_eq!(preferences_link.host_str().unwrap(), "127.0.0.1");
+        preferences_link.set_port(Some(self.port)).unwrap();
+        preferences_link
+    }
+
+    /// The emails recipients got out of the requests received by the email server:
+    /// one per personalization, with its attributes filled in like the provider does.
+    pub async fn get_delivered_emails(&self) -> Vec<serde_json::Value> {
+        let mut emails = Vec::new();
+        for request in self.email_server.received_requests().await.unwrap() {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            for personalization in body["personalization"].as_array().unwrap() {
+                let fill = |s: &serde_json::Value| {
+                    let mut s = s.as_str().unwrap().to_owned();
+                    if let Some(attributes) = personalization["attributes"].as_object() {
+                        for (name, value) in attributes {
+                            s = s.replace(&format!("[%{}%]", name), value.as_str().unwrap());
+                        }
+                    }
+                    serde_json::Value::from(s)
+                };
+
+                let mut email = body.clone();
+                email["subject"] = fill(&body["subject"]);
+                for content in email["content"].as_array_mut().unwrap() {
+                    content["value"] = fill(&content["value"]);
+                }
+                email["personalization"] = serde_json::json!([personalization]);
+                emails.push(email);
+            }
+        }
+        emails
+    }
+
+    pub async fn post_preferences(&self, body: String) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/preferences", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_issues_archive_html(&self, page: u32) -> String {
+        self.api_client
+            .get(format!("{}/issues?page={}", &self.address, page))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_archived_issue(&self, id_or_slug: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/issues/{}", &self.address, id_or_slug))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_issues(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/issues", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_issue_history_html(&self, issue_id: Uuid) -> String {
+        self.api_client
+            .get(format!("{}/admin/issues/{}", &self.address, issue_id))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// `action` is one of `pause`, `resume` or `cancel`.
+    pub async fn post_delivery_action(&self, issue_id: Uuid, action: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/admin/issues/{}/{}",
+                &self.address, issue_id, action
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_layouts(&self) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/admin/layouts", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_layouts_html(&self) -> String {
+        self.get_layouts().await.text().await.unwrap()
+    }
+
+    pub async fn post_layout(&self, name: &str, html: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/layouts", &self.address))
+            .form(&serde_json::json!({ "name": name, "html": html }))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_list(&self, slug: &str, name: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/lists", &self.address))
+            .form(&serde_json::json!({ "slug": slug, "name": name }))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub async fn post_newsletters<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
@@ -172,18 +318,209 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    pub async fn post_subscribers_import(&self, mode: &str, csv: &str) -> reqwest::Response {
+        let form = reqwest::multipart::Form::new()
+            .text("mode", mode.to_owned())
+            .part(
+                "file",
+                reqwest::multipart::Part::text(csv.to_owned())
+                    .file_name("subscribers.csv")
+                    .mime_str("text/csv")
+                    .unwrap(),
+            );
+
+        self.api_client
+            .post(format!("{}/admin/subscribers/import", &self.address))
+            .multipart(form)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_attachment(
+        &self,
+        filename: &str,
+        content_type: &str,
+        content: Vec<u8>,
+        inline: bool,
+    ) -> reqwest::Response {
+        self.post_attachment_after("", filename, content_type, content, inline)
+            .await
+    }
+
+    /// Upload a file after `attachments`, the comma-separated ids of the
+    /// files uploaded so far.
+    pub async fn post_attachment_after(
+        &self,
+        attachments: &str,
+        filename: &str,
+        content_type: &str,
+        content: Vec<u8>,
+        inline: bool,
+    ) -> reqwest::Response {
+        let mut form = reqwest::multipart::Form::new();
+        if !attachments.is_empty() {
+            form = form.text("attachments", attachments.to_owned());
+        }
+        if inline {
+            form = form.text("inline", "true");
+        }
+        let form = form.part(
+            "file",
+            reqwest::multipart::Part::bytes(content)
+                .file_name(filename.to_owned())
+                .mime_str(content_type)
+                .unwrap(),
+        );
+
+        self.api_client
+            .post(format!("{}/admin/newsletters/attachments", &self.address))
+            .multipart(form)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_subscriber_tags(
+        &self,
+        email: &str,
+        tags: &str,
+        action: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/subscribers/tags", &self.address))
+            .form(&serde_json::json!({ "email": email, "tags": tags, "action": action }))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_newsletter_recipients_preview_html(
+        &self,
+        list: &str,
+        segment: &str,
+    ) -> String {
+        self.api_client
+            .get(format!("{}/admin/newsletters", &self.address))
+            .query(&[("list", list), ("segment", segment)])
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_suppression(&self, email: &str, reason: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/suppressions", &self.address))
+            .form(&serde_json::json!({ "email": email, "reason": reason }))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_remove_suppression(&self, email_hash: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/admin/suppressions/remove", &self.address))
+            .form(&serde_json::json!({ "email_hash": email_hash }))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_suppressions_import(&self, csv: &str) -> reqwest::Response {
+        let form = reqwest::multipart::Form::new().part(
+            "file",
+            reqwest::multipart::Part::text(csv.to_owned())
+                .file_name("suppressions.csv")
+                .mime_str("text/csv")
+                .unwrap(),
+        );
+
+        self.api_client
+            .post(format!("{}/admin/suppressions/import", &self.address))
+            .multipart(form)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_suppressions_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/suppressions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_email_events(&self, body: &serde_json::Value) -> reqwest::Response {
+        let body = serde_json::to_vec(body).unwrap();
+
+        self.api_client
+            .post(format!("{}/webhooks/email-events", &self.address))
+            .header("Content-Type", "application/json")
+            .header("X-Signature", sign(&self.webhook_secret, &body))
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_data_access_request(&self, email: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/data", &self.address))
+            .form(&serde_json::json!({ "email": email }))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_erase_subscriber_data(&self, token: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions/data/erase", &self.address))
+            .form(&serde_json::json!({ "token": token }))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
-                    .await
-                    .unwrap()
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.db_pool,
+                &self.email_client,
+                &self.base_url,
+                &self.hmac_secret,
+                self.delivery_batch_size,
+            )
+            .await
+            .unwrap()
             {
                 break;
             }
         }
     }
 
+    /// Run the delivery workers in the background, like the application does,
+    /// until `shutdown` is triggered.
+    pub fn spawn_delivery_workers(
+        &self,
+        poll_interval_seconds: u64,
+        shutdown: &Shutdown,
+    ) -> tokio::task::JoinHandle<Result<(), anyhow::Error>> {
+        let mut configuration = self.configuration.clone();
+        configuration.delivery.poll_interval_seconds = poll_interval_seconds;
+        tokio::spawn(run_worker_until_stopped(
+            configuration,
+            self.email_client.clone(),
+            shutdown.clone(),
+        ))
+    }
+
     pub async fn delete_all_expired_idempotency_keys(&self, expiration_time: DateTime<Utc>) {
         delete_expired_idempotency_keys(&self.db_pool, expiration_time)
             .await
@@ -281,7 +618,9 @@ pub async fn spawn_app() -> TestApp {
 
     configure_database(&configuration.database).await;
 
-    let application = Application::build(configuration.clone())
+    // Shared by the application and the workers dispatching emails in tests
+    let email_client = configuration.email_client.clone().client();
+    let application = Application::build(configuration.clone(), email_client.clone())
         .await
         .expect("Failed to build application");
 
@@ -302,7 +641,12 @@ pub async fn spawn_app() -> TestApp {
         email_server,
         test_user: TestUser::generate(),
         api_client: client,
-        email_client: configuration.email_client.client(),
+        webhook_secret: configuration.email_client.webhook_secret.clone(),
+        base_url: configuration.application.base_url.clone(),
+        hmac_secret: configuration.application.hmac_secret.clone(),
+        delivery_batch_size: configuration.delivery.batch_size,
+        email_client,
+        configuration,
     };
 
     test_app.test_user.store(&test_app.db_pool).await;
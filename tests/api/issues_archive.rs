@@ -0,0 +1,141 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn publish_issue(app: &TestApp, title: &str, html: &str, exclude_from_archive: bool) {
+    let mut form = serde_json::json!({
+        "title": title,
+        "text": "Newsletter body as plain text",
+        "html": html,
+        "layout": "none",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    if exclude_from_archive {
+        form["exclude_from_archive"] = "true".into();
+    }
+
+    let response = app.post_newsletters(&form).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+}
+
+#[tokio::test]
+async fn the_archive_lists_published_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_issue(&app, "Release notes", "<p>What's new</p>", false).await;
+    publish_issue(&app, "Internal memo", "<p>Secret</p>", true).await;
+    app.post_logout().await;
+
+    // Act
+    let html_page = app.get_issues_archive_html(1).await;
+
+    // Assert
+    assert!(html_page.contains("Release notes"));
+    assert!(!html_page.contains("Internal memo"));
+}
+
+#[tokio::test]
+async fn archived_issues_can_be_read_by_slug_or_id() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_issue(&app, "Release notes", "<p>What's new</p>", false).await;
+    let issue = sqlx::query!("SELECT newsletter_issue_id, slug FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the saved issue");
+    assert!(issue.slug.starts_with("release-notes-"));
+
+    for id_or_slug in [issue.slug, issue.newsletter_issue_id.to_string()] {
+        // Act
+        let response = app.get_archived_issue(&id_or_slug).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+        assert!(response.text().await.unwrap().contains("<p>What's new</p>"));
+    }
+}
+
+#[tokio::test]
+async fn issues_kept_out_of_the_archive_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_issue(&app, "Internal memo", "<p>Secret</p>", true).await;
+    let slug = sqlx::query!("SELECT slug FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the saved issue")
+        .slug;
+
+    // Act
+    let response = app.get_archived_issue(&slug).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn archived_issues_are_not_personalised() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    publish_issue(
+        &app,
+        "Release notes",
+        r#"<p>Hi {{ name }}</p><a href="{{ unsubscribe_url }}">Unsubscribe</a>"#,
+        false,
+    )
+    .await;
+    let slug = sqlx::query!("SELECT slug FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the saved issue")
+        .slug;
+
+    // Act
+    let html_page = app.get_archived_issue(&slug).await.text().await.unwrap();
+
+    // Assert
+    assert!(html_page.contains("reader"));
+    assert!(!html_page.contains("{{"));
+    assert!(!html_page.contains("signature="));
+}
+
+#[tokio::test]
+async fn the_archive_is_paginated() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    for i in 0..21 {
+        publish_issue(&app, &format!("Issue {}", i), "<p>Content</p>", false).await;
+    }
+
+    // Act - Part 1 - The most recent issues
+    let html_page = app.get_issues_archive_html(1).await;
+    assert!(html_page.contains("Issue 20"));
+    assert!(!html_page.contains("Issue 0<"));
+    assert!(html_page.contains("Older issues"));
+
+    // Act - Part 2 - The next page
+    let html_page = app.get_issues_archive_html(2).await;
+    assert!(html_page.contains("Issue 0<"));
+    assert!(html_page.contains("Newer issues"));
+    assert!(!html_page.contains("Older issues"));
+}
+
+#[tokio::test]
+async fn pages_out_of_range_are_not_found() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/issues?page={}", &app.address, i64::MAX))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+}
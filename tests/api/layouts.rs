@@ -0,0 +1,119 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+const LAYOUT: &str = r#"<!DOCTYPE html>
+<html>
+<head><style>.brand { color: #ff0000; }</style></head>
+<body><p class="brand">Acme</p>{{ content }}</body>
+</html>"#;
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_layouts() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_layouts().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn layouts_without_a_content_placeholder_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act - Part 1 - Create the layout
+    let response = app
+        .post_layout("Broken", "<html><body>No content</body></html>")
+        .await;
+    assert_is_redirect_to(&response, "/admin/layouts");
+
+    // Act - Part 2 - Follow the redirect
+    let html_page = app.get_layouts_html().await;
+
+    // Assert
+    assert!(html_page.contains("A layout must contain `{{ content }}` exactly once"));
+}
+
+#[tokio::test]
+async fn issues_are_wrapped_in_the_selected_layout() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_subscribers_import(
+        "confirmed",
+        "email,name\nursula@domain.com,Ursula Le Guin\n",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let response = app.post_layout("Acme", LAYOUT).await;
+    assert_is_redirect_to(&response, "/admin/layouts");
+    let layout_id = sqlx::query!("SELECT layout_id FROM email_layouts WHERE name = 'Acme'")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch the saved layout")
+        .layout_id;
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+            "layout": layout_id.to_string(),
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html = body["content"][0]["value"].as_str().unwrap();
+    assert!(html.contains("Acme"));
+    assert!(html.contains("<p>Newsletter body as HTML</p>"));
+    // The CSS has been inlined
+    assert!(!html.contains("<style>"));
+    assert!(html.contains("#ff0000"));
+}
+
+#[tokio::test]
+async fn confirmation_emails_use_the_default_layout() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions(body.into()).await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let html = body["content"][0]["value"].as_str().unwrap();
+    assert!(html.contains("zero2prod, 1 Newsletter Street"));
+    assert!(!html.contains("{{ email }}"));
+}
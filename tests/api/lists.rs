@@ -0,0 +1,144 @@
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// Subscribe `email` to `list` and follow the confirmation link.
+async fn create_confirmed_subscriber(app: &TestApp, email: &str, list: &str) {
+    let _mock_guard = Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": "Ursula",
+        "email": email,
+        "list": list
+    }))
+    .unwrap();
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_create_a_list() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.post_list("engineering-blog", "Engineering blog").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=Ursula&email=ursula%40domain.com&list=not-a-list".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn issues_are_only_delivered_to_subscribers_of_the_target_list() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    let response = app.post_list("engineering-blog", "Engineering blog").await;
+    assert_is_redirect_to(&response, "/admin/lists");
+
+    create_confirmed_subscriber(&app, "ursula@domain.com", "engineering-blog").await;
+    create_confirmed_subscriber(&app, "terry@domain.com", "newsletter").await;
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+            "list": "engineering-blog",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(
+        body["personalization"][0]["to"]["email"],
+        "ursula@domain.com"
+    );
+}
+
+#[tokio::test]
+async fn confirmed_subscribers_join_another_list_without_confirming_again() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_list("engineering-blog", "Engineering blog").await;
+    create_confirmed_subscriber(&app, "ursula@domain.com", "newsletter").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=Ursula&email=ursula%40domain.com&list=engineering-blog".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+
+    let n_lists = sqlx::query!("SELECT list_id FROM list_subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .len();
+    assert_eq!(n_lists, 2);
+}
@@ -1,8 +1,23 @@
+mod ab_testing;
+mod admin_cli;
 mod admin_dashboard;
+mod attachments;
 mod change_password;
+mod delivery_control;
+mod email_events;
+mod feeds;
 mod health_check;
 mod helpers;
+mod issues_archive;
+mod layouts;
+mod lists;
 mod login;
 mod newsletter;
+mod preferences;
+mod subscriber_data;
+mod subscribers_import;
 mod subscriptions;
 mod subscriptions_confirm;
+mod suppressions;
+mod tags;
+mod tracking;
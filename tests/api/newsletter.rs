@@ -9,7 +9,12 @@ use serde_json::Value;
 
 use wiremock::{
     matchers::{any, method, path},
-    Mock, ResponseTemplate,
+    Mock, Request, ResponseTemplate,
+};
+
+use zero2prod::{
+    issue_delivery_worker::count_sent_today, operations::requeue_failed_deliveries,
+    shutdown::Shutdown,
 };
 
 use crate::helpers::{assert_is_redirect_to, spawn_app, ConfirmationLinks, TestApp};
@@ -69,6 +74,537 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     app.dispatch_all_pending_emails().await;
 }
 
+#[tokio::test]
+async fn concurrent_workers_deliver_each_task_once() {
+    // Arrange
+    let mut app = spawn_app().await;
+    // Small enough for every worker to get a batch
+    app.delivery_batch_size = 2;
+    app.test_user.login(&app).await;
+    app.post_subscribers_import(
+        "confirmed",
+        "email,name\n\
+        ursula@domain.com,Ursula Le Guin\n\
+        octavia@domain.com,Octavia Butler\n\
+        ted@domain.com,Ted Chiang\n\
+        nnedi@domain.com,Nnedi Okorafor\n\
+        becky@domain.com,Becky Chambers\n",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_millis(50)))
+        .expect(3..=5)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = create_publish_newsletter_form_data();
+    let response = app.post_newsletters(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act
+    futures_util::future::join_all((0..3).map(|_| app.dispatch_all_pending_emails())).await;
+
+    // Assert
+    let mut recipients: Vec<String> = app
+        .get_delivered_emails()
+        .await
+        .iter()
+        .map(|body| {
+            body["personalization"][0]["to"]["email"]
+                .as_str()
+                .unwrap()
+                .to_owned()
+        })
+        .collect();
+    assert_eq!(recipients.len(), 5);
+    recipients.sort();
+    recipients.dedup();
+    assert_eq!(recipients.len(), 5);
+}
+
+#[tokio::test]
+async fn recipients_of_an_issue_are_sent_to_in_a_single_request() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_subscribers_import(
+        "confirmed",
+        "email,name\n\
+        ursula@domain.com,Ursula Le Guin\n\
+        octavia@domain.com,Octavia Butler\n\
+        ted@domain.com,Ted Chiang\n",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text": "Hi {{ name }}",
+            "html": "<p>Hi {{ name }}</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let emails = app.get_delivered_emails().await;
+    assert_eq!(emails.len(), 3);
+    for email in emails {
+        let name = email["personalization"][0]["to"]["name"].as_str().unwrap();
+        let text = email["content"][1]["value"].as_str().unwrap();
+        assert!(text.starts_with(&format!("Hi {}", name)));
+    }
+}
+
+#[tokio::test]
+async fn recipients_rejected_by_the_provider_do_not_hold_back_the_others() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_subscribers_import(
+        "confirmed",
+        "email,name\n\
+        ursula@domain.com,Ursula Le Guin\n\
+        octavia@domain.com,Octavia Butler\n",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let is_rejected = |r: &Request| String::from_utf8_lossy(&r.body).contains("octavia@domain.com");
+    // The batch, then Octavia on her own
+    Mock::given(path("/v5/mail/send"))
+        .and(is_rejected)
+        .respond_with(ResponseTemplate::new(400))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/v5/mail/send"))
+        .and(move |r: &Request| !is_rejected(r))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(&create_publish_newsletter_form_data())
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let tasks = sqlx::query!(
+        r#"
+        SELECT subscriber_email, delivered_at, n_retries, execute_after > now() AS "backing_off!"
+        FROM issue_delivery_queue
+        ORDER BY subscriber_email
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(tasks[0].subscriber_email, "octavia@domain.com");
+    assert!(tasks[0].delivered_at.is_none());
+    assert_eq!(tasks[0].n_retries, 1);
+    assert!(tasks[0].backing_off);
+    assert!(tasks[1].delivered_at.is_some());
+}
+
+#[tokio::test]
+async fn send_attempts_are_recorded_with_the_provider_message_id() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(serde_json::json!({"data": {"message_id": "provider-id"}})),
+        )
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(&create_publish_newsletter_form_data())
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let attempt = sqlx::query!("SELECT sent_at, error, provider_message_id FROM deliveries")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(attempt.sent_at.is_some());
+    assert!(attempt.error.is_none());
+    assert_eq!(attempt.provider_message_id.as_deref(), Some("provider-id"));
+}
+
+#[tokio::test]
+async fn retried_tasks_skip_the_emails_that_already_went_out() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_subscribers_import(
+        "confirmed",
+        "email,name\n\
+        ursula@domain.com,Ursula Le Guin\n\
+        octavia@domain.com,Octavia Butler\n",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // Octavia only
+    Mock::given(path("/v5/mail/send"))
+        .and(|r: &Request| !String::from_utf8_lossy(&r.body).contains("ursula@domain.com"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&create_publish_newsletter_form_data())
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // A worker sent to Ursula, then crashed before completing the task
+    sqlx::query!(
+        r#"
+        INSERT INTO deliveries (
+            attempt_id,
+            newsletter_issue_id,
+            subscriber_email,
+            idempotency_key,
+            attempted_at,
+            sent_at
+        )
+        SELECT $1, newsletter_issue_id, 'ursula@domain.com', 'key', now(), now()
+        FROM newsletter_issues
+        "#,
+        uuid::Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let n_undelivered = sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM issue_delivery_queue WHERE delivered_at IS NULL"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_undelivered, 0);
+}
+
+#[tokio::test]
+async fn attempts_without_a_recorded_outcome_are_given_up_on() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    let mock_guard = Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&create_publish_newsletter_form_data())
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // A worker crashed while sending: the email may or may not have gone out
+    sqlx::query!(
+        r#"
+        INSERT INTO deliveries (
+            attempt_id,
+            newsletter_issue_id,
+            subscriber_email,
+            idempotency_key,
+            attempted_at
+        )
+        SELECT $1, i.newsletter_issue_id, s.email, 'key', now()
+        FROM newsletter_issues i, subscriptions s
+        "#,
+        uuid::Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act - Part 1 - Retry the task
+    app.dispatch_all_pending_emails().await;
+
+    // Assert - Given up on, not reported as delivered
+    let task =
+        sqlx::query!("SELECT delivered_at, failed_at, failure_reason FROM issue_delivery_queue")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert!(task.delivered_at.is_none());
+    assert!(task.failed_at.is_some());
+    assert_eq!(task.failure_reason.as_deref(), Some("in_doubt"));
+    drop(mock_guard);
+
+    // Act - Part 2 - An admin requeues it
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    requeue_failed_deliveries(&app.db_pool, None).await.unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let task = sqlx::query!("SELECT delivered_at, failure_reason FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(task.delivered_at.is_some());
+    assert!(task.failure_reason.is_none());
+}
+
+#[tokio::test]
+async fn emails_sent_today_are_counted_from_the_send_log() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&create_publish_newsletter_form_data())
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    assert_eq!(count_sent_today(&app.db_pool).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn recipients_are_given_up_on_after_repeated_failures() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&create_publish_newsletter_form_data())
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Act - Run out of retries
+    sqlx::query!("UPDATE issue_delivery_queue SET n_retries = 5, execute_after = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let issue_id = sqlx::query!(
+        "SELECT newsletter_issue_id FROM issue_delivery_queue WHERE failed_at IS NOT NULL"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id;
+    let html_page = app.get_issue_history_html(issue_id).await;
+    assert!(html_page.contains("Delivered to 0 out of 1 recipient(s)."));
+    assert!(html_page.contains("Gave up on 1 recipient(s) after repeated failures."));
+}
+
+#[tokio::test]
+async fn throttled_deliveries_are_postponed_for_as_long_as_the_provider_asks() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "120"))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(&create_publish_newsletter_form_data())
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let task = sqlx::query!(
+        r#"
+        SELECT
+            delivered_at,
+            n_retries,
+            execute_after > now() + interval '100 seconds' AS "postponed!"
+        FROM issue_delivery_queue
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(task.delivered_at.is_none());
+    // Not a failure: it does not count towards the retries
+    assert_eq!(task.n_retries, 0);
+    assert!(task.postponed);
+}
+
+#[tokio::test]
+async fn idle_workers_are_woken_up_as_soon_as_an_issue_is_published() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Polling alone would not get there before the end of the test
+    let shutdown = Shutdown::new();
+    let workers = app.spawn_delivery_workers(3600, &shutdown);
+    // Let them find the queue empty and go idle
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // Act
+    let response = app
+        .post_newsletters(&create_publish_newsletter_form_data())
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Assert
+    let mut n_delivered = 0;
+    for _ in 0..50 {
+        n_delivered =
+            sqlx::query!(r#"SELECT COUNT(delivered_at) AS "count!" FROM issue_delivery_queue"#)
+                .fetch_one(&app.db_pool)
+                .await
+                .unwrap()
+                .count;
+        if n_delivered > 0 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    shutdown.trigger();
+    workers.await.unwrap().unwrap();
+    assert_eq!(n_delivered, 1);
+}
+
+#[tokio::test]
+async fn idle_workers_stop_as_soon_as_shutdown_is_triggered() {
+    // Arrange
+    let app = spawn_app().await;
+    let shutdown = Shutdown::new();
+    let workers = app.spawn_delivery_workers(3600, &shutdown);
+    // Let them find the queue empty and go idle
+    tokio::time::sleep(Duration::from_millis(500)).await;
+
+    // Act
+    shutdown.trigger();
+
+    // Assert
+    tokio::time::timeout(Duration::from_secs(5), workers)
+        .await
+        .expect("Workers did not stop")
+        .unwrap()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn workers_finish_the_batch_they_are_delivering_before_stopping() {
+    // Arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(1)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&create_publish_newsletter_form_data())
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let shutdown = Shutdown::new();
+    let workers = app.spawn_delivery_workers(3600, &shutdown);
+    // Let a worker pick the task up and wait on the provider
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    // Act
+    shutdown.trigger();
+    tokio::time::timeout(Duration::from_secs(5), workers)
+        .await
+        .expect("Workers did not stop")
+        .unwrap()
+        .unwrap();
+
+    // Assert
+    let n_delivered =
+        sqlx::query!(r#"SELECT COUNT(delivered_at) AS "count!" FROM issue_delivery_queue"#)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap()
+            .count;
+    assert_eq!(n_delivered, 1);
+}
+
 #[tokio::test]
 async fn newsletters_returns_400_for_invalid_data() {
     // Arrange
@@ -226,6 +762,104 @@ async fn expired_idempotency_keys_are_deleted() {
     assert_eq!(remaining_keys, 0);
 }
 
+#[tokio::test]
+async fn merge_tags_are_filled_in_for_each_recipient() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_subscribers_import(
+        "confirmed",
+        "email,name\nursula@domain.com,Ursula & Terry\n",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text": "Hi {{ name }}, this was sent to {{ email }}",
+            "html": "<p>Hi {{ name }}</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let body = &app.get_delivered_emails().await[0];
+    assert_eq!(body["personalization"][0]["to"]["name"], "Ursula & Terry");
+
+    let html = body["content"][0]["value"].as_str().unwrap();
+    assert!(!html.contains("{{"));
+    assert!(!html.contains("Ursula & Terry"));
+
+    let text = body["content"][1]["value"].as_str().unwrap();
+    assert!(text.starts_with("Hi Ursula & Terry, this was sent to ursula@domain.com"));
+}
+
+#[tokio::test]
+async fn unknown_merge_tags_are_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text": "Hi {{ first_name }}",
+            "html": "<p>Hi {{ name }}</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn issues_can_be_written_in_markdown() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let markdown =
+        "# Hello\n\nRead [the docs](https://example.com/docs).\n\n<script>alert('hi')</script>";
+
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "markdown": markdown,
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    let saved =
+        sqlx::query!("SELECT html_content, text_content, markdown_content FROM newsletter_issues")
+            .fetch_one(&app.db_pool)
+            .await
+            .expect("Failed to fetch saved issue");
+    assert!(saved.html_content.contains("<h1>Hello</h1>"));
+    assert!(!saved.html_content.contains("<script>"));
+    assert_eq!(
+        saved.text_content,
+        "Hello\n\nRead the docs [1].\n\n[1] https://example.com/docs"
+    );
+    assert_eq!(saved.markdown_content.as_deref(), Some(markdown));
+}
+
 async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
     let name: String = Name().fake();
     let email: String = SafeEmail().fake();
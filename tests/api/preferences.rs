@@ -0,0 +1,255 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// Send an issue to a single confirmed subscriber and return the link to
+/// their preference centre found in the email.
+async fn get_preferences_link(app: &TestApp) -> reqwest::Url {
+    app.test_user.login(app).await;
+    app.post_subscribers_import(
+        "confirmed",
+        "email,name\nursula@domain.com,Ursula Le Guin\n",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    app.post_list("rust", "Rust digest")
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let _mock_guard = Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .named("Issue delivery")
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_newsletters(&serde_json::json!({
+        "title": "Newsletter title",
+        "text": "Newsletter body as plain text",
+        "html": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    let email = &app.get_delivered_emails().await[0];
+    app.get_preferences_link(email)
+}
+
+#[tokio::test]
+async fn issues_link_to_the_preference_centre() {
+    // Arrange
+    let app = spawn_app().await;
+    let preferences_link = get_preferences_link(&app).await;
+
+    // Act
+    let response = reqwest::get(preferences_link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(&htmlescape::encode_attribute("Ursula Le Guin")));
+    assert!(html_page.contains("Rust digest"));
+}
+
+#[tokio::test]
+async fn the_preference_centre_rejects_tampered_links() {
+    // Arrange
+    let app = spawn_app().await;
+    let mut preferences_link = get_preferences_link(&app).await;
+    let subscriber_id = preferences_link
+        .query_pairs()
+        .find(|(k, _)| k == "subscriber_id")
+        .unwrap()
+        .1
+        .into_owned();
+    preferences_link.set_query(Some(&format!(
+        "subscriber_id={}&signature=00",
+        subscriber_id
+    )));
+
+    // Act
+    let response = reqwest::get(preferences_link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn subscribers_can_update_their_preferences() {
+    // Arrange
+    let app = spawn_app().await;
+    let preferences_link = get_preferences_link(&app).await;
+    let query = preferences_link.query().unwrap();
+
+    // Act
+    let response = app
+        .post_preferences(format!(
+            "{}&name=Ursula%20K.%20Le%20Guin&lists=rust&text_only=true",
+            query
+        ))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/subscriptions/preferences?{}", query));
+
+    let saved = sqlx::query!(
+        r#"
+        SELECT s.name AS "name!", s.text_only AS "text_only!", l.slug AS "slug!"
+        FROM subscriptions s
+        JOIN list_subscriptions ls ON ls.subscriber_id = s.id
+        JOIN lists l ON l.list_id = ls.list_id
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .expect("Failed to fetch saved preferences");
+    assert_eq!(saved.name, "Ursula K. Le Guin");
+    assert!(saved.text_only);
+    assert_eq!(saved.slug, "rust");
+}
+
+#[tokio::test]
+async fn an_invalid_name_is_not_saved() {
+    // Arrange
+    let app = spawn_app().await;
+    let preferences_link = get_preferences_link(&app).await;
+    let query = preferences_link.query().unwrap();
+
+    // Act
+    let response = app
+        .post_preferences(format!("{}&name=%3Cscript%3E&lists=newsletter", query))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/subscriptions/preferences?{}", query));
+
+    let saved = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.name, "Ursula Le Guin");
+}
+
+#[tokio::test]
+async fn text_only_subscribers_do_not_receive_html() {
+    // Arrange
+    let app = spawn_app().await;
+    let preferences_link = get_preferences_link(&app).await;
+    app.post_preferences(format!(
+        "{}&name=Ursula%20Le%20Guin&lists=newsletter&text_only=true",
+        preferences_link.query().unwrap()
+    ))
+    .await;
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_newsletters(&serde_json::json!({
+        "title": "Second issue",
+        "text": "Newsletter body as plain text",
+        "html": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request[1].body).unwrap();
+    let content = body["content"].as_array().unwrap();
+    assert_eq!(content.len(), 1);
+    assert_eq!(content[0]["type"], "text");
+}
+
+#[tokio::test]
+async fn subscribers_can_unsubscribe_from_everything() {
+    // Arrange
+    let app = spawn_app().await;
+    let preferences_link = get_preferences_link(&app).await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!(
+            "{}/subscriptions/preferences/unsubscribe",
+            &app.address
+        ))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(preferences_link.query().unwrap().to_owned())
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.status, "unsubscribed");
+
+    let n_lists = sqlx::query!(r#"SELECT count(*) AS "n!" FROM list_subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_lists, 0);
+}
+
+#[tokio::test]
+async fn subscribers_cannot_join_lists_again_after_unsubscribing_from_everything() {
+    // Arrange
+    let app = spawn_app().await;
+    let preferences_link = get_preferences_link(&app).await;
+    let query = preferences_link.query().unwrap();
+    app.api_client
+        .post(format!(
+            "{}/subscriptions/preferences/unsubscribe",
+            &app.address
+        ))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(query.to_owned())
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_preferences(format!("{}&name=Ursula%20Le%20Guin&lists=rust", query))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &format!("/subscriptions/preferences?{}", query));
+    let html_page = app
+        .api_client
+        .get(preferences_link.clone())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("You have unsubscribed from all our emails"));
+
+    let n_lists = sqlx::query!(r#"SELECT count(*) AS "n!" FROM list_subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_lists, 0);
+}
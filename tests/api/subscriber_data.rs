@@ -0,0 +1,329 @@
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use zero2prod::domain::SubscriberEmail;
+use zero2prod::operations::confirm_address;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+async fn create_subscriber(app: &TestApp) {
+    let _mock_guard = Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+/// Request a data access link and return the token it carries.
+async fn request_data_access_token(app: &TestApp) -> String {
+    let _mock_guard = Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+
+    let response = app.post_data_access_request(EMAIL).await;
+    assert_is_redirect_to(&response, "/subscriptions/data");
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let links = app.get_confirmation_links(&email_request);
+    assert_eq!(links.html.path(), "/subscriptions/data/manage");
+
+    links
+        .html
+        .query_pairs()
+        .find(|(k, _)| k == "token")
+        .unwrap()
+        .1
+        .into_owned()
+}
+
+#[tokio::test]
+async fn no_email_is_sent_for_an_unknown_address() {
+    // Arrange
+    let app = spawn_app().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_data_access_request(EMAIL).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/subscriptions/data");
+}
+
+#[tokio::test]
+async fn subscribers_can_download_their_data() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+    app.post_email_events(&serde_json::json!([{
+        "EVENT": "delivered",
+        "EMAIL": EMAIL,
+        "TIMESTAMP": "1690000000",
+    }]))
+    .await
+    .error_for_status()
+    .unwrap();
+    let token = request_data_access_token(&app).await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/subscriptions/data/export", &app.address))
+        .query(&[("token", &token)])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["subscription"]["email"], EMAIL);
+    assert_eq!(export["subscription"]["status"], "pending_confirmation");
+    assert_eq!(export["subscription_tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(export["email_events"][0]["event_type"], "delivered");
+}
+
+#[tokio::test]
+async fn the_export_includes_the_issues_delivered_to_the_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_subscribers_import("confirmed", &format!("email,name\n{},Ursula\n", EMAIL))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    {
+        let _mock_guard = Mock::given(path("/v5/mail/send"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount_as_scoped(&app.email_server)
+            .await;
+
+        let response = app
+            .post_newsletters(&serde_json::json!({
+                "title": "Newsletter title",
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+                "idempotency_key": uuid::Uuid::new_v4().to_string()
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/admin/newsletters");
+        app.dispatch_all_pending_emails().await;
+    }
+
+    let token = request_data_access_token(&app).await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/subscriptions/data/export", &app.address))
+        .query(&[("token", &token)])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    let export: serde_json::Value = response.json().await.unwrap();
+    let history = export["delivery_history"].as_array().unwrap();
+    assert_eq!(history.len(), 1);
+    assert_eq!(history[0]["title"], "Newsletter title");
+    assert!(history[0]["delivered_at"].is_string());
+    let send_attempts = export["send_attempts"].as_array().unwrap();
+    assert_eq!(send_attempts.len(), 1);
+    assert!(send_attempts[0]["sent_at"].is_string());
+}
+
+#[tokio::test]
+async fn the_export_includes_cancelled_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+    let email = SubscriberEmail::parse(EMAIL.into()).unwrap();
+    confirm_address(&app.db_pool, &email).await.unwrap();
+
+    app.test_user.login(&app).await;
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    app.post_delivery_action(issue_id, "cancel").await;
+
+    let token = request_data_access_token(&app).await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/subscriptions/data/export", &app.address))
+        .query(&[("token", &token)])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["pending_deliveries"].as_array().unwrap().len(), 0);
+    assert_eq!(
+        export["cancelled_deliveries"][0]["title"],
+        "Newsletter title"
+    );
+}
+
+#[tokio::test]
+async fn the_export_includes_tracked_clicks() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO email_events (
+            email_event_id,
+            subscriber_id,
+            subscriber_email,
+            event_type,
+            url,
+            occurred_at,
+            received_at
+        )
+        SELECT gen_random_uuid(), id, email, 'click', 'https://example.com/docs', now(), now()
+        FROM subscriptions
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let token = request_data_access_token(&app).await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/subscriptions/data/export", &app.address))
+        .query(&[("token", &token)])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    let export: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(export["email_events"][0]["event_type"], "click");
+    assert_eq!(export["email_events"][0]["url"], "https://example.com/docs");
+}
+
+#[tokio::test]
+async fn an_invalid_token_is_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/subscriptions/data/export", &app.address))
+        .query(&[("token", "not-a-token")])
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn erased_subscribers_are_deleted_and_cannot_be_reimported() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+    app.post_email_events(&serde_json::json!([{
+        "EVENT": "delivered",
+        "EMAIL": EMAIL,
+        "TIMESTAMP": "1690000000",
+    }]))
+    .await
+    .error_for_status()
+    .unwrap();
+    let token = request_data_access_token(&app).await;
+
+    // Act - Part 1 - Erase
+    let response = app.post_erase_subscriber_data(&token).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // Assert
+    let n_subscribers = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .len();
+    assert_eq!(n_subscribers, 0);
+    let n_events = sqlx::query!("SELECT email_event_id FROM email_events")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .len();
+    assert_eq!(n_events, 0);
+
+    // Act - Part 2 - Import the erased address again
+    app.test_user.login(&app).await;
+    let response = app
+        .post_subscribers_import("confirmed", &format!("email,name\n{},Ursula\n", EMAIL))
+        .await;
+
+    // Assert
+    let report = response.text().await.unwrap();
+    assert!(report.contains("Imported 0 out of 1 subscribers."));
+    assert!(report.contains("The address is on the suppression list"));
+}
+
+#[tokio::test]
+async fn erasing_a_suppressed_subscriber_does_not_keep_their_address() {
+    // Arrange
+    let app = spawn_app().await;
+    create_subscriber(&app).await;
+    let token = request_data_access_token(&app).await;
+    app.test_user.login(&app).await;
+    app.post_suppression(EMAIL, "hard_bounce").await;
+
+    // Act
+    let response = app.post_erase_subscriber_data(&token).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let suppression = sqlx::query!("SELECT email, reason FROM suppressions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(suppression.email, None);
+    assert_eq!(suppression.reason, "hard_bounce");
+}
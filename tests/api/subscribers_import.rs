@@ -0,0 +1,231 @@
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+
+use zero2prod::domain::SubscriberEmail;
+use zero2prod::operations::unsubscribe_address;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_import_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscribers_import("confirmed", "email,name\nursula@domain.com,Ursula\n")
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_imported_as_confirmed() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscribers_import(
+            "confirmed",
+            "name,email\nUrsula Le Guin,ursula@domain.com\nTerry Pratchett,terry@domain.com\n",
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Imported 2 out of 2 subscribers."));
+
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions");
+
+    assert_eq!(saved.len(), 2);
+    assert_eq!(saved[0].email, "terry@domain.com");
+    assert!(saved.iter().all(|s| s.status == "confirmed"));
+}
+
+#[tokio::test]
+async fn imported_subscribers_receive_a_confirmation_email() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscribers_import(
+            "send_confirmation",
+            "email,name\nursula@domain.com,Ursula Le Guin\nterry@domain.com,Terry Pratchett",
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let report = response.text().await.unwrap();
+    assert!(report.contains("Confirmation emails are being sent to 2 new subscribers."));
+
+    let n_tokens = sqlx::query!("SELECT subscription_token FROM subscription_tokens")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .len();
+    assert_eq!(n_tokens, 2);
+
+    // The emails go out after the response
+    let mut n_sent = 0;
+    for _ in 0..50 {
+        n_sent = app.email_server.received_requests().await.unwrap().len();
+        if n_sent == 2 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    }
+    assert_eq!(n_sent, 2);
+}
+
+#[tokio::test]
+async fn invalid_and_duplicated_rows_are_reported_and_skipped() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_subscribers_import(
+            "confirmed",
+            "email,name\n\
+            ursula@domain.com,Ursula Le Guin\n\
+            not-an-email,Terry Pratchett\n\
+            ursula@domain.com,Ursula Again\n\
+            terry@domain.com,\n",
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let report = response.text().await.unwrap();
+    assert!(report.contains("Imported 1 out of 4 subscribers."));
+    assert!(report.contains("not-an-email is not a valid subscriber email"));
+    assert!(report.contains("The email is already subscribed"));
+    assert!(report.contains(" is not a valid subscriber name"));
+}
+
+#[tokio::test]
+async fn addresses_that_look_mistyped_are_imported_as_they_are() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_subscribers_import("confirmed", "email,name\nursula@gmial.com,Ursula Le Guin\n")
+        .await;
+
+    // Assert
+    let report = response.text().await.unwrap();
+    assert!(report.contains("Imported 1 out of 1 subscribers."));
+}
+
+#[tokio::test]
+async fn addresses_differing_only_by_case_are_imported_once() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_subscribers_import(
+            "confirmed",
+            "email,name\nUrsula@Domain.com,Ursula Le Guin\nursula@domain.com,Ursula Again\n",
+        )
+        .await;
+
+    // Assert
+    let report = response.text().await.unwrap();
+    assert!(report.contains("Imported 1 out of 2 subscribers."));
+    assert!(report.contains("The email is already subscribed"));
+}
+
+#[tokio::test]
+async fn importing_an_unsubscribed_address_again_does_not_resubscribe_it() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_subscribers_import("confirmed", "email,name\nursula@domain.com,Ursula\n")
+        .await
+        .error_for_status()
+        .unwrap();
+    let email = SubscriberEmail::parse("ursula@domain.com".into()).unwrap();
+    assert!(unsubscribe_address(&app.db_pool, &email).await.unwrap());
+
+    // Act
+    let response = app
+        .post_subscribers_import(
+            "confirmed",
+            "email,name,tags\nursula@domain.com,Ursula,beta\n",
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let subscriber = sqlx::query!("SELECT id, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscriber.status, "unsubscribed");
+    let n_lists = sqlx::query!(
+        "SELECT list_id FROM list_subscriptions WHERE subscriber_id = $1",
+        subscriber.id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .len();
+    assert_eq!(n_lists, 0);
+    let n_tags = sqlx::query!(
+        "SELECT tag FROM subscriber_tags WHERE subscriber_id = $1",
+        subscriber.id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .len();
+    assert_eq!(n_tags, 0);
+}
+
+#[tokio::test]
+async fn a_csv_file_without_the_expected_columns_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_subscribers_import("confirmed", "mail,full_name\nursula@domain.com,Ursula\n")
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
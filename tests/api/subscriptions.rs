@@ -156,3 +156,94 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     // Assert
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn subscribe_returns_a_400_for_disposable_or_mistyped_addresses() {
+    // Arrange
+    let app = spawn_app().await;
+
+    let test_cases = vec![
+        (
+            "name=Ursula&email=ursula%40mailinator.com",
+            "disposable email",
+        ),
+        ("name=Ursula&email=ursula%40gmial.com", "mistyped domain"),
+    ];
+
+    for (body, desc) in test_cases {
+        // Act
+        let resp = app.post_subscriptions(body.into()).await;
+
+        // Assert
+        assert_eq!(
+            400,
+            resp.status().as_u16(),
+            "The API did not return a 400 when the payload had a {}",
+            desc
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscribers_can_keep_an_address_that_looks_mistyped() {
+    // Arrange
+    let app = spawn_app().await;
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Get a suggestion
+    let response = app
+        .post_subscriptions("name=Ursula&email=ursula%40gmial.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("did you mean ursula@gmail.com?"));
+
+    // Act - Part 2 - Keep the address as typed
+    let response = app
+        .post_subscriptions("name=Ursula&email=ursula%40gmial.com&keep_email=true".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula@gmial.com");
+}
+
+#[tokio::test]
+async fn subscribe_normalises_the_email_address() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=%20Ursula_Le_Guin%40GMail.com%20";
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    app.post_subscriptions(body.into()).await;
+
+    // Assert
+    let saved = sqlx::query!("SELECT email, email_key FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+
+    assert_eq!(saved.email, "Ursula_Le_Guin@gmail.com");
+    assert_eq!(saved.email_key, "ursula_le_guin@gmail.com");
+}
@@ -0,0 +1,154 @@
+use wiremock::{
+    matchers::{any, method, path},
+    Mock, ResponseTemplate,
+};
+use zero2prod::suppression::email_hash;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_suppression_list() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/admin/suppressions", &app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn suppressed_addresses_do_not_receive_a_confirmation_email() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    let response = app.post_suppression(EMAIL, "hard_bounce").await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn newsletters_are_not_delivered_to_suppressed_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_subscribers_import("confirmed", &format!("email,name\n{},Ursula\n", EMAIL))
+        .await;
+    app.post_suppression(EMAIL, "complaint").await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Assert
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn subscribers_suppressed_after_an_issue_is_published_do_not_receive_it() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_subscribers_import("confirmed", &format!("email,name\n{},Ursula\n", EMAIL))
+        .await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+
+    // Act
+    app.post_suppression(EMAIL, "complaint").await;
+
+    // Assert
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn lifting_a_suppression_allows_sending_again() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+    app.post_suppression(EMAIL, "manual").await;
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app.post_remove_suppression(&email_hash(EMAIL)).await;
+    assert_is_redirect_to(&response, "/admin/suppressions");
+
+    // Assert
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn a_suppression_list_can_be_imported_from_csv() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_suppressions_import("email\nursula@domain.com\nnot-an-email\nterry@domain.com\n")
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/suppressions");
+    let html_page = app.get_suppressions_html().await;
+    assert!(html_page.contains("2 addresses have been added to the suppression list."));
+    assert!(html_page.contains("Rows with an invalid email have been skipped: 2."));
+    assert!(html_page.contains("terry@domain.com"));
+}
@@ -0,0 +1,145 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn import_tagged_subscribers(app: &TestApp) {
+    app.test_user.login(app).await;
+    app.post_subscribers_import(
+        "confirmed",
+        "email,name,tags\n\
+        ursula@domain.com,Ursula Le Guin,beta\n\
+        terry@domain.com,Terry Pratchett,beta;churned\n\
+        iain@domain.com,Iain Banks,\n",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_tag_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriber_tags("ursula@domain.com", "beta", "add")
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn tags_can_be_added_and_removed() {
+    // Arrange
+    let app = spawn_app().await;
+    import_tagged_subscribers(&app).await;
+
+    // Act - Part 1 - Add tags
+    let response = app
+        .post_subscriber_tags("Iain@Domain.com", "paying Region:EU", "add")
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers/tags");
+
+    // Act - Part 2 - Remove a tag
+    let response = app
+        .post_subscriber_tags("iain@domain.com", "paying", "remove")
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers/tags");
+
+    // Assert
+    let tags: Vec<String> = sqlx::query!(
+        r#"
+        SELECT t.tag AS "tag!"
+        FROM subscriber_tags t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE s.email = 'iain@domain.com'
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| r.tag)
+    .collect();
+    assert_eq!(tags, ["region:eu"]);
+}
+
+#[tokio::test]
+async fn the_publish_form_previews_how_many_subscribers_a_segment_matches() {
+    // Arrange
+    let app = spawn_app().await;
+    import_tagged_subscribers(&app).await;
+
+    // Act - Part 1 - A valid segment
+    let html_page = app
+        .get_newsletter_recipients_preview_html("newsletter", "tag beta AND NOT tag churned")
+        .await;
+    assert!(html_page.contains("The issue would be sent to 1 subscriber(s)."));
+
+    // Act - Part 2 - An invalid segment
+    let html_page = app
+        .get_newsletter_recipients_preview_html("newsletter", "tag beta AND")
+        .await;
+    assert!(html_page.contains("Invalid segment"));
+}
+
+#[tokio::test]
+async fn an_invalid_segment_is_rejected_with_a_400() {
+    // Arrange
+    let app = spawn_app().await;
+    app.test_user.login(&app).await;
+
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+            "segment": "beta OR",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn segment_targeted_issues_are_only_delivered_to_matching_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    import_tagged_subscribers(&app).await;
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_newsletters(&serde_json::json!({
+            "title": "Newsletter title",
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+            "segment": "tag beta AND NOT tag churned",
+            "idempotency_key": uuid::Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    // Assert
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(
+        body["personalization"][0]["to"]["email"],
+        "ursula@domain.com"
+    );
+}
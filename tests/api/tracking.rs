@@ -0,0 +1,201 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// Publish an issue linking to the docs and send it to a single subscriber,
+/// returning the HTML body they received.
+async fn send_issue(app: &TestApp, tracking: bool) -> String {
+    app.test_user.login(app).await;
+    app.post_subscribers_import(
+        "confirmed",
+        "email,name\nursula@domain.com,Ursula Le Guin\n",
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    Mock::given(path("/v5/mail/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let mut body = serde_json::json!({
+        "title": "Newsletter title",
+        "text": "Newsletter body as plain text",
+        "html": r#"<p>Read <a href="https://example.com/docs">the docs</a></p>"#,
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    if tracking {
+        body["track_opens"] = "true".into();
+        body["track_clicks"] = "true".into();
+    }
+    let response = app.post_newsletters(&body).await;
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+
+    let body = &app.get_delivered_emails().await[0];
+    body["content"][0]["value"].as_str().unwrap().to_owned()
+}
+
+/// The first URL in `html` containing `marker`, pointing to the test app.
+fn find_url(app: &TestApp, html: &str, marker: &str) -> reqwest::Url {
+    let i = html.find(marker).unwrap();
+    let start = html[..i].rfind('"').unwrap() + 1;
+    let end = html[i..].find('"').unwrap() + i;
+
+    let mut url = reqwest::Url::parse(&html[start..end]).unwrap();
+    assert_eq!(url.host_str().unwrap(), "127.0.0.1");
+    url.set_port(Some(app.port)).unwrap();
+    url
+}
+
+async fn count_events(app: &TestApp, event_type: &str) -> i64 {
+    sqlx::query!(
+        r#"SELECT COUNT(*) AS "count!" FROM email_events WHERE event_type = $1"#,
+        event_type
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count
+}
+
+#[tokio::test]
+async fn tracked_issues_route_links_through_the_redirector_and_carry_an_open_pixel() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let html = send_issue(&app, true).await;
+
+    // Assert
+    assert!(!html.contains(r#"href="https://example.com/docs""#));
+    assert!(html.contains("/t/c/"));
+    assert!(html.contains("/t/o/"));
+}
+
+#[tokio::test]
+async fn untracked_issues_are_sent_as_they_are() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let html = send_issue(&app, false).await;
+
+    // Assert
+    assert!(html.contains(r#"href="https://example.com/docs""#));
+    assert!(!html.contains("/t/c/"));
+    assert!(!html.contains("/t/o/"));
+}
+
+#[tokio::test]
+async fn clicks_are_recorded_and_redirected_to_the_original_link() {
+    // Arrange
+    let app = spawn_app().await;
+    let html = send_issue(&app, true).await;
+    let tracked_link = find_url(&app, &html, "/t/c/");
+
+    // Act
+    let response = app.api_client.get(tracked_link).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 302);
+    assert_eq!(response.headers()["Location"], "https://example.com/docs");
+
+    let click = sqlx::query!(
+        "SELECT newsletter_issue_id, subscriber_id, url FROM email_events WHERE event_type = 'click'"
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(click.newsletter_issue_id.is_some());
+    assert!(click.subscriber_id.is_some());
+    assert_eq!(click.url.as_deref(), Some("https://example.com/docs"));
+}
+
+#[tokio::test]
+async fn opens_are_recorded_by_the_pixel() {
+    // Arrange
+    let app = spawn_app().await;
+    let html = send_issue(&app, true).await;
+    let pixel = find_url(&app, &html, "/t/o/");
+
+    // Act
+    let response = app.api_client.get(pixel).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "image/gif");
+    assert_eq!(count_events(&app, "opened").await, 1);
+}
+
+#[tokio::test]
+async fn tampered_tokens_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    let html = send_issue(&app, true).await;
+    let tracked_link = find_url(&app, &html, "/t/c/");
+    let tampered_link = format!("{}0", tracked_link);
+
+    // Act
+    let response = app.api_client.get(&tampered_link).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 404);
+    assert_eq!(count_events(&app, "click").await, 0);
+}
+
+#[tokio::test]
+async fn the_issue_report_shows_opens_and_clicks() {
+    // Arrange
+    let app = spawn_app().await;
+    let html = send_issue(&app, true).await;
+    app.api_client
+        .get(find_url(&app, &html, "/t/o/"))
+        .send()
+        .await
+        .unwrap();
+    app.api_client
+        .get(find_url(&app, &html, "/t/c/"))
+        .send()
+        .await
+        .unwrap();
+    let issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    // Act
+    let html_page = app.get_issue_history_html(issue_id).await;
+
+    // Assert
+    assert!(html_page
+        .contains("Opened by 1 recipient(s) (100.0%), clicked by 1 recipient(s) (100.0%)."));
+    assert!(html_page.contains("<tr><td>https://example.com/docs</td><td>1</td><td>1</td></tr>"));
+}
+
+#[tokio::test]
+async fn the_public_archive_does_not_carry_tracking_tokens() {
+    // Arrange
+    let app = spawn_app().await;
+    send_issue(&app, true).await;
+    let slug = sqlx::query!("SELECT slug FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .slug;
+
+    // Act
+    let html_page = app.get_archived_issue(&slug).await.text().await.unwrap();
+
+    // Assert
+    assert!(html_page.contains("https://example.com/docs"));
+    assert!(!html_page.contains("/t/c/"));
+    assert!(!html_page.contains("/t/o/"));
+}